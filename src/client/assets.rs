@@ -0,0 +1,329 @@
+// Asset manager, maps logical asset IDs to files and tracks who is still using them
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc
+};
+use anyhow::{Error, Result};
+
+/// Folder the game's asset files live in
+pub const ASSET_DIR: &str = "assets";
+
+/// Default manifest listing every asset the game knows about
+pub const MANIFEST_PATH: &str = "assets/manifest.txt";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AssetKind {
+    Texture,
+    Atlas,
+    Sound,
+    Font,
+    Map,
+}
+
+impl AssetKind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "texture" => Some(AssetKind::Texture),
+            "atlas" => Some(AssetKind::Atlas),
+            "sound" => Some(AssetKind::Sound),
+            "font" => Some(AssetKind::Font),
+            "map" => Some(AssetKind::Map),
+            _ => None
+        }
+    }
+}
+
+/// Marker types so handles to different kinds of asset can't be mixed up
+pub trait Asset {
+    const KIND: AssetKind;
+}
+
+pub struct Texture;
+pub struct Atlas;
+pub struct Sound;
+pub struct Font;
+pub struct Map;
+
+impl Asset for Texture { const KIND: AssetKind = AssetKind::Texture; }
+impl Asset for Atlas { const KIND: AssetKind = AssetKind::Atlas; }
+impl Asset for Sound { const KIND: AssetKind = AssetKind::Sound; }
+impl Asset for Font { const KIND: AssetKind = AssetKind::Font; }
+impl Asset for Map { const KIND: AssetKind = AssetKind::Map; }
+
+/// A reference to a loaded asset, the asset stays loaded while any clone of its handle is alive
+pub struct Handle<T: Asset> {
+    id: Arc<str>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Asset> Handle<T> {
+    /// The logical ID, which is also the key the renderer stores the asset under
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl<T: Asset> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Asset> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{:?}>({})", T::KIND, self.id)
+    }
+}
+
+/// Why an asset couldn't be used
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetError {
+    /// The ID isn't listed in the manifest
+    NotInManifest { id: String },
+    /// The ID is listed as a different kind of asset
+    WrongKind { id: String, expected: AssetKind, found: AssetKind },
+    /// The file couldn't be read
+    Read { path: PathBuf, message: String },
+    /// The file was read but isn't a valid image
+    Decode { path: PathBuf, message: String },
+    /// The asset loaded but its contents are unusable, e.g. an atlas frame outside the image
+    Invalid { id: String, message: String },
+    /// Something tried to draw a texture that isn't loaded
+    Missing { id: String },
+}
+
+impl std::fmt::Display for AssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetError::NotInManifest { id } => write!(f, "Asset '{}' is not in the manifest", id),
+            AssetError::WrongKind { id, expected, found } => write!(f, "Asset '{}' is a {:?}, not a {:?}", id, found, expected),
+            AssetError::Read { path, message } => write!(f, "Failed to read '{}': {}", path.display(), message),
+            AssetError::Decode { path, message } => write!(f, "Failed to decode '{}': {}", path.display(), message),
+            AssetError::Invalid { id, message } => write!(f, "Asset '{}' is invalid: {}", id, message),
+            AssetError::Missing { id } => write!(f, "Texture '{}' is not loaded", id),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl AssetError {
+    /// Turn any error from loading an asset into an asset error, keeping it as is if it already is one
+    pub fn from_error(id: &str, err: &Error) -> Self {
+        match err.downcast_ref::<AssetError>() {
+            Some(asset_error) => asset_error.clone(),
+            None => AssetError::Invalid { id: String::from(id), message: err.to_string() }
+        }
+    }
+}
+
+/// Where an asset lives on disk, from the manifest
+#[derive(Clone, Debug)]
+pub struct AssetEntry {
+    pub kind: AssetKind,
+    pub path: PathBuf,
+    /// Anything after the path, e.g. an atlas layout
+    pub options: Option<String>,
+}
+
+#[derive(Default)]
+pub struct AssetManager {
+    manifest: HashMap<String, AssetEntry>,
+    // The manager keeps one clone of every live handle, so a strong count of 1 means nobody else uses it
+    live: HashMap<String, (AssetKind, Arc<str>)>,
+    // Every distinct asset failure so far, oldest first
+    errors: Vec<AssetError>,
+}
+
+impl AssetManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a manifest where each line is `<kind> <id> <path> [options]`, blank lines and `#` comments are skipped
+    pub fn load_manifest(&mut self, path: &Path) -> Result<usize, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read manifest '{}': {}", path.display(), err))?;
+        let mut count = 0;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (kind, id, asset_path) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(kind), Some(id), Some(asset_path)) => (kind, id, asset_path),
+                _ => return Err(anyhow::anyhow!("{}:{}: expected '<kind> <id> <path>'", path.display(), line_number + 1))
+            };
+
+            let kind = AssetKind::parse(kind)
+                .ok_or_else(|| anyhow::anyhow!("{}:{}: unknown asset kind '{}'", path.display(), line_number + 1, kind))?;
+            let options: Vec<&str> = parts.collect();
+
+            self.manifest.insert(String::from(id), AssetEntry {
+                kind,
+                path: PathBuf::from(asset_path),
+                options: (!options.is_empty()).then(|| options.join(" ")),
+            });
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Add or replace a single manifest entry
+    pub fn register(&mut self, kind: AssetKind, id: &str, path: &Path, options: Option<&str>) {
+        self.manifest.insert(String::from(id), AssetEntry {
+            kind,
+            path: path.to_path_buf(),
+            options: options.map(String::from),
+        });
+    }
+
+    /// Look up where an asset lives
+    pub fn resolve(&self, id: &str) -> Option<&AssetEntry> {
+        self.manifest.get(id)
+    }
+
+    /// Get a handle to an asset, the bool is true if the asset isn't loaded yet and the caller needs to load it
+    pub fn acquire<T: Asset>(&mut self, id: &str) -> Result<(Handle<T>, bool), Error> {
+        let entry = self.manifest.get(id)
+            .ok_or_else(|| AssetError::NotInManifest { id: String::from(id) })?;
+
+        if entry.kind != T::KIND {
+            return Err(AssetError::WrongKind { id: String::from(id), expected: T::KIND, found: entry.kind }.into());
+        }
+
+        // Already loaded, share it instead of loading it again
+        if let Some((_kind, live_id)) = self.live.get(id) {
+            return Ok((Handle { id: live_id.clone(), _marker: PhantomData }, false));
+        }
+
+        let live_id: Arc<str> = Arc::from(id);
+        self.live.insert(String::from(id), (T::KIND, live_id.clone()));
+        Ok((Handle { id: live_id, _marker: PhantomData }, true))
+    }
+
+    /// Forget an asset that failed to load so the next acquire tries again
+    pub fn forget(&mut self, id: &str) {
+        self.live.remove(id);
+    }
+
+    /// Number of handles to an asset that are still alive
+    pub fn ref_count(&self, id: &str) -> usize {
+        self.live.get(id).map(|(_kind, live_id)| Arc::strong_count(live_id) - 1).unwrap_or(0)
+    }
+
+    pub fn is_loaded(&self, id: &str) -> bool {
+        self.live.contains_key(id)
+    }
+
+    /// Drop every asset nobody holds a handle to, unless `in_use` says it is still needed, and return them so their resources can be freed
+    pub fn collect_unused(&mut self, in_use: impl Fn(&str) -> bool) -> Vec<(String, AssetKind)> {
+        let unused: Vec<(String, AssetKind)> = self.live.iter()
+            .filter(|(id, (_kind, live_id))| Arc::strong_count(live_id) == 1 && !in_use(id))
+            .map(|(id, (kind, _live_id))| (id.clone(), *kind))
+            .collect();
+
+        for (id, _kind) in &unused {
+            self.live.remove(id);
+        }
+
+        unused
+    }
+
+    /// Log an asset failure, the same failure is only logged (and printed) once so a broken asset drawn every frame doesn't flood the log
+    pub fn report(&mut self, error: AssetError) {
+        if self.errors.contains(&error) {
+            return;
+        }
+        println!("Asset error: {}", error);
+        self.errors.push(error);
+    }
+
+    /// Every distinct asset failure so far, oldest first
+    pub fn errors(&self) -> &[AssetError] {
+        &self.errors
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager knowing about one texture and one sound
+    fn manager() -> AssetManager {
+        let mut assets = AssetManager::new();
+        assets.register(AssetKind::Texture, "grass", Path::new("assets/grass.png"), None);
+        assets.register(AssetKind::Sound, "step", Path::new("assets/step.ogg"), None);
+        assets
+    }
+
+    #[test]
+    fn acquire_only_asks_for_the_first_load() {
+        let mut assets = manager();
+        let (first, needs_load) = assets.acquire::<Texture>("grass").unwrap();
+        assert!(needs_load);
+        let (second, needs_load) = assets.acquire::<Texture>("grass").unwrap();
+        assert!(!needs_load);
+        assert_eq!(first.id(), second.id());
+        assert!(assets.is_loaded("grass"));
+    }
+
+    #[test]
+    fn acquire_rejects_unknown_ids_and_other_kinds() {
+        let mut assets = manager();
+        let err = assets.acquire::<Texture>("stone").unwrap_err();
+        assert_eq!(err.downcast_ref::<AssetError>(), Some(&AssetError::NotInManifest { id: String::from("stone") }));
+
+        let err = assets.acquire::<Texture>("step").unwrap_err();
+        let expected = AssetError::WrongKind { id: String::from("step"), expected: AssetKind::Texture, found: AssetKind::Sound };
+        assert_eq!(err.downcast_ref::<AssetError>(), Some(&expected));
+        assert!(!assets.is_loaded("step"));
+    }
+
+    #[test]
+    fn ref_count_follows_handles() {
+        let mut assets = manager();
+        assert_eq!(assets.ref_count("grass"), 0);
+
+        let (handle, _needs_load) = assets.acquire::<Texture>("grass").unwrap();
+        let clone = handle.clone();
+        assert_eq!(assets.ref_count("grass"), 2);
+        drop(handle);
+        assert_eq!(assets.ref_count("grass"), 1);
+        drop(clone);
+        assert_eq!(assets.ref_count("grass"), 0);
+        // Still loaded until it is collected
+        assert!(assets.is_loaded("grass"));
+    }
+
+    #[test]
+    fn collect_unused_keeps_held_and_in_use_assets() {
+        let mut assets = manager();
+        let (handle, _needs_load) = assets.acquire::<Texture>("grass").unwrap();
+        let (sound, _needs_load) = assets.acquire::<Sound>("step").unwrap();
+        drop(sound);
+
+        // Nobody holds the sound but something still uses it, so it stays
+        assert!(assets.collect_unused(|id| id == "step").is_empty());
+        assert_eq!(assets.collect_unused(|_id| false), [(String::from("step"), AssetKind::Sound)]);
+        assert!(!assets.is_loaded("step"));
+
+        drop(handle);
+        assert_eq!(assets.collect_unused(|_id| false), [(String::from("grass"), AssetKind::Texture)]);
+        // Collected assets need loading again
+        assert!(assets.acquire::<Texture>("grass").unwrap().1);
+    }
+}
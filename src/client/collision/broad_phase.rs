@@ -0,0 +1,145 @@
+// This file contains the broad phase, a uniform grid that narrows every pair of shapes down to the ones close enough to test
+use std::collections::{HashMap, HashSet};
+
+/// Buckets boxes by the grid cells they cover, so only boxes sharing a cell are paired up
+/// Ids are whatever the caller indexes its shapes by, e.g. a position in a list
+#[derive(Clone, Debug, Default)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<[i32; 2], Vec<usize>>,
+    // Boxes as (min, max) corners by id, for queries
+    boxes: HashMap<usize, ([f32; 2], [f32; 2])>,
+}
+
+impl SpatialGrid {
+    /// An empty grid, cells work best a little bigger than most shapes
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(1.0),
+            cells: HashMap::new(),
+            boxes: HashMap::new(),
+        }
+    }
+
+    /// Remove every box, keeping the memory for the next step
+    pub fn clear(&mut self) {
+        for ids in self.cells.values_mut() {
+            ids.clear();
+        }
+        self.boxes.clear();
+    }
+
+    /// Add a box by its (min, max) corners
+    pub fn insert(&mut self, id: usize, min: [f32; 2], max: [f32; 2]) {
+        let (first, last) = self.cell_range(min, max);
+        for y in first[1]..=last[1] {
+            for x in first[0]..=last[0] {
+                self.cells.entry([x, y]).or_default().push(id);
+            }
+        }
+        self.boxes.insert(id, (min, max));
+    }
+
+    /// Every pair of boxes that overlap, each once with the lower id first, in order
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs: HashSet<(usize, usize)> = HashSet::new();
+        for ids in self.cells.values() {
+            for (i, a) in ids.iter().enumerate() {
+                for b in &ids[i + 1..] {
+                    let pair: (usize, usize) = (*a.min(b), *a.max(b));
+                    if pair.0 != pair.1 && self.overlap(pair.0, pair.1) {
+                        pairs.insert(pair);
+                    }
+                }
+            }
+        }
+
+        let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Every box overlapping an area, in order
+    pub fn query(&self, min: [f32; 2], max: [f32; 2]) -> Vec<usize> {
+        let (first, last) = self.cell_range(min, max);
+        let mut found: HashSet<usize> = HashSet::new();
+        for y in first[1]..=last[1] {
+            for x in first[0]..=last[0] {
+                let ids = self.cells.get(&[x, y]).into_iter().flatten();
+                found.extend(ids.filter(|id| self.boxes.get(id).is_some_and(|(box_min, box_max)| overlap(min, max, *box_min, *box_max))));
+            }
+        }
+
+        let mut found: Vec<usize> = found.into_iter().collect();
+        found.sort_unstable();
+        found
+    }
+
+    fn overlap(&self, a: usize, b: usize) -> bool {
+        match (self.boxes.get(&a), self.boxes.get(&b)) {
+            (Some((a_min, a_max)), Some((b_min, b_max))) => overlap(*a_min, *a_max, *b_min, *b_max),
+            _ => false
+        }
+    }
+
+    // First and last cells a box covers
+    fn cell_range(&self, min: [f32; 2], max: [f32; 2]) -> ([i32; 2], [i32; 2]) {
+        let cell = |point: [f32; 2]| [(point[0] / self.cell_size).floor() as i32, (point[1] / self.cell_size).floor() as i32];
+        (cell(min), cell(max))
+    }
+}
+
+// Boxes that only touch don't overlap
+fn overlap(a_min: [f32; 2], a_max: [f32; 2], b_min: [f32; 2], b_max: [f32; 2]) -> bool {
+    a_min[0] < b_max[0] && b_min[0] < a_max[0] && a_min[1] < b_max[1] && b_min[1] < a_max[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> SpatialGrid {
+        let mut grid = SpatialGrid::new(16.0);
+        grid.insert(0, [0.0, 0.0], [40.0, 40.0]);
+        grid.insert(1, [30.0, 30.0], [50.0, 50.0]);
+        grid.insert(2, [100.0, 100.0], [110.0, 110.0]);
+        grid.insert(3, [-20.0, -20.0], [5.0, 5.0]);
+        grid
+    }
+
+    #[test]
+    fn pairs_overlapping_boxes_once() {
+        // 0 and 1 share several cells but are paired once
+        assert_eq!(grid().pairs(), vec![(0, 1), (0, 3)]);
+    }
+
+    #[test]
+    fn sharing_a_cell_is_not_overlapping() {
+        let mut grid = SpatialGrid::new(100.0);
+        grid.insert(0, [0.0, 0.0], [10.0, 10.0]);
+        grid.insert(1, [20.0, 20.0], [30.0, 30.0]);
+        // Boxes that only touch don't overlap either
+        grid.insert(2, [10.0, 0.0], [15.0, 10.0]);
+        assert!(grid.pairs().is_empty());
+    }
+
+    #[test]
+    fn queries_an_area() {
+        let grid = grid();
+        assert_eq!(grid.query([95.0, 95.0], [101.0, 101.0]), vec![2]);
+        assert_eq!(grid.query([-5.0, -5.0], [35.0, 35.0]), vec![0, 1, 3]);
+        assert!(grid.query([60.0, 0.0], [90.0, 20.0]).is_empty());
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let mut grid = grid();
+        grid.clear();
+        assert!(grid.pairs().is_empty());
+        assert!(grid.query([-100.0, -100.0], [200.0, 200.0]).is_empty());
+
+        grid.insert(5, [0.0, 0.0], [1.0, 1.0]);
+        grid.insert(6, [0.5, 0.5], [2.0, 2.0]);
+        assert_eq!(grid.pairs(), vec![(5, 6)]);
+    }
+}
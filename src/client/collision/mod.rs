@@ -0,0 +1,6 @@
+mod shape;
+mod broad_phase;
+mod trigger;
+pub use self::shape::{contact, Contact, Shape};
+pub use self::broad_phase::SpatialGrid;
+pub use self::trigger::{TriggerEvent, TriggerKind, TriggerTracker};
\ No newline at end of file
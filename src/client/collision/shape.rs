@@ -0,0 +1,332 @@
+// This file contains collision shapes and the narrow phase, overlaps are found with the separating axis test
+
+/// A collision shape around a point, in world pixels with y pointing down, shapes don't rotate
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// Axis aligned box
+    Aabb { half_size: [f32; 2] },
+    Circle { radius: f32 },
+    /// Convex polygon, points relative to the shape's position in either winding order
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+/// How two overlapping shapes touch
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Contact {
+    /// Unit direction from the first shape towards the second
+    pub normal: [f32; 2],
+    /// How far the shapes overlap along the normal, moving the second shape this far along it separates them
+    pub depth: f32,
+}
+
+impl Contact {
+    /// How far to move each shape to pull them apart, split evenly when both can move
+    pub fn separation(&self, a_moves: bool, b_moves: bool) -> ([f32; 2], [f32; 2]) {
+        let (a_share, b_share): (f32, f32) = match (a_moves, b_moves) {
+            (true, true) => (0.5, 0.5),
+            (true, false) => (1.0, 0.0),
+            (false, true) => (0.0, 1.0),
+            (false, false) => (0.0, 0.0),
+        };
+        (
+            [-self.normal[0] * self.depth * a_share, -self.normal[1] * self.depth * a_share],
+            [self.normal[0] * self.depth * b_share, self.normal[1] * self.depth * b_share],
+        )
+    }
+}
+
+impl Shape {
+    /// A box `size` pixels across, centered on the position
+    pub fn aabb(size: [f32; 2]) -> Self {
+        Shape::Aabb { half_size: [size[0] / 2.0, size[1] / 2.0] }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Shape::Circle { radius }
+    }
+
+    /// A polygon from at least three points, None if the points don't make a convex polygon
+    pub fn polygon(points: Vec<[f32; 2]>) -> Option<Self> {
+        is_convex(&points).then_some(Shape::Polygon { points })
+    }
+
+    /// Get the (min, max) corners of the box around the shape at a position
+    pub fn bounds(&self, position: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        match self {
+            Shape::Aabb { half_size } => (
+                [position[0] - half_size[0], position[1] - half_size[1]],
+                [position[0] + half_size[0], position[1] + half_size[1]],
+            ),
+            Shape::Circle { radius } => (
+                [position[0] - radius, position[1] - radius],
+                [position[0] + radius, position[1] + radius],
+            ),
+            Shape::Polygon { points } => {
+                let mut min: [f32; 2] = [f32::INFINITY, f32::INFINITY];
+                let mut max: [f32; 2] = [f32::NEG_INFINITY, f32::NEG_INFINITY];
+                for point in points {
+                    for axis in 0..2 {
+                        min[axis] = min[axis].min(position[axis] + point[axis]);
+                        max[axis] = max[axis].max(position[axis] + point[axis]);
+                    }
+                }
+                (min, max)
+            }
+        }
+    }
+
+    /// Size of the box around the shape
+    pub fn size(&self) -> [f32; 2] {
+        let (min, max) = self.bounds([0.0, 0.0]);
+        [max[0] - min[0], max[1] - min[1]]
+    }
+
+    /// True if a point in world space is inside the shape at a position
+    pub fn contains(&self, position: [f32; 2], point: [f32; 2]) -> bool {
+        let local: [f32; 2] = [point[0] - position[0], point[1] - position[1]];
+        match self {
+            Shape::Aabb { half_size } => local[0].abs() < half_size[0] && local[1].abs() < half_size[1],
+            Shape::Circle { radius } => dot(local, local) < radius * radius,
+            // Inside if the point is on the same side of every edge
+            Shape::Polygon { points } => {
+                let sides: Vec<f32> = points.iter().zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| (b[0] - a[0]) * (local[1] - a[1]) - (b[1] - a[1]) * (local[0] - a[0]))
+                    .collect();
+                sides.iter().all(|side| *side > 0.0) || sides.iter().all(|side| *side < 0.0)
+            }
+        }
+    }
+}
+
+/// Find how two shapes overlap, None if they don't, shapes that only touch don't overlap
+pub fn contact(a: &Shape, a_position: [f32; 2], b: &Shape, b_position: [f32; 2]) -> Option<Contact> {
+    let a: Convex = Convex::new(a, a_position);
+    let b: Convex = Convex::new(b, b_position);
+
+    // The shapes are apart if any of these axes has a gap between them
+    let mut axes: Vec<[f32; 2]> = Vec::new();
+    a.add_axes(&b, &mut axes);
+    b.add_axes(&a, &mut axes);
+
+    let mut best: Option<Contact> = None;
+    for axis in axes {
+        let (a_min, a_max) = a.project(axis);
+        let (b_min, b_max) = b.project(axis);
+        let depth: f32 = a_max.min(b_max) - a_min.max(b_min);
+        if depth <= 0.0 {
+            return None;
+        }
+        if best.is_none_or(|best| depth < best.depth) {
+            best = Some(Contact { normal: axis, depth });
+        }
+    }
+
+    // Point the normal from a to b
+    let mut contact: Contact = best?;
+    let between: [f32; 2] = [b.center[0] - a.center[0], b.center[1] - a.center[1]];
+    if dot(between, contact.normal) < 0.0 {
+        contact.normal = [-contact.normal[0], -contact.normal[1]];
+    }
+    Some(contact)
+}
+
+// A shape placed in the world, boxes become polygons so every pair is handled the same way
+struct Convex {
+    points: Vec<[f32; 2]>,
+    // Circles are a center and radius, polygons have a radius of 0
+    radius: f32,
+    center: [f32; 2],
+}
+
+impl Convex {
+    fn new(shape: &Shape, position: [f32; 2]) -> Self {
+        match shape {
+            Shape::Aabb { .. } => {
+                let (min, max) = shape.bounds(position);
+                Self { points: vec![min, [max[0], min[1]], max, [min[0], max[1]]], radius: 0.0, center: position }
+            }
+            Shape::Circle { radius } => Self { points: Vec::new(), radius: *radius, center: position },
+            Shape::Polygon { points } => {
+                let points: Vec<[f32; 2]> = points.iter().map(|point| [position[0] + point[0], position[1] + point[1]]).collect();
+                let count: f32 = points.len().max(1) as f32;
+                let center: [f32; 2] = [
+                    points.iter().map(|point| point[0]).sum::<f32>() / count,
+                    points.iter().map(|point| point[1]).sum::<f32>() / count,
+                ];
+                Self { points, radius: 0.0, center }
+            }
+        }
+    }
+
+    // Add the axes this shape could be separated from another along
+    fn add_axes(&self, other: &Convex, axes: &mut Vec<[f32; 2]>) {
+        if self.points.is_empty() {
+            // A circle separates along the line to the other shape's closest point
+            let closest: [f32; 2] = other.points.iter()
+                .min_by(|a, b| distance_squared(**a, self.center).total_cmp(&distance_squared(**b, self.center)))
+                .copied()
+                .unwrap_or(other.center);
+            axes.push(normalize([closest[0] - self.center[0], closest[1] - self.center[1]]));
+            return;
+        }
+
+        for (a, b) in self.points.iter().zip(self.points.iter().cycle().skip(1)) {
+            axes.push(normalize([b[1] - a[1], a[0] - b[0]]));
+        }
+    }
+
+    // Get the range the shape covers along an axis
+    fn project(&self, axis: [f32; 2]) -> (f32, f32) {
+        if self.points.is_empty() {
+            let center: f32 = dot(self.center, axis);
+            return (center - self.radius, center + self.radius);
+        }
+
+        self.points.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), point| {
+            let projected: f32 = dot(*point, axis);
+            (min.min(projected), max.max(projected))
+        })
+    }
+}
+
+// True if the points make a convex polygon, every turn along it goes the same way
+fn is_convex(points: &[[f32; 2]]) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+
+    let mut sign: f32 = 0.0;
+    for index in 0..points.len() {
+        let a: [f32; 2] = points[index];
+        let b: [f32; 2] = points[(index + 1) % points.len()];
+        let c: [f32; 2] = points[(index + 2) % points.len()];
+        let cross: f32 = (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0]);
+        if cross.abs() <= f32::EPSILON {
+            continue;
+        }
+        if sign != 0.0 && cross.signum() != sign {
+            return false;
+        }
+        sign = cross.signum();
+    }
+    sign != 0.0
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn distance_squared(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
+
+// Scale to a length of 1, a zero vector points along x so circles on the same spot still separate
+fn normalize(vector: [f32; 2]) -> [f32; 2] {
+    let length: f32 = dot(vector, vector).sqrt();
+    if length <= f32::EPSILON {
+        return [1.0, 0.0];
+    }
+    [vector[0] / length, vector[1] / length]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    /// A triangle pointing up, 10 wide and 10 tall around its position
+    fn triangle() -> Shape {
+        Shape::polygon(vec![[0.0, -5.0], [5.0, 5.0], [-5.0, 5.0]]).unwrap()
+    }
+
+    #[test]
+    fn boxes_overlap_along_the_shallowest_axis() {
+        let square = Shape::aabb([10.0, 10.0]);
+        let contact = contact(&square, [0.0, 0.0], &square, [8.0, 1.0]).unwrap();
+        assert_eq!(contact.normal, [1.0, 0.0]);
+        assert!(close(contact.depth, 2.0));
+
+        // The normal always points from the first shape to the second
+        let contact = super::contact(&square, [8.0, 1.0], &square, [0.0, 0.0]).unwrap();
+        assert_eq!(contact.normal, [-1.0, 0.0]);
+    }
+
+    #[test]
+    fn touching_is_not_overlapping() {
+        let square = Shape::aabb([10.0, 10.0]);
+        assert_eq!(contact(&square, [0.0, 0.0], &square, [10.0, 0.0]), None);
+        assert_eq!(contact(&Shape::circle(5.0), [0.0, 0.0], &Shape::circle(5.0), [0.0, 10.0]), None);
+    }
+
+    #[test]
+    fn circles() {
+        let circle = Shape::circle(5.0);
+        let contact = contact(&circle, [0.0, 0.0], &circle, [0.0, 8.0]).unwrap();
+        assert!(close(contact.normal[1], 1.0));
+        assert!(close(contact.depth, 2.0));
+
+        // Circles on the same spot still separate
+        let contact = super::contact(&circle, [3.0, 3.0], &circle, [3.0, 3.0]).unwrap();
+        assert!(close(contact.depth, 10.0));
+    }
+
+    #[test]
+    fn circle_against_box() {
+        let square = Shape::aabb([10.0, 10.0]);
+        let circle = Shape::circle(5.0);
+
+        let contact = contact(&square, [0.0, 0.0], &circle, [-9.0, 0.0]).unwrap();
+        assert!(close(contact.normal[0], -1.0));
+        assert!(close(contact.depth, 1.0));
+
+        // Near a corner the boxes around them overlap but the shapes don't
+        assert_eq!(super::contact(&square, [0.0, 0.0], &circle, [9.0, 9.0]), None);
+        assert!(super::contact(&square, [0.0, 0.0], &circle, [7.0, 7.0]).is_some());
+    }
+
+    #[test]
+    fn polygons() {
+        let square = Shape::aabb([10.0, 10.0]);
+        assert!(contact(&triangle(), [0.0, 0.0], &square, [0.0, 8.0]).is_some());
+        assert_eq!(contact(&triangle(), [0.0, 0.0], &square, [0.0, 12.0]), None);
+
+        // Beside the slanted edge, inside the box around the triangle but outside the triangle
+        assert_eq!(contact(&triangle(), [0.0, 0.0], &Shape::circle(1.0), [4.0, -3.0]), None);
+        assert!(contact(&triangle(), [0.0, 0.0], &Shape::circle(1.0), [1.0, 0.0]).is_some());
+    }
+
+    #[test]
+    fn only_convex_polygons() {
+        assert!(Shape::polygon(vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]]).is_some());
+        // Either winding order works
+        assert!(Shape::polygon(vec![[0.0, 0.0], [0.0, 10.0], [10.0, 10.0], [10.0, 0.0]]).is_some());
+        // An arrowhead dents inwards
+        assert!(Shape::polygon(vec![[0.0, 0.0], [10.0, 5.0], [0.0, 10.0], [3.0, 5.0]]).is_none());
+        // Too few points or all in a line
+        assert!(Shape::polygon(vec![[0.0, 0.0], [10.0, 0.0]]).is_none());
+        assert!(Shape::polygon(vec![[0.0, 0.0], [5.0, 0.0], [10.0, 0.0]]).is_none());
+    }
+
+    #[test]
+    fn bounds_and_contains() {
+        assert_eq!(triangle().bounds([10.0, 10.0]), ([5.0, 5.0], [15.0, 15.0]));
+        assert_eq!(Shape::circle(3.0).size(), [6.0, 6.0]);
+
+        assert!(triangle().contains([10.0, 10.0], [10.0, 12.0]));
+        assert!(!triangle().contains([10.0, 10.0], [14.0, 6.0]));
+        assert!(Shape::aabb([4.0, 4.0]).contains([0.0, 0.0], [1.9, -1.9]));
+        assert!(!Shape::circle(2.0).contains([0.0, 0.0], [1.5, 1.5]));
+    }
+
+    #[test]
+    fn separation_is_shared_by_what_moves() {
+        let contact = Contact { normal: [1.0, 0.0], depth: 4.0 };
+        assert_eq!(contact.separation(true, true), ([-2.0, 0.0], [2.0, 0.0]));
+        assert_eq!(contact.separation(true, false), ([-4.0, 0.0], [0.0, 0.0]));
+        assert_eq!(contact.separation(false, true), ([0.0, 0.0], [4.0, 0.0]));
+        assert_eq!(contact.separation(false, false), ([0.0, 0.0], [0.0, 0.0]));
+    }
+}
@@ -0,0 +1,105 @@
+// This file contains trigger tracking, it turns the overlaps of each step into enter and exit events
+use std::collections::BTreeSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerKind {
+    Enter,
+    Exit,
+}
+
+/// Something entering or leaving a trigger volume, e.g. the player walking through a door
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TriggerEvent<T> {
+    pub kind: TriggerKind,
+    pub trigger: T,
+    /// What entered or left the trigger
+    pub other: T,
+}
+
+/// Remembers which things are inside which triggers between steps
+#[derive(Clone, Debug)]
+pub struct TriggerTracker<T> {
+    inside: BTreeSet<(T, T)>,
+}
+
+impl<T> Default for TriggerTracker<T> {
+    fn default() -> Self {
+        Self { inside: BTreeSet::new() }
+    }
+}
+
+impl<T: Copy + Ord> TriggerTracker<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give this step's (trigger, other) overlaps, returns what entered since the last step then what left
+    /// Things that stop existing simply stop overlapping, so they get an exit event
+    pub fn update(&mut self, overlaps: impl IntoIterator<Item = (T, T)>) -> Vec<TriggerEvent<T>> {
+        let current: BTreeSet<(T, T)> = overlaps.into_iter().collect();
+
+        let entered = current.difference(&self.inside)
+            .map(|(trigger, other)| TriggerEvent { kind: TriggerKind::Enter, trigger: *trigger, other: *other });
+        let exited = self.inside.difference(&current)
+            .map(|(trigger, other)| TriggerEvent { kind: TriggerKind::Exit, trigger: *trigger, other: *other });
+        let events: Vec<TriggerEvent<T>> = entered.chain(exited).collect();
+
+        self.inside = current;
+        events
+    }
+
+    /// True if something is inside a trigger as of the last update
+    pub fn is_inside(&self, trigger: T, other: T) -> bool {
+        self.inside.contains(&(trigger, other))
+    }
+
+    /// Forget everything, no exit events are sent, e.g. when the level changes
+    pub fn clear(&mut self) {
+        self.inside.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: TriggerKind, trigger: u32, other: u32) -> TriggerEvent<u32> {
+        TriggerEvent { kind, trigger, other }
+    }
+
+    #[test]
+    fn enters_once_then_exits() {
+        let mut tracker = TriggerTracker::new();
+        assert_eq!(tracker.update([(1, 10)]), vec![event(TriggerKind::Enter, 1, 10)]);
+        // Staying inside sends nothing
+        assert!(tracker.update([(1, 10)]).is_empty());
+        assert!(tracker.is_inside(1, 10));
+        assert_eq!(tracker.update([]), vec![event(TriggerKind::Exit, 1, 10)]);
+        assert!(!tracker.is_inside(1, 10));
+    }
+
+    #[test]
+    fn enters_come_before_exits() {
+        let mut tracker = TriggerTracker::new();
+        tracker.update([(1, 10), (2, 10)]);
+        assert_eq!(tracker.update([(2, 10), (3, 10)]), vec![
+            event(TriggerKind::Enter, 3, 10),
+            event(TriggerKind::Exit, 1, 10),
+        ]);
+    }
+
+    #[test]
+    fn duplicate_overlaps_count_once() {
+        let mut tracker = TriggerTracker::new();
+        assert_eq!(tracker.update([(1, 10), (1, 10)]).len(), 1);
+    }
+
+    #[test]
+    fn clear_forgets_without_exits() {
+        let mut tracker = TriggerTracker::new();
+        tracker.update([(1, 10)]);
+        tracker.clear();
+        assert!(tracker.update([]).is_empty());
+        assert_eq!(tracker.update([(1, 10)]), vec![event(TriggerKind::Enter, 1, 10)]);
+    }
+}
@@ -1,4 +1,15 @@
 // Game config stuff
 pub const WINDOW_WIDTH: f64 = 1280.0;
 pub const WINDOW_HEIGHT: f64 = 720.0;
-pub const FRAMERATE: f64 = 30.0;
\ No newline at end of file
+pub const FRAMERATE: f64 = 30.0;
+pub const MAX_RECORDING_SECONDS: f64 = 10.0;
+// Reload changed assets and shaders while the game runs, on for debug builds
+pub const HOT_RELOAD: bool = cfg!(debug_assertions);
+// Length of one game tick in seconds, game logic and the world clock advance once per tick
+pub const TICK_SECONDS: f64 = 0.6;
+// Physics steps per second, motion and animation advance once per step
+pub const PHYSICS_RATE: f64 = 60.0;
+// Most ticks or physics steps run in one frame after a stall, time beyond that is skipped
+pub const MAX_CATCH_UP_STEPS: u32 = 8;
+// Size of the broad phase grid cells in pixels, a little bigger than most colliders
+pub const COLLISION_CELL_SIZE: f32 = 128.0;
\ No newline at end of file
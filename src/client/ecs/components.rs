@@ -0,0 +1,238 @@
+// This file contains the components entities are built from, they are plain data and the systems give them behaviour
+use std::collections::VecDeque;
+use crate::client::collision::Shape;
+use crate::client::grafx::{BlendMode, Layer};
+
+/// Where an entity is in the world, in pixels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub position: [f32; 2],
+    /// Position at the start of the current physics step, drawing blends from here to `position`
+    pub previous_position: [f32; 2],
+    pub scale: [f32; 2],
+    /// Rotation in radians
+    pub rotation: f32,
+}
+
+impl Transform {
+    pub fn new(position: [f32; 2]) -> Self {
+        Self {
+            position,
+            previous_position: position,
+            scale: [1.0, 1.0],
+            rotation: 0.0,
+        }
+    }
+
+    /// Jump to a position without blending from the old one, e.g. for teleports
+    pub fn snap(&mut self, position: [f32; 2]) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    /// Get the position to draw, `alpha` is how far real time is into the next physics step
+    pub fn interpolated(&self, alpha: f32) -> [f32; 2] {
+        [
+            self.previous_position[0] + (self.position[0] - self.previous_position[0]) * alpha,
+            self.previous_position[1] + (self.position[1] - self.previous_position[1]) * alpha,
+        ]
+    }
+}
+
+/// What an entity looks like, drawn at its transform
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteRef {
+    pub texture_key: String,
+    /// Frame of the atlas the texture is from, None draws the whole texture
+    pub frame: Option<String>,
+    /// Area of the texture to show, as x, y, width and height in 0..1, a frame overrides it
+    pub uv_rect: [f32; 4],
+    /// Size in pixels before the transform's scale
+    pub size: [f32; 2],
+    pub layer: Layer,
+    pub z: i32,
+    pub color: [f32; 4],
+    pub blend_mode: BlendMode,
+    pub visible: bool,
+}
+
+impl SpriteRef {
+    pub fn new(texture_key: &str, size: [f32; 2]) -> Self {
+        Self {
+            texture_key: String::from(texture_key),
+            frame: None,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            size,
+            layer: Layer::Character,
+            z: 0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            blend_mode: BlendMode::Alpha,
+            visible: true,
+        }
+    }
+
+    /// Show a single frame of an atlas
+    pub fn with_frame(mut self, frame: &str) -> Self {
+        self.frame = Some(String::from(frame));
+        self
+    }
+}
+
+/// How fast an entity moves, in pixels per second
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Velocity {
+    pub linear: [f32; 2],
+    /// Radians per second
+    pub angular: f32,
+}
+
+impl Velocity {
+    pub fn new(linear: [f32; 2]) -> Self {
+        Self { linear, angular: 0.0 }
+    }
+}
+
+/// A shape around an entity's position that it collides with
+/// Solid colliders push entities that move out of each other, triggers only report what enters and leaves them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider {
+    pub shape: Shape,
+    /// Offset of the shape from the entity's position
+    pub offset: [f32; 2],
+    pub trigger: bool,
+}
+
+impl Collider {
+    /// A solid box centered on the entity
+    pub fn new(size: [f32; 2]) -> Self {
+        Self::from_shape(Shape::aabb(size))
+    }
+
+    pub fn from_shape(shape: Shape) -> Self {
+        Self { shape, offset: [0.0, 0.0], trigger: false }
+    }
+
+    /// Make the collider a trigger volume, e.g. for doors, pickups and quest zones
+    pub fn as_trigger(mut self) -> Self {
+        self.trigger = true;
+        self
+    }
+
+    /// Where the shape is for an entity at `position`
+    pub fn position(&self, position: [f32; 2]) -> [f32; 2] {
+        [position[0] + self.offset[0], position[1] + self.offset[1]]
+    }
+
+    /// Get the (min, max) corners of the box around the shape for an entity at `position`
+    pub fn bounds(&self, position: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        self.shape.bounds(self.position(position))
+    }
+}
+
+/// A name and class for finding entities from game code, e.g. the NPCs and doors placed in a map
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub class: String,
+}
+
+impl Tag {
+    pub fn new(name: &str, class: &str) -> Self {
+        Self { name: String::from(name), class: String::from(class) }
+    }
+}
+
+/// Walks a path of map tiles, one tile per game tick, e.g. after the player clicks somewhere
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathFollower {
+    /// Tiles left to walk, the next one first
+    pub path: VecDeque<[u32; 2]>,
+    /// Tile the entity is on, or stepping onto
+    pub tile: [u32; 2],
+    // Where the current step ends, and how fast to walk so it ends with the tick
+    target: Option<[f32; 2]>,
+    speed: f32,
+}
+
+impl PathFollower {
+    pub fn new(tile: [u32; 2]) -> Self {
+        Self { tile, ..Default::default() }
+    }
+
+    /// Start walking a new path, from the tile the entity is on or stepping onto
+    pub fn walk(&mut self, path: Vec<[u32; 2]>) {
+        self.path = VecDeque::from(path);
+    }
+
+    /// True while there are tiles left to walk or a step to finish
+    pub fn is_moving(&self) -> bool {
+        !self.path.is_empty() || self.target.is_some()
+    }
+
+    /// Start the step to the next tile of the path, `tile_center` gives where a tile is in the world
+    pub fn next_step(&mut self, position: [f32; 2], tick_seconds: f32, tile_center: impl Fn([u32; 2]) -> [f32; 2]) {
+        match self.path.pop_front() {
+            Some(tile) => {
+                let target: [f32; 2] = tile_center(tile);
+                self.speed = distance(position, target) / tick_seconds.max(f32::EPSILON);
+                self.target = Some(target);
+                self.tile = tile;
+            }
+            None => self.target = None
+        }
+    }
+
+    /// Walk towards the end of the current step
+    pub fn advance(&mut self, position: &mut [f32; 2], dt: f32) {
+        let target: [f32; 2] = match self.target {
+            Some(target) => target,
+            None => return
+        };
+
+        let left: f32 = distance(*position, target);
+        let step: f32 = self.speed * dt;
+        if step >= left {
+            *position = target;
+        } else {
+            position[0] += (target[0] - position[0]) * step / left;
+            position[1] += (target[1] - position[1]) * step / left;
+        }
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+/// Hit points, the entity is despawned once they run out
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn damage(&mut self, amount: f32) {
+        self.current = (self.current - amount).max(0.0);
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    /// Health left in 0..1, e.g. for health bars
+    pub fn fraction(&self) -> f32 {
+        if self.max <= 0.0 {
+            return 0.0;
+        }
+        self.current / self.max
+    }
+}
@@ -0,0 +1,5 @@
+mod world;
+mod components;
+pub mod systems;
+pub use self::world::{Component, Entity, Storage, World};
+pub use self::components::{Collider, Health, PathFollower, SpriteRef, Tag, Transform, Velocity};
\ No newline at end of file
@@ -0,0 +1,265 @@
+// This file contains the systems, functions that run over every entity with the components they need
+use super::components::Collider;
+use super::world::{Entity, World};
+use crate::client::collision::{self, Contact, SpatialGrid, TriggerEvent, TriggerTracker};
+use crate::client::grafx::{AnimationEvent, Grafx, SpriteInstance};
+use crate::client::map::{CollisionGrid, TileMap};
+use crate::client::physics::PlatformerInput;
+
+/// Move every entity with a velocity by one physics step
+/// Entities with a solid collider stop against the map's solid areas, and lose their speed along the blocked axis
+/// Triggers pass through the map, e.g. a moving danger zone
+pub fn movement(world: &mut World, dt: f32, collision: Option<&CollisionGrid>) {
+    for (entity, transform) in world.transforms.iter_mut() {
+        // Everything remembers where it was, including still entities, so a teleport last step doesn't blend again
+        transform.previous_position = transform.position;
+
+        if let Some(velocity) = world.velocities.get_mut(entity) {
+            let mut delta: [f32; 2] = [velocity.linear[0] * dt, velocity.linear[1] * dt];
+
+            if let Some(collision) = collision
+                && let Some(collider) = world.colliders.get(entity)
+                && !collider.trigger {
+                let (min, max) = collider.bounds(transform.position);
+                let moved: [f32; 2] = collision.move_box(min, max, delta);
+                for axis in 0..2 {
+                    if moved[axis] != delta[axis] {
+                        velocity.linear[axis] = 0.0;
+                    }
+                }
+                delta = moved;
+            }
+
+            transform.position[0] += delta[0];
+            transform.position[1] += delta[1];
+            transform.rotation += velocity.angular * dt;
+        }
+    }
+}
+
+/// Run every platformer character for one physics step, they all follow the same input
+/// Runs after `movement` so the steps they take are blended like any other motion
+pub fn platformers(world: &mut World, input: PlatformerInput, collision: Option<&CollisionGrid>, dt: f32) {
+    let empty: CollisionGrid = CollisionGrid::default();
+    let collision: &CollisionGrid = collision.unwrap_or(&empty);
+
+    for (entity, platformer) in world.platformers.iter_mut() {
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            platformer.step(&mut transform.position, input, collision, dt);
+        }
+    }
+}
+
+/// Start every path follower's step to its next tile, runs once per game tick so each step takes a tick
+pub fn walk_paths(world: &mut World, map: &TileMap, tick_seconds: f32) {
+    for (entity, follower) in world.paths.iter_mut() {
+        if let Some(transform) = world.transforms.get(entity) {
+            follower.next_step(transform.position, tick_seconds, |tile| map.tile_center(tile));
+        }
+    }
+}
+
+/// Move every path follower along its current step for one physics step, after `movement` like `platformers`
+pub fn follow_paths(world: &mut World, dt: f32) {
+    for (entity, follower) in world.paths.iter_mut() {
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            follower.advance(&mut transform.position, dt);
+        }
+    }
+}
+
+/// Find how every pair of overlapping colliders touch, each pair once with the lower entity first
+/// The grid narrows the pairs down to those sharing a cell before their shapes are tested, it is cleared first
+pub fn contacts(world: &World, grid: &mut SpatialGrid) -> Vec<(Entity, Entity, Contact)> {
+    let placed: Vec<(Entity, &Collider, [f32; 2])> = world.colliders.iter()
+        .filter_map(|(entity, collider)| Some((entity, collider, collider.position(world.transforms.get(entity)?.position))))
+        .collect();
+
+    grid.clear();
+    for (index, (_entity, collider, position)) in placed.iter().enumerate() {
+        let (min, max) = collider.shape.bounds(*position);
+        grid.insert(index, min, max);
+    }
+
+    grid.pairs().into_iter()
+        .filter_map(|(a, b)| {
+            let (a_entity, a_collider, a_position) = placed[a];
+            let (b_entity, b_collider, b_position) = placed[b];
+            let contact: Contact = collision::contact(&a_collider.shape, a_position, &b_collider.shape, b_position)?;
+            Some((a_entity, b_entity, contact))
+        })
+        .collect()
+}
+
+/// Push entities that move out of the solid colliders they overlap, and stop their velocity into them
+/// Entities with a velocity, platformer or path move, everything else stays put, triggers never push
+pub fn resolve_contacts(world: &mut World, contacts: &[(Entity, Entity, Contact)]) {
+    for (a, b, contact) in contacts {
+        let solid = |entity: &Entity| world.colliders.get(*entity).is_some_and(|collider| !collider.trigger);
+        if !solid(a) || !solid(b) {
+            continue;
+        }
+
+        let moves = |entity: &Entity| world.velocities.contains(*entity) || world.platformers.contains(*entity) || world.paths.contains(*entity);
+        let (a_push, b_push) = contact.separation(moves(a), moves(b));
+
+        for (entity, push) in [(*a, a_push), (*b, b_push)] {
+            if push == [0.0, 0.0] {
+                continue;
+            }
+            if let Some(transform) = world.transforms.get_mut(entity) {
+                transform.position[0] += push[0];
+                transform.position[1] += push[1];
+            }
+            // Only the speed heading back into the other collider is lost, sliding along it keeps going
+            if let Some(velocity) = world.velocities.get_mut(entity) {
+                let into: f32 = -(velocity.linear[0] * push[0] + velocity.linear[1] * push[1]) / (push[0].hypot(push[1]));
+                if into > 0.0 {
+                    let normal: [f32; 2] = [push[0] / push[0].hypot(push[1]), push[1] / push[0].hypot(push[1])];
+                    velocity.linear[0] += normal[0] * into;
+                    velocity.linear[1] += normal[1] * into;
+                }
+            }
+            // The map's floors ground platformers in their own step, anything else solid grounds them here
+            if let Some(platformer) = world.platformers.get_mut(entity) {
+                platformer.push_out(push);
+            }
+        }
+    }
+}
+
+/// Turn this step's contacts into events for things entering and leaving triggers
+/// Triggers overlapping each other are left out, so a door inside a quest zone doesn't enter it
+pub fn triggers(world: &World, contacts: &[(Entity, Entity, Contact)], tracker: &mut TriggerTracker<Entity>) -> Vec<TriggerEvent<Entity>> {
+    let is_trigger = |entity: Entity| world.colliders.get(entity).is_some_and(|collider| collider.trigger);
+
+    let inside = contacts.iter().filter_map(|(a, b, _contact)| match (is_trigger(*a), is_trigger(*b)) {
+        (true, false) => Some((*a, *b)),
+        (false, true) => Some((*b, *a)),
+        _ => None
+    });
+    tracker.update(inside)
+}
+
+/// Advance every animator by one physics step and show its current frame on the entity's sprite
+/// Returns the events of the frames entered, despawned entities take their animator with them
+pub fn animate(world: &mut World, dt: f32) -> Vec<AnimationEvent<Entity>> {
+    let mut events: Vec<AnimationEvent<Entity>> = Vec::new();
+
+    for (entity, animator) in world.animators.iter_mut() {
+        events.extend(animator.update(dt, entity));
+
+        if let Some(sprite) = world.sprites.get_mut(entity)
+            && let Some(frame) = animator.current_frame() {
+            sprite.frame = Some(String::from(frame));
+        }
+    }
+    events
+}
+
+/// Find the entity whose collider covers a point in the world, e.g. what the player clicked on
+/// The first in entity order wins where colliders overlap
+pub fn pick(world: &World, point: [f32; 2]) -> Option<Entity> {
+    world.colliders.iter()
+        .find(|(entity, collider)| {
+            world.transforms.get(*entity).is_some_and(|transform| collider.shape.contains(collider.position(transform.position), point))
+        })
+        .map(|(entity, _collider)| entity)
+}
+
+/// Despawn every entity whose health ran out, returns them
+pub fn despawn_dead(world: &mut World) -> Vec<Entity> {
+    let dead: Vec<Entity> = world.healths.iter()
+        .filter(|(_entity, health)| health.is_dead())
+        .map(|(entity, _health)| entity)
+        .collect();
+
+    for entity in &dead {
+        world.despawn(*entity);
+    }
+    dead
+}
+
+/// Queue every visible entity with a transform and sprite for this frame, `alpha` blends between the last two physics steps
+pub fn draw(world: &World, grafx: &mut Grafx, alpha: f32) {
+    for (entity, sprite) in world.sprites.iter() {
+        let transform = match world.transforms.get(entity) {
+            Some(transform) if sprite.visible => transform,
+            _ => continue
+        };
+
+        let scale: [f32; 2] = [sprite.size[0] * transform.scale[0], sprite.size[1] * transform.scale[1]];
+        let mut instance: SpriteInstance = SpriteInstance::new(transform.interpolated(alpha), scale, transform.rotation);
+        instance.uv_rect = sprite.uv_rect;
+        instance.color = sprite.color;
+        grafx.draw_sprite(&sprite.texture_key, sprite.frame.as_deref(), instance, sprite.layer, sprite.z, sprite.blend_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ecs::{SpriteRef, Transform, Velocity};
+    use crate::client::grafx::{AnimationClip, Animator, PlaybackMode};
+
+    #[test]
+    fn animators_drive_their_entity_sprite() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        let mut animator = Animator::new();
+        animator.add_clip(AnimationClip::from_range("walk", 0..3, 0.1, PlaybackMode::Loop).with_event(1, "footstep"));
+        animator.play("walk").unwrap();
+        world.insert(entity, SpriteRef::new("player", [16.0, 16.0]));
+        world.insert(entity, animator);
+
+        let events = animate(&mut world, 0.1);
+        assert_eq!(world.sprites.get(entity).unwrap().frame.as_deref(), Some("1"));
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].target, events[0].name.as_str()), (entity, "footstep"));
+
+        // Nothing is left to animate once the entity is gone
+        world.despawn(entity);
+        assert!(world.animators.is_empty());
+        assert!(animate(&mut world, 0.1).is_empty());
+    }
+
+    #[test]
+    fn pick_finds_the_collider_under_a_point() {
+        let mut world = World::new();
+        let door = world.spawn();
+        world.insert(door, Transform::new([10.0, 10.0]));
+        world.insert(door, Collider::new([4.0, 4.0]).as_trigger());
+        // Without a transform the collider is nowhere
+        let lost = world.spawn();
+        world.insert(lost, Collider::new([100.0, 100.0]));
+
+        assert_eq!(pick(&world, [11.0, 9.0]), Some(door));
+        assert_eq!(pick(&world, [13.0, 10.0]), None);
+    }
+
+    #[test]
+    fn only_solid_colliders_stop_at_the_map() {
+        // A wall of solid cells from x = 20 on
+        let mut collision = CollisionGrid::new([4, 4], [10.0, 10.0]);
+        for y in 0..4 {
+            collision.set_solid(2, y, true);
+        }
+
+        let mut world = World::new();
+        let mut spawn = |collider: Collider| {
+            let entity = world.spawn();
+            world.insert(entity, Transform::new([10.0, 15.0]));
+            world.insert(entity, Velocity::new([100.0, 0.0]));
+            world.insert(entity, collider);
+            entity
+        };
+        let solid = spawn(Collider::new([4.0, 4.0]));
+        let trigger = spawn(Collider::new([4.0, 4.0]).as_trigger());
+
+        movement(&mut world, 0.1, Some(&collision));
+        assert!(world.transforms.get(solid).unwrap().position[0] <= 18.0);
+        assert_eq!(world.velocities.get(solid).unwrap().linear, [0.0, 0.0]);
+        assert_eq!(world.transforms.get(trigger).unwrap().position, [20.0, 15.0]);
+        assert_eq!(world.velocities.get(trigger).unwrap().linear, [100.0, 0.0]);
+    }
+}
@@ -0,0 +1,276 @@
+// This file contains entity storage, an entity is just an ID and each kind of component lives in its own storage keyed by it
+use super::components::{Collider, Health, PathFollower, SpriteRef, Tag, Transform, Velocity};
+use crate::client::grafx::Animator;
+use crate::client::physics::Platformer;
+
+/// A game object, the generation makes IDs of despawned entities stale instead of pointing at whatever reuses their slot
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// One kind of component for every entity that has it, indexed by entity so lookups are a single array access
+/// Iteration is in entity order, which keeps systems deterministic
+pub struct Storage<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self { slots: Vec::new() }
+    }
+}
+
+impl<T> Storage<T> {
+    // Give an entity this component, replacing the one it had, only `World::insert` knows if the entity is alive
+    pub(super) fn insert(&mut self, entity: Entity, component: T) {
+        let index = entity.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index] = Some((entity.generation, component));
+    }
+
+    /// Take this component off an entity, returns it if the entity had one
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slots.get_mut(entity.index as usize)?;
+        match slot {
+            Some((generation, _component)) if *generation == entity.generation => slot.take().map(|(_generation, component)| component),
+            _ => None
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index as usize)? {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize)? {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// Every entity with this component
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            slot.as_mut().map(|(generation, component)| (Entity { index: index as u32, generation: *generation }, component))
+        })
+    }
+
+    /// Number of entities with this component
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Something that can be put on an entity, knows which of the world's storages it lives in
+pub trait Component: Sized {
+    fn storage_mut(world: &mut World) -> &mut Storage<Self>;
+}
+
+macro_rules! component {
+    ($component:ty, $storage:ident) => {
+        impl Component for $component {
+            fn storage_mut(world: &mut World) -> &mut Storage<Self> {
+                &mut world.$storage
+            }
+        }
+    };
+}
+
+component!(Transform, transforms);
+component!(SpriteRef, sprites);
+component!(Velocity, velocities);
+component!(Collider, colliders);
+component!(Health, healths);
+component!(Tag, tags);
+component!(Platformer, platformers);
+component!(PathFollower, paths);
+component!(Animator, animators);
+
+/// Every entity and their components, systems borrow the storages they need directly
+#[derive(Default)]
+pub struct World {
+    // Current generation of each slot, and whether something lives in it
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    // Slots of despawned entities, reused before the world grows
+    free: Vec<u32>,
+    pub transforms: Storage<Transform>,
+    pub sprites: Storage<SpriteRef>,
+    pub velocities: Storage<Velocity>,
+    pub colliders: Storage<Collider>,
+    pub healths: Storage<Health>,
+    pub tags: Storage<Tag>,
+    pub platformers: Storage<Platformer>,
+    pub paths: Storage<PathFollower>,
+    pub animators: Storage<Animator>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an entity with no components
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity { index, generation: self.generations[index as usize] }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity { index: (self.generations.len() - 1) as u32, generation: 0 }
+            }
+        }
+    }
+
+    /// Remove an entity and all of its components, returns false if it was already gone
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        self.transforms.remove(entity);
+        self.sprites.remove(entity);
+        self.velocities.remove(entity);
+        self.colliders.remove(entity);
+        self.healths.remove(entity);
+        self.tags.remove(entity);
+        self.platformers.remove(entity);
+        self.paths.remove(entity);
+        self.animators.remove(entity);
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    /// Give an entity a component, replacing the one it had
+    /// Returns false and does nothing if the entity was despawned, so a stale ID can't take over its slot
+    pub fn insert<C: Component>(&mut self, entity: Entity, component: C) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        C::storage_mut(self).insert(entity, component);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    /// Every living entity, in index order
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate()
+            .filter(|(_index, alive)| **alive)
+            .map(|(index, _alive)| Entity { index: index as u32, generation: self.generations[index] })
+    }
+
+    /// Number of living entities
+    pub fn len(&self) -> usize {
+        self.generations.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Find the first entity tagged with a name, e.g. the player
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.tags.iter()
+            .find(|(_entity, tag)| tag.name == name)
+            .map(|(entity, _tag)| entity)
+    }
+
+    /// Despawn every entity
+    pub fn clear(&mut self) {
+        let entities: Vec<Entity> = self.entities().collect();
+        for entity in entities {
+            self.despawn(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_slots_with_a_new_generation() {
+        let mut world = World::new();
+        let first = world.spawn();
+        world.despawn(first);
+        let second = world.spawn();
+
+        assert_eq!(first.index(), second.index());
+        assert_ne!(first, second);
+        assert!(!world.is_alive(first));
+        assert!(world.is_alive(second));
+    }
+
+    #[test]
+    fn stale_entities_get_no_components() {
+        let mut world = World::new();
+        let stale = world.spawn();
+        world.despawn(stale);
+        let live = world.spawn();
+
+        assert!(world.insert(live, Health::new(10.0)));
+        assert!(!world.insert(stale, Health::new(1.0)));
+        assert!(!world.insert(stale, Transform::new([0.0, 0.0])));
+
+        assert_eq!(world.healths.get(live).map(|health| health.max), Some(10.0));
+        assert!(world.healths.get(stale).is_none());
+        assert!(world.transforms.is_empty());
+        assert_eq!(world.healths.iter().map(|(entity, _health)| entity).collect::<Vec<Entity>>(), vec![live]);
+    }
+
+    #[test]
+    fn despawn_removes_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Transform::new([1.0, 2.0]));
+        world.insert(entity, Tag::new("player", ""));
+
+        assert_eq!(world.find("player"), Some(entity));
+        assert!(world.despawn(entity));
+        assert!(!world.despawn(entity));
+        assert!(world.transforms.is_empty());
+        assert_eq!(world.find("player"), None);
+    }
+}
@@ -0,0 +1,206 @@
+// This file contains the world clock that drives the day/night cycle
+
+/// Real seconds for one full in-game day at normal speed
+const DEFAULT_DAY_LENGTH: f32 = 20.0 * 60.0;
+
+const NIGHT: [f32; 3] = [0.35, 0.4, 0.65];
+const DAWN: [f32; 3] = [1.0, 0.75, 0.6];
+const DAY: [f32; 3] = [1.0, 1.0, 1.0];
+const DUSK: [f32; 3] = [1.0, 0.6, 0.5];
+
+/// World tint at each hour, colors in between are blended
+const TINT_RAMP: [(f32, [f32; 3]); 8] = [
+    (0.0, NIGHT),
+    (5.0, NIGHT),
+    (6.5, DAWN),
+    (8.0, DAY),
+    (17.0, DAY),
+    (18.5, DUSK),
+    (20.0, NIGHT),
+    (24.0, NIGHT),
+];
+
+/// How much of the day sky shows at each hour, 1.0 is full day and 0.0 full night
+const DAYLIGHT_RAMP: [(f32, f32); 6] = [
+    (0.0, 0.0),
+    (5.0, 0.0),
+    (8.0, 1.0),
+    (17.0, 1.0),
+    (20.0, 0.0),
+    (24.0, 0.0),
+];
+
+pub struct WorldClock {
+    /// Hour of the day in 0..24
+    hours: f32,
+    day_length: f32,
+    speed: f32,
+    paused: bool,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorldClock {
+    /// Create a clock starting at noon
+    pub fn new() -> Self {
+        Self {
+            hours: 12.0,
+            day_length: DEFAULT_DAY_LENGTH,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    /// Advance the clock by `dt` real seconds
+    pub fn update(&mut self, dt: f32) {
+        if self.paused {
+            return;
+        }
+        self.hours = (self.hours + dt * self.speed * 24.0 / self.day_length).rem_euclid(24.0);
+    }
+
+    pub fn hours(&self) -> f32 {
+        self.hours
+    }
+
+    /// Jump to an hour of the day, wrapped into 0..24
+    pub fn set_time(&mut self, hours: f32) {
+        self.hours = hours.rem_euclid(24.0);
+    }
+
+    /// Set how many times faster than normal the clock runs
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Set how many real seconds a full day lasts at normal speed
+    pub fn set_day_length(&mut self, seconds: f32) {
+        self.day_length = seconds.max(f32::EPSILON);
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// How much of the day sky should show, 1.0 is full day and 0.0 full night
+    pub fn daylight(&self) -> f32 {
+        let mut daylight = 0.0;
+        for pair in DAYLIGHT_RAMP.windows(2) {
+            let ((start, from), (end, to)) = (pair[0], pair[1]);
+            if self.hours >= start && self.hours <= end {
+                daylight = from + (to - from) * (self.hours - start) / (end - start);
+                break;
+            }
+        }
+        daylight
+    }
+
+    /// Color to multiply the world by at the current time
+    pub fn tint(&self) -> [f32; 4] {
+        let mut tint = NIGHT;
+        for pair in TINT_RAMP.windows(2) {
+            let ((start, from), (end, to)) = (pair[0], pair[1]);
+            if self.hours >= start && self.hours <= end {
+                let t = (self.hours - start) / (end - start);
+                tint = [
+                    from[0] + (to[0] - from[0]) * t,
+                    from[1] + (to[1] - from[1]) * t,
+                    from[2] + (to[2] - from[2]) * t,
+                ];
+                break;
+            }
+        }
+        [tint[0], tint[1], tint[2], 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.0001
+    }
+
+    #[test]
+    fn runs_a_day_per_day_length_and_wraps() {
+        let mut clock = WorldClock::new();
+        clock.set_day_length(24.0);
+        assert_eq!(clock.hours(), 12.0);
+
+        clock.update(6.0);
+        assert!(close(clock.hours(), 18.0));
+        clock.update(10.0);
+        assert!(close(clock.hours(), 4.0));
+
+        clock.set_speed(2.0);
+        clock.update(1.0);
+        assert!(close(clock.hours(), 6.0));
+    }
+
+    #[test]
+    fn paused_and_stopped_clocks_stand_still() {
+        let mut clock = WorldClock::new();
+        clock.pause();
+        clock.update(600.0);
+        assert!(clock.is_paused());
+        assert_eq!(clock.hours(), 12.0);
+
+        clock.resume();
+        clock.set_speed(-3.0);
+        assert_eq!(clock.speed(), 0.0);
+        clock.update(600.0);
+        assert_eq!(clock.hours(), 12.0);
+    }
+
+    #[test]
+    fn set_time_wraps_into_the_day() {
+        let mut clock = WorldClock::new();
+        clock.set_time(25.5);
+        assert!(close(clock.hours(), 1.5));
+        clock.set_time(-2.0);
+        assert!(close(clock.hours(), 22.0));
+    }
+
+    #[test]
+    fn daylight_follows_the_ramp() {
+        let mut clock = WorldClock::new();
+        for (hours, daylight) in [(0.0, 0.0), (5.0, 0.0), (6.5, 0.5), (12.0, 1.0), (18.5, 0.5), (20.0, 0.0), (23.9, 0.0)] {
+            clock.set_time(hours);
+            assert!(close(clock.daylight(), daylight), "{} hours gave {}", hours, clock.daylight());
+        }
+    }
+
+    #[test]
+    fn tint_blends_between_colors() {
+        let mut clock = WorldClock::new();
+        clock.set_time(12.0);
+        assert_eq!(clock.tint(), [1.0, 1.0, 1.0, 1.0]);
+        clock.set_time(2.0);
+        assert_eq!(clock.tint(), [NIGHT[0], NIGHT[1], NIGHT[2], 1.0]);
+
+        // Halfway from dawn to day
+        clock.set_time(7.25);
+        let tint = clock.tint();
+        for channel in 0..3 {
+            assert!(close(tint[channel], (DAWN[channel] + DAY[channel]) / 2.0));
+        }
+        assert_eq!(tint[3], 1.0);
+    }
+}
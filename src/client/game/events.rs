@@ -0,0 +1,52 @@
+// This file contains what the simulation reports back each frame, scenes read it to react, e.g. opening a door the player walked into
+use crate::client::collision::{TriggerEvent, TriggerTracker};
+use crate::client::ecs::Entity;
+use crate::client::grafx::AnimationEvent;
+
+/// Something the player asked of the world, for game code to react to, e.g. opening a door they clicked on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlayerAction {
+    /// Interact with a world position the player didn't walk to, and the entity whose collider covers it
+    Interact { at: [f32; 2], entity: Option<Entity> },
+    OpenInventory,
+    /// Open a context menu for a world position, and the entity whose collider covers it
+    ContextMenu { at: [f32; 2], entity: Option<Entity> },
+}
+
+/// What happened in the world simulation during the last frame
+#[derive(Default)]
+pub struct SimulationEvents {
+    /// Entities entering and leaving trigger volumes, in the order they happened
+    pub triggers: Vec<TriggerEvent<Entity>>,
+    /// Frames with a named event that entity animations reached, e.g. a footstep
+    pub animations: Vec<AnimationEvent<Entity>>,
+    /// What the player asked for this frame, in the order they asked, kept until the scenes take input again
+    pub actions: Vec<PlayerAction>,
+    // Which entities are inside which triggers between physics steps
+    pub(super) tracker: TriggerTracker<Entity>,
+}
+
+impl SimulationEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget every event and who is inside which trigger without sending exit events, e.g. when the level changes
+    pub fn reset(&mut self) {
+        self.triggers.clear();
+        self.animations.clear();
+        self.actions.clear();
+        self.tracker.clear();
+    }
+
+    // Drop last frame's events before the simulation runs again
+    pub(super) fn begin_frame(&mut self) {
+        self.triggers.clear();
+        self.animations.clear();
+    }
+
+    // Drop last frame's actions before the scenes take input again
+    pub(super) fn begin_input(&mut self) {
+        self.actions.clear();
+    }
+}
@@ -1,5 +1,7 @@
 use std::{
-    time::{Duration, Instant},
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
     sync::Arc
 };
 
@@ -10,14 +12,46 @@ use winit::{
     window::{Window, WindowId}
 };
 
-use crate::client::grafx::Grafx;
+use crate::client::grafx::{AnimationEvent, CaptureFormat, Grafx};
+use super::clock::WorldClock;
+use super::events::SimulationEvents;
+use super::timestep::{FixedTimestep, Interpolated};
+use super::scene::{SceneChange, SceneContext, SceneStack};
+use super::scenes::TitleScreen;
+use crate::client::assets::{Font, Handle};
+use crate::client::collision::{Contact, SpatialGrid, TriggerEvent};
+use crate::client::ecs::{self, Entity, World};
+use crate::client::input::{self, Bindings, Input};
+use crate::client::map::TileMap;
+use crate::client::physics::PlatformerInput;
+use crate::client::ui::{Ui, UiTheme};
 use crate::client::config;
 
+/// Manifest ID of the font the interface is drawn with, the interface works without it but has no labels
+const UI_FONT: &str = "ui";
+
 pub struct Game {
     grafx: Option<Grafx>,
     framerate: Duration,
     last_render_time: Instant,
-    mouse_position: (f64, f64)
+    // When the last frame caught scenes and the simulation up to real time
+    last_update: Instant,
+    ticks: FixedTimestep,
+    physics: FixedTimestep,
+    // Positions the simulation gave dynamic sprites, drawn blended between the last two physics steps
+    motion: HashMap<String, Interpolated>,
+    world: World,
+    // Map of the current level, if the world scene loaded one
+    map: Option<TileMap>,
+    // Broad phase for collider contacts, kept between physics steps to reuse its memory
+    broad_phase: SpatialGrid,
+    events: SimulationEvents,
+    input: Input,
+    clock: WorldClock,
+    scenes: SceneStack,
+    ui: Ui,
+    // Held so the interface font stays loaded
+    ui_font: Option<Handle<Font>>
 }
 
 
@@ -28,8 +62,135 @@ impl Game {
             grafx: None,
             framerate: Duration::from_secs_f64(1.0 / config::FRAMERATE),
             last_render_time: Instant::now(),
-            mouse_position: (0.0, 0.0)
+            last_update: Instant::now(),
+            ticks: FixedTimestep::new(Duration::from_secs_f64(config::TICK_SECONDS), config::MAX_CATCH_UP_STEPS).expect("config::TICK_SECONDS must be above zero"),
+            physics: FixedTimestep::from_rate(config::PHYSICS_RATE, config::MAX_CATCH_UP_STEPS).expect("config::PHYSICS_RATE must be above zero"),
+            motion: HashMap::new(),
+            world: World::new(),
+            map: None,
+            broad_phase: SpatialGrid::new(config::COLLISION_CELL_SIZE),
+            events: SimulationEvents::new(),
+            input: Input::new(Self::load_bindings()),
+            clock: WorldClock::new(),
+            scenes: SceneStack::new(),
+            ui: Ui::new(UiTheme::new(UI_FONT)),
+            ui_font: None
+        }
+    }
+
+    // Read the player's key bindings, anything the file leaves out keeps its default
+    fn load_bindings() -> Bindings {
+        let mut bindings: Bindings = Bindings::default();
+        if let Err(err) = bindings.load(std::path::Path::new(input::BINDINGS_PATH)) {
+            println!("Bindings not all loaded, the actions they were for keep their defaults: {}", err);
+        }
+        bindings
+    }
+
+    /// Get the input state, e.g. to rebind actions
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// Get the world clock, e.g. to pause it or change its speed
+    pub fn clock_mut(&mut self) -> &mut WorldClock {
+        &mut self.clock
+    }
+
+    /// Get the entities in the world, e.g. to spawn or despawn them
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Get what happened in the simulation during the last frame, e.g. what entered and left trigger volumes
+    pub fn events(&self) -> &SimulationEvents {
+        &self.events
+    }
+
+    /// Number of game ticks run so far
+    pub fn game_ticks(&self) -> u64 {
+        self.ticks.steps()
+    }
+
+    /// Move a dynamic sprite from simulation code, it is drawn sliding smoothly between physics steps
+    pub fn move_sprite(&mut self, key: &str, position: [f32; 2]) {
+        self.motion.entry(String::from(key))
+            .or_insert_with(|| Interpolated::new(position))
+            .current = position;
+    }
+
+    /// Move a dynamic sprite without sliding there, e.g. for teleports
+    pub fn snap_sprite(&mut self, key: &str, position: [f32; 2]) {
+        self.motion.entry(String::from(key))
+            .or_insert_with(|| Interpolated::new(position))
+            .snap(position);
+    }
+
+    // Run as many game ticks and physics steps as the real time since the last frame covers, each with the same dt
+    // While paused the time still passes, so the world doesn't jump ahead when it resumes
+    fn update_simulation(&mut self, running: bool, elapsed: Duration) {
+        self.events.begin_frame();
+
+        if running {
+            for _ in 0..self.ticks.advance(elapsed) {
+                self.game_tick();
+            }
+            for _ in 0..self.physics.advance(elapsed) {
+                self.physics_step();
+            }
+        }
+
+        self.apply_motion();
+    }
+
+    // Advance game logic by one tick
+    fn game_tick(&mut self) {
+        self.clock.update(self.ticks.dt());
+        ecs::systems::despawn_dead(&mut self.world);
+        if let Some(map) = &self.map {
+            ecs::systems::walk_paths(&mut self.world, map, self.ticks.dt());
+        }
+    }
+
+    // Advance motion and animation by one physics step
+    fn physics_step(&mut self) {
+        for motion in self.motion.values_mut() {
+            motion.begin_step();
+        }
+        let collision = self.map.as_ref().map(|map| &map.collision);
+        ecs::systems::movement(&mut self.world, self.physics.dt(), collision);
+
+        let controls: PlatformerInput = PlatformerInput {
+            horizontal: self.input.axis("move_left", "move_right"),
+            jump: self.input.is_held("jump"),
+            drop: self.input.is_held("move_down"),
+        };
+        ecs::systems::platformers(&mut self.world, controls, collision, self.physics.dt());
+        ecs::systems::follow_paths(&mut self.world, self.physics.dt());
+
+        let contacts: Vec<(Entity, Entity, Contact)> = ecs::systems::contacts(&self.world, &mut self.broad_phase);
+        ecs::systems::resolve_contacts(&mut self.world, &contacts);
+        let triggers: Vec<TriggerEvent<Entity>> = ecs::systems::triggers(&self.world, &contacts, &mut self.events.tracker);
+        self.events.triggers.extend(triggers);
+
+        let animations: Vec<AnimationEvent<Entity>> = ecs::systems::animate(&mut self.world, self.physics.dt());
+        self.events.animations.extend(animations);
+    }
+
+    // Place moving sprites and entities between their last two physics positions, by how far real time is into the next step
+    fn apply_motion(&mut self) {
+        let grafx = match &mut self.grafx {
+            Some(grafx) => grafx,
+            None => return
+        };
+
+        let alpha: f32 = self.physics.alpha();
+        // Forget sprites that were removed
+        self.motion.retain(|key, motion| grafx.set_sprite_position(key, motion.lerp(alpha)).is_ok());
+        if let Some(map) = &self.map {
+            map.draw(grafx);
         }
+        ecs::systems::draw(&self.world, grafx, alpha);
     }
 
     // Create game window tied to an event loop
@@ -38,7 +199,7 @@ impl Game {
         let attributes = Window::default_attributes()
         .with_title("Poprustica")
         .with_inner_size(winit::dpi::LogicalSize::new(config::WINDOW_WIDTH, config::WINDOW_HEIGHT))
-        .with_resizable(false);
+        .with_resizable(true);
 
         // Create the window
         let window = Arc::new(
@@ -47,10 +208,84 @@ impl Game {
                 .expect("Failed to create window"),
         );
 
-        let grafx = pollster::block_on(Grafx::new(window.clone()));
+        let mut grafx = match pollster::block_on(Grafx::new(window.clone())) {
+            Ok(grafx) => grafx,
+            Err(err) => {
+                println!("Failed to start the renderer: {}", err);
+                event_loop.exit();
+                return;
+            }
+        };
+        grafx.set_hot_reload(config::HOT_RELOAD);
+
+        // No font ships with the game, so only load one if the manifest lists it
+        if grafx.assets().resolve(UI_FONT).is_some() {
+            self.ui_font = grafx.load_font_asset(UI_FONT).ok();
+        }
+
         self.grafx = Some(grafx);
+        self.with_scenes(|scenes, ctx| scenes.apply(ctx, SceneChange::Push(Box::new(TitleScreen))));
+
+        // Creating the window can take a while, don't simulate that time
+        self.last_update = Instant::now();
+    }
+
+    // Run something on the scene stack with the game state it can reach, does nothing before the window exists
+    fn with_scenes(&mut self, run: impl FnOnce(&mut SceneStack, &mut SceneContext)) {
+        let grafx = match &mut self.grafx {
+            Some(grafx) => grafx,
+            None => return
+        };
+
+        let mut ctx: SceneContext = SceneContext::new(grafx, &self.input, &mut self.ui, &mut self.world, &mut self.map, &mut self.clock, &mut self.events, self.ticks.steps());
+        run(&mut self.scenes, &mut ctx);
+    }
+
+    // Capture hotkeys work in every scene
+    fn update_controls(&mut self) {
+        if let Some(grafx) = &mut self.grafx {
+            for action in ["screenshot", "record"] {
+                if self.input.is_pressed(action) {
+                    capture(grafx, action);
+                }
+            }
+        }
     }
+}
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Save a screenshot or start/stop recording a GIF, `action` is "screenshot" or "record"
+pub fn capture(grafx: &mut Grafx, action: &str) {
+    // Name captures by time so they never overwrite each other
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+
+    match action {
+        "screenshot" => {
+            let path = PathBuf::from(format!("screenshots/screenshot_{}.png", timestamp));
+            println!("Saving screenshot to '{}'", path.display());
+            grafx.request_screenshot(&path);
+        }
+        "record" if grafx.is_recording() => {
+            match grafx.stop_recording() {
+                Ok(_) => println!("Recording stopped, saving it"),
+                Err(err) => println!("Failed to save recording: {}", err)
+            }
+        }
+        "record" => {
+            let path = PathBuf::from(format!("screenshots/recording_{}.gif", timestamp));
+            let max_frames = (config::FRAMERATE * config::MAX_RECORDING_SECONDS) as usize;
+            match grafx.start_recording(&path, CaptureFormat::Gif, max_frames) {
+                Ok(_) => println!("Recording to '{}'", path.display()),
+                Err(err) => println!("Failed to start recording: {}", err)
+            }
+        }
+        _ => {}
+    }
 }
 
 // Implement the ApplicationHandler trait for Game
@@ -61,34 +296,79 @@ impl ApplicationHandler for Game {
             WindowEvent::RedrawRequested => {
                 self.last_render_time += self.framerate;
 
-                match &mut self.grafx {
-                    Some(grafx) => {
-                        let _ = grafx.render();
+                // Frames don't always come on time, scenes and the camera move by the real time since the last one
+                let now: Instant = Instant::now();
+                let elapsed: Duration = now - self.last_update;
+                self.last_update = now;
+                let dt: f32 = elapsed.as_secs_f32();
+                if let Some(grafx) = &mut self.grafx {
+                    grafx.poll_loading();
+                    grafx.poll_hot_reload();
+                }
+
+                // Scenes and the camera run per frame, the world only moves in fixed steps while a scene that simulates it is on top
+                self.update_controls();
+                self.events.begin_input();
+                self.with_scenes(|scenes, ctx| scenes.update(ctx, dt));
+                self.update_simulation(self.scenes.runs_simulation(), elapsed);
+                self.with_scenes(|scenes, ctx| scenes.render(ctx));
+                self.ui.end_frame();
+
+                if self.scenes.should_quit() {
+                    event_loop.exit();
+                    return;
+                }
+
+                if let Some(grafx) = &mut self.grafx {
+                    grafx.update_camera(dt);
+                    grafx.collect_unused_assets();
+                    if let Err(err) = grafx.render() {
+                        println!("Failed to render frame: {}", err);
                     }
-                    _ => {}
                 }
+                self.input.end_frame();
             }
             WindowEvent::CloseRequested => {
                 event_loop.exit()
             }
+            WindowEvent::Resized(size) => {
+                if let Some(grafx) = &mut self.grafx {
+                    grafx.resize(size);
+                }
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
+                // The new physical size usually follows in a Resized event, but not on every platform
+                if let Some(grafx) = &mut self.grafx
+                    && let Some(size) = grafx.get_window().map(|window| window.inner_size()) {
+                    grafx.resize(size);
+                }
+            }
+            WindowEvent::KeyboardInput { .. } | WindowEvent::Focused(_) => {
+                self.input.handle_event(&event);
+            }
             WindowEvent::CursorMoved { position, ..  } => {
-                self.mouse_position = (position.x, position.y);
+                self.input.handle_event(&event);
+                if let Some(grafx) = &self.grafx {
+                    self.ui.mouse_moved(grafx.screen_to_logical((position.x, position.y)));
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                // Scrolling over the interface doesn't zoom the world
+                let consumed: bool = self.ui.mouse_wheel(input::scroll_lines(&delta));
+                if !consumed {
+                    self.input.handle_event(&event);
+                }
             }
             WindowEvent::MouseInput { button, state, .. } => {
-                match (button, state) {
-                    (MouseButton::Left, ElementState::Pressed) => {
-                        println!("Left mouse button pressed!");
-                    }
-                    (MouseButton::Left, ElementState::Released) => {
-                        println!("Left mouse button released!");
-                    }
-                    (MouseButton::Right, ElementState::Pressed) => {
-                        println!("Right mouse button pressed!");
-                    }
-                    (MouseButton::Right, ElementState::Released) => {
-                        println!("Right mouse button released!");
-                    }
-                    _ => {}
+                // Presses of any button on the interface don't reach the world, but releases always do so nothing stays held
+                // Only the left button drives widgets
+                let pressed: bool = state == ElementState::Pressed;
+                let consumed: bool = match button {
+                    MouseButton::Left => self.ui.mouse_button(pressed),
+                    _ => self.ui.is_mouse_over_ui()
+                };
+                if !consumed || !pressed {
+                    self.input.handle_event(&event);
                 }
             }
             _ => {}
@@ -112,8 +392,9 @@ impl ApplicationHandler for Game {
         let now = Instant::now();
 
         // If its time to render a new frame, call request_redraw
-        if now.duration_since(self.last_render_time) >= self.framerate {
-            self.grafx.as_mut().unwrap().get_window().request_redraw();
+        if now.duration_since(self.last_render_time) >= self.framerate
+            && let Some(window) = self.grafx.as_ref().and_then(|grafx| grafx.get_window()) {
+            window.request_redraw();
         }
 
         // Wait until time to next render or another event arrives (helps prevent cpu from getting slammed)
@@ -1,2 +1,13 @@
+#[allow(clippy::module_inception)]
 mod game;
-pub use self::game::Game;
\ No newline at end of file
+mod clock;
+mod timestep;
+mod scene;
+mod events;
+mod scenes;
+pub use self::game::Game;
+pub use self::clock::WorldClock;
+pub use self::events::{PlayerAction, SimulationEvents};
+pub use self::timestep::{FixedTimestep, Interpolated};
+pub use self::scene::{Scene, SceneChange, SceneContext, SceneStack};
+pub use self::scenes::{LoadingScene, PauseMenu, SettingsMenu, TitleScreen, WorldScene};
\ No newline at end of file
@@ -0,0 +1,389 @@
+// This file contains the scene stack, the game is always in the scene on top and overlays like menus are pushed over it
+use std::collections::VecDeque;
+use crate::client::ecs::World;
+use crate::client::grafx::{Grafx, WHITE_TEXTURE_KEY};
+use crate::client::input::Input;
+use crate::client::map::TileMap;
+use crate::client::ui::Ui;
+use super::clock::WorldClock;
+use super::events::SimulationEvents;
+
+/// Seconds to fade to black, and again to fade back in, when a transition changes scenes
+const FADE_SECONDS: f32 = 0.3;
+
+/// A mode the game can be in, every hook gets the shared game state and can ask for scene changes through it
+pub trait Scene {
+    /// Name shown in logs
+    fn name(&self) -> &str;
+
+    /// Called when the scene is added to the stack
+    fn enter(&mut self, _ctx: &mut SceneContext) {}
+
+    /// Called when the scene is removed from the stack
+    fn exit(&mut self, _ctx: &mut SceneContext) {}
+
+    /// React to this frame's input, only the top scene gets input
+    fn input(&mut self, _ctx: &mut SceneContext) {}
+
+    /// Advance by a frame, only the top scene is updated
+    fn update(&mut self, _ctx: &mut SceneContext, _dt: f32) {}
+
+    /// Draw this frame's interface, every visible scene is rendered from the bottom up
+    fn render(&mut self, _ctx: &mut SceneContext) {}
+
+    /// True if the scenes beneath still show, like a pause menu over the world
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    /// True if the world simulation runs while this scene is on top
+    fn runs_simulation(&self) -> bool {
+        false
+    }
+}
+
+/// A change to the scene stack
+pub enum SceneChange {
+    /// Put a scene on top, the one beneath stays but stops getting input and updates
+    Push(Box<dyn Scene>),
+    /// Remove the top scene, the game quits if none are left
+    Pop,
+    /// Swap the top scene for another
+    Replace(Box<dyn Scene>),
+    /// Remove every scene and start over with one
+    Reset(Box<dyn Scene>),
+    Quit,
+}
+
+/// What scenes can reach while their hooks run
+pub struct SceneContext<'a> {
+    pub grafx: &'a mut Grafx,
+    pub input: &'a Input,
+    pub ui: &'a mut Ui,
+    pub world: &'a mut World,
+    /// Map of the current level, drawn under the world and blocking movement while set
+    pub map: &'a mut Option<TileMap>,
+    pub clock: &'a mut WorldClock,
+    /// What the simulation reported during the last frame, e.g. the player walking into a door
+    pub events: &'a mut SimulationEvents,
+    /// Game ticks run so far
+    pub ticks: u64,
+    // Changes asked for by hooks, applied by the stack once the hook returns
+    changes: Vec<(SceneChange, bool)>,
+}
+
+impl<'a> SceneContext<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(grafx: &'a mut Grafx, input: &'a Input, ui: &'a mut Ui, world: &'a mut World, map: &'a mut Option<TileMap>, clock: &'a mut WorldClock, events: &'a mut SimulationEvents, ticks: u64) -> Self {
+        Self { grafx, input, ui, world, map, clock, events, ticks, changes: Vec::new() }
+    }
+
+    /// Change scenes straight away
+    pub fn change(&mut self, change: SceneChange) {
+        self.changes.push((change, false));
+    }
+
+    /// Fade to black, change scenes and fade back in
+    pub fn transition(&mut self, change: SceneChange) {
+        self.changes.push((change, true));
+    }
+}
+
+// A transition in progress, the change is made once the screen is fully black
+struct Fade {
+    change: Option<SceneChange>,
+    elapsed: f32,
+}
+
+// Fades between scenes, transitions asked for while one runs each get their own fade once it finishes
+#[derive(Default)]
+struct Transitions {
+    fade: Option<Fade>,
+    queued: VecDeque<SceneChange>,
+}
+
+impl Transitions {
+    // Start fading to a change, or queue it behind the running fade
+    fn request(&mut self, change: SceneChange) {
+        if self.fade.is_none() {
+            self.fade = Some(Fade { change: Some(change), elapsed: 0.0 });
+        } else {
+            self.queued.push_back(change);
+        }
+    }
+
+    fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    // Advance the fade, returns its change once the screen is fully black so it is made while nothing shows
+    fn advance(&mut self, dt: f32) -> Option<SceneChange> {
+        let fade = self.fade.as_mut()?;
+        fade.elapsed += dt;
+        if fade.elapsed >= FADE_SECONDS {
+            return fade.change.take();
+        }
+        None
+    }
+
+    // Once faded back in, start the next queued transition, runs after the change is made so one it asked for queues first
+    fn finish(&mut self) {
+        if self.fade.as_ref().is_some_and(|fade| fade.elapsed >= FADE_SECONDS * 2.0) {
+            self.fade = self.queued.pop_front().map(|change| Fade { change: Some(change), elapsed: 0.0 });
+        }
+    }
+
+    // How dark the screen is in 0..1, None while not fading
+    fn opacity(&self) -> Option<f32> {
+        let fade = self.fade.as_ref()?;
+        let opacity: f32 = if fade.elapsed < FADE_SECONDS {
+            fade.elapsed / FADE_SECONDS
+        } else {
+            2.0 - fade.elapsed / FADE_SECONDS
+        };
+        Some(opacity.clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Default)]
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    transitions: Transitions,
+    quit: bool,
+}
+
+impl SceneStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name of the top scene
+    pub fn current(&self) -> Option<&str> {
+        self.scenes.last().map(|scene| scene.name())
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /// True once a scene asked to quit or the last scene was popped
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// True if the world simulation should run this frame
+    pub fn runs_simulation(&self) -> bool {
+        !self.transitions.is_fading() && self.scenes.last().is_some_and(|scene| scene.runs_simulation())
+    }
+
+    /// Change scenes straight away, e.g. to push the first scene
+    pub fn apply(&mut self, ctx: &mut SceneContext, change: SceneChange) {
+        match change {
+            SceneChange::Push(mut scene) => {
+                scene.enter(ctx);
+                self.scenes.push(scene);
+            }
+            SceneChange::Pop => {
+                if let Some(mut scene) = self.pop() {
+                    scene.exit(ctx);
+                }
+            }
+            SceneChange::Replace(mut scene) => {
+                if let Some(mut old) = self.scenes.pop() {
+                    old.exit(ctx);
+                }
+                scene.enter(ctx);
+                self.scenes.push(scene);
+            }
+            SceneChange::Reset(mut scene) => {
+                while let Some(mut old) = self.scenes.pop() {
+                    old.exit(ctx);
+                }
+                scene.enter(ctx);
+                self.scenes.push(scene);
+            }
+            SceneChange::Quit => {
+                self.quit = true;
+            }
+        }
+
+        // Scenes entered above may have asked for more changes
+        self.apply_requested(ctx);
+    }
+
+    /// Give the top scene this frame's input and update it, and advance any fade
+    pub fn update(&mut self, ctx: &mut SceneContext, dt: f32) {
+        if self.transitions.is_fading() {
+            if let Some(change) = self.transitions.advance(dt) {
+                self.apply(ctx, change);
+            }
+            self.transitions.finish();
+            return;
+        }
+
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.input(ctx);
+            scene.update(ctx, dt);
+        }
+        self.apply_requested(ctx);
+    }
+
+    /// Render every visible scene from the bottom up, only the top one's interface can be clicked
+    pub fn render(&mut self, ctx: &mut SceneContext) {
+        let first_visible: usize = self.first_visible();
+        let top: usize = self.scenes.len().saturating_sub(1);
+        let fading: bool = self.transitions.is_fading();
+
+        for (index, scene) in self.scenes.iter_mut().enumerate().skip(first_visible) {
+            ctx.ui.set_interactive(index == top && !fading);
+            scene.render(ctx);
+        }
+        ctx.ui.set_interactive(true);
+
+        if let Some(opacity) = self.transitions.opacity() {
+            let viewport: [f32; 2] = ctx.grafx.camera().viewport();
+            ctx.grafx.draw_ui_rect(WHITE_TEXTURE_KEY, [0.0, 0.0, viewport[0], viewport[1]], [0.0, 0.0, 1.0, 1.0], [0.0, 0.0, 0.0, opacity], None);
+        }
+    }
+
+    // Take the top scene off, the game quits once none are left
+    fn pop(&mut self) -> Option<Box<dyn Scene>> {
+        let scene: Option<Box<dyn Scene>> = self.scenes.pop();
+        if self.scenes.is_empty() {
+            self.quit = true;
+        }
+        scene
+    }
+
+    // Overlays show what is beneath them, down to the first scene that isn't one
+    fn first_visible(&self) -> usize {
+        self.scenes.iter().rposition(|scene| !scene.is_overlay()).unwrap_or(0)
+    }
+
+    // Apply the changes hooks asked for, a transition starts a fade or waits for the running one to finish
+    // e.g. a scene entered during a fade's swap can transition on straight away
+    fn apply_requested(&mut self, ctx: &mut SceneContext) {
+        for (change, fade) in std::mem::take(&mut ctx.changes) {
+            if fade {
+                self.transitions.request(change);
+            } else {
+                self.apply(ctx, change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scene that only answers what kind of scene it is, the stack logic below never calls its hooks
+    struct Stub {
+        name: &'static str,
+        overlay: bool,
+        simulates: bool,
+    }
+
+    impl Scene for Stub {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn is_overlay(&self) -> bool {
+            self.overlay
+        }
+
+        fn runs_simulation(&self) -> bool {
+            self.simulates
+        }
+    }
+
+    fn stack_of(scenes: Vec<Stub>) -> SceneStack {
+        let mut stack = SceneStack::new();
+        for scene in scenes {
+            stack.scenes.push(Box::new(scene));
+        }
+        stack
+    }
+
+    fn world() -> Stub {
+        Stub { name: "world", overlay: false, simulates: true }
+    }
+
+    fn pause() -> Stub {
+        Stub { name: "pause", overlay: true, simulates: false }
+    }
+
+    fn settings() -> Stub {
+        Stub { name: "settings", overlay: false, simulates: false }
+    }
+
+    /// Name of the scene a change pushes
+    fn pushed(change: Option<SceneChange>) -> Option<String> {
+        match change? {
+            SceneChange::Push(scene) => Some(String::from(scene.name())),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn popping_the_last_scene_quits() {
+        let mut stack = stack_of(vec![world(), pause()]);
+        assert_eq!(stack.pop().map(|scene| String::from(scene.name())), Some(String::from("pause")));
+        assert!(!stack.should_quit());
+        assert_eq!(stack.current(), Some("world"));
+
+        assert!(stack.pop().is_some());
+        assert!(stack.should_quit());
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn overlays_show_the_scenes_beneath_them() {
+        let stack = stack_of(vec![world(), pause()]);
+        assert_eq!(stack.first_visible(), 0);
+        // Menus over the world stop it, even though it still shows
+        assert!(!stack.runs_simulation());
+
+        let stack = stack_of(vec![world(), settings(), pause()]);
+        assert_eq!(stack.first_visible(), 1);
+
+        let mut stack = stack_of(vec![world()]);
+        assert_eq!(stack.first_visible(), 0);
+        assert!(stack.runs_simulation());
+        // Nothing moves while the screen fades
+        stack.transitions.request(SceneChange::Pop);
+        assert!(!stack.runs_simulation());
+    }
+
+    #[test]
+    fn transitions_asked_for_during_a_fade_wait_for_it() {
+        let mut transitions = Transitions::default();
+        transitions.request(SceneChange::Push(Box::new(world())));
+        assert_eq!(transitions.opacity(), Some(0.0));
+
+        // Nothing changes until the screen is fully black
+        assert!(transitions.advance(0.25).is_none());
+        assert_eq!(pushed(transitions.advance(0.25)).as_deref(), Some("world"));
+        // The scene swapped in asks to move on straight away, which waits
+        transitions.request(SceneChange::Push(Box::new(settings())));
+        transitions.finish();
+        assert!(transitions.advance(0.0).is_none());
+
+        // Fading back in, then the queued transition gets a fade of its own
+        assert!(transitions.advance(0.25).is_none());
+        transitions.finish();
+        assert_eq!(transitions.opacity(), Some(0.0));
+        assert!(transitions.queued.is_empty());
+        assert!(transitions.advance(0.25).is_none());
+        assert_eq!(pushed(transitions.advance(0.25)).as_deref(), Some("settings"));
+
+        assert!(transitions.advance(0.3).is_none());
+        transitions.finish();
+        assert!(!transitions.is_fading());
+    }
+}
@@ -0,0 +1,66 @@
+// This file contains the loading scene, it streams the world's assets in behind the loading screen
+use crate::client::assets::{AssetKind, Handle, Texture};
+use crate::client::game::scene::{Scene, SceneChange, SceneContext};
+use crate::client::map::TileMap;
+use super::world::WorldScene;
+
+/// Manifest ID of the level map, the world is empty without one
+const LEVEL_MAP: &str = "level";
+
+pub struct LoadingScene {
+    sky: Vec<Handle<Texture>>,
+    map: Option<TileMap>,
+}
+
+impl LoadingScene {
+    pub fn new() -> Self {
+        Self { sky: Vec::new(), map: None }
+    }
+}
+
+impl Default for LoadingScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene for LoadingScene {
+    fn name(&self) -> &str {
+        "loading"
+    }
+
+    fn enter(&mut self, ctx: &mut SceneContext) {
+        let sky_queued = ctx.grafx.queue_texture_asset("sky_day")
+            .and_then(|day| Ok(vec![day, ctx.grafx.queue_texture_asset("sky_night")?]));
+
+        match sky_queued {
+            Ok(sky) => self.sky = sky,
+            Err(err) => println!("sky not loaded: {}", err)
+        }
+
+        // The map is read here and its tilesets stream in with the sky, tilesets that fail to load are drawn as the missing texture
+        if let Some(entry) = ctx.grafx.assets().resolve(LEVEL_MAP)
+            && entry.kind == AssetKind::Map {
+            let path = entry.path.clone();
+            match TileMap::load(&path) {
+                Ok(map) => {
+                    map.queue_tilesets(ctx.grafx);
+                    self.map = Some(map);
+                }
+                Err(err) => println!("Failed to load level: {}", err)
+            }
+        }
+        ctx.grafx.set_loading_screen(true);
+    }
+
+    fn exit(&mut self, ctx: &mut SceneContext) {
+        ctx.grafx.set_loading_screen(false);
+    }
+
+    fn update(&mut self, ctx: &mut SceneContext, _dt: f32) {
+        if !ctx.grafx.is_loading() {
+            let sky: Vec<Handle<Texture>> = std::mem::take(&mut self.sky);
+            ctx.transition(SceneChange::Replace(Box::new(WorldScene::new(sky, self.map.take()))));
+        }
+    }
+}
@@ -0,0 +1,10 @@
+mod title;
+mod loading;
+mod world;
+mod pause;
+mod settings;
+pub use self::title::TitleScreen;
+pub use self::loading::LoadingScene;
+pub use self::world::WorldScene;
+pub use self::pause::PauseMenu;
+pub use self::settings::SettingsMenu;
\ No newline at end of file
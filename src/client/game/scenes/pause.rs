@@ -0,0 +1,56 @@
+// This file contains the pause menu, an overlay that stops the world until it is closed
+use crate::client::grafx::{TextAlign, TextStyle};
+use crate::client::game::scene::{Scene, SceneChange, SceneContext};
+use super::settings::SettingsMenu;
+use super::title::TitleScreen;
+
+/// Size of the menu panel in logical pixels
+const MENU_SIZE: [f32; 2] = [280.0, 256.0];
+
+/// Color drawn over the world behind the menu
+const BACKDROP_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+pub struct PauseMenu;
+
+impl Scene for PauseMenu {
+    fn name(&self) -> &str {
+        "pause"
+    }
+
+    fn input(&mut self, ctx: &mut SceneContext) {
+        if ctx.input.is_pressed("pause") {
+            ctx.change(SceneChange::Pop);
+        }
+    }
+
+    fn render(&mut self, ctx: &mut SceneContext) {
+        let viewport: [f32; 2] = ctx.grafx.camera().viewport();
+        let panel: [f32; 4] = [(viewport[0] - MENU_SIZE[0]) / 2.0, (viewport[1] - MENU_SIZE[1]) / 2.0, MENU_SIZE[0], MENU_SIZE[1]];
+        let button_width: f32 = panel[2] - 32.0;
+        let mut clicked: Option<&str> = None;
+
+        let mut frame = ctx.ui.begin(ctx.grafx);
+        frame.rect([0.0, 0.0, viewport[0], viewport[1]], BACKDROP_COLOR);
+        frame.panel(panel);
+        frame.label_styled([panel[0] + panel[2] / 2.0, panel[1] + 16.0], "Paused", &TextStyle { align: TextAlign::Center, ..TextStyle::default() });
+
+        let buttons: [(&str, &str); 4] = [("pause_resume", "Resume"), ("pause_settings", "Settings"), ("pause_title", "Quit to title"), ("pause_quit", "Quit game")];
+        for (index, (id, text)) in buttons.into_iter().enumerate() {
+            if frame.button(id, [panel[0] + 16.0, panel[1] + 56.0 + index as f32 * 44.0, button_width, 36.0], text) {
+                clicked = Some(id);
+            }
+        }
+
+        match clicked {
+            Some("pause_resume") => ctx.change(SceneChange::Pop),
+            Some("pause_settings") => ctx.change(SceneChange::Push(Box::new(SettingsMenu::new()))),
+            Some("pause_title") => ctx.transition(SceneChange::Reset(Box::new(TitleScreen))),
+            Some("pause_quit") => ctx.change(SceneChange::Quit),
+            _ => {}
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,85 @@
+// This file contains the settings menu, an overlay that can be opened from the title screen or the pause menu
+use crate::client::grafx::{TextAlign, TextStyle};
+use crate::client::game::scene::{Scene, SceneChange, SceneContext};
+
+/// Size of the menu panel in logical pixels
+const MENU_SIZE: [f32; 2] = [420.0, 400.0];
+
+/// Height of one row in the bindings list
+const ROW_HEIGHT: f32 = 24.0;
+
+pub struct SettingsMenu {
+    tab: usize,
+}
+
+impl SettingsMenu {
+    pub fn new() -> Self {
+        Self { tab: 0 }
+    }
+}
+
+impl Default for SettingsMenu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene for SettingsMenu {
+    fn name(&self) -> &str {
+        "settings"
+    }
+
+    fn input(&mut self, ctx: &mut SceneContext) {
+        if ctx.input.is_pressed("pause") {
+            ctx.change(SceneChange::Pop);
+        }
+    }
+
+    fn render(&mut self, ctx: &mut SceneContext) {
+        let viewport: [f32; 2] = ctx.grafx.camera().viewport();
+        let panel: [f32; 4] = [(viewport[0] - MENU_SIZE[0]) / 2.0, (viewport[1] - MENU_SIZE[1]) / 2.0, MENU_SIZE[0], MENU_SIZE[1]];
+        let inner_width: f32 = panel[2] - 32.0;
+        let hot_reloading: bool = ctx.grafx.is_hot_reloading();
+        let mut toggle_hot_reload: bool = false;
+
+        let mut frame = ctx.ui.begin(ctx.grafx);
+        frame.panel(panel);
+        frame.label_styled([panel[0] + panel[2] / 2.0, panel[1] + 16.0], "Settings", &TextStyle { align: TextAlign::Center, ..TextStyle::default() });
+        frame.tabs("settings_tabs", [panel[0] + 16.0, panel[1] + 48.0, inner_width, 32.0], &["Controls", "Graphics"], &mut self.tab);
+
+        let area: [f32; 4] = [panel[0] + 16.0, panel[1] + 92.0, inner_width, panel[3] - 152.0];
+        match self.tab {
+            0 => {
+                // Every action and what it is bound to, bindings are changed in the bindings file
+                let bindings = ctx.input.bindings();
+                let actions: Vec<&str> = bindings.actions();
+                let origin: [f32; 2] = frame.begin_scroll("settings_bindings", area, actions.len() as f32 * ROW_HEIGHT);
+                for (row, action) in actions.iter().enumerate() {
+                    let y: f32 = origin[1] + row as f32 * ROW_HEIGHT;
+                    let sources: Vec<String> = bindings.sources(action).iter().map(|source| source.name()).collect();
+                    frame.label([origin[0] + 4.0, y], action);
+                    frame.label([origin[0] + area[2] / 2.0, y], &sources.join(", "));
+                }
+                frame.end_scroll();
+            }
+            _ => {
+                if frame.button("settings_hot_reload", [area[0], area[1], area[2], 36.0], if hot_reloading { "Hot reload: on" } else { "Hot reload: off" }) {
+                    toggle_hot_reload = true;
+                }
+            }
+        }
+
+        let back: bool = frame.button("settings_back", [panel[0] + 16.0, panel[1] + panel[3] - 52.0, inner_width, 36.0], "Back");
+
+        if back {
+            ctx.change(SceneChange::Pop);
+        }
+        if toggle_hot_reload {
+            ctx.grafx.set_hot_reload(!hot_reloading);
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
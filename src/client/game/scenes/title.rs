@@ -0,0 +1,46 @@
+// This file contains the title screen, the first scene the game opens on
+use crate::client::grafx::{TextAlign, TextStyle};
+use crate::client::game::scene::{Scene, SceneChange, SceneContext};
+use super::loading::LoadingScene;
+use super::settings::SettingsMenu;
+
+/// Size of the menu panel in logical pixels
+const MENU_SIZE: [f32; 2] = [280.0, 216.0];
+
+pub struct TitleScreen;
+
+impl Scene for TitleScreen {
+    fn name(&self) -> &str {
+        "title"
+    }
+
+    fn input(&mut self, ctx: &mut SceneContext) {
+        if ctx.input.is_pressed("confirm") {
+            ctx.transition(SceneChange::Replace(Box::new(LoadingScene::new())));
+        }
+    }
+
+    fn render(&mut self, ctx: &mut SceneContext) {
+        let viewport: [f32; 2] = ctx.grafx.camera().viewport();
+        let panel: [f32; 4] = [(viewport[0] - MENU_SIZE[0]) / 2.0, (viewport[1] - MENU_SIZE[1]) / 2.0, MENU_SIZE[0], MENU_SIZE[1]];
+        let button_width: f32 = panel[2] - 32.0;
+        let mut clicked: Option<&str> = None;
+
+        let mut frame = ctx.ui.begin(ctx.grafx);
+        frame.panel(panel);
+        frame.label_styled([panel[0] + panel[2] / 2.0, panel[1] + 16.0], "Poprustica", &TextStyle { align: TextAlign::Center, scale: 2.0, ..TextStyle::default() });
+
+        for (index, (id, text)) in [("title_play", "Play"), ("title_settings", "Settings"), ("title_quit", "Quit")].into_iter().enumerate() {
+            if frame.button(id, [panel[0] + 16.0, panel[1] + 72.0 + index as f32 * 44.0, button_width, 36.0], text) {
+                clicked = Some(id);
+            }
+        }
+
+        match clicked {
+            Some("title_play") => ctx.transition(SceneChange::Replace(Box::new(LoadingScene::new()))),
+            Some("title_settings") => ctx.change(SceneChange::Push(Box::new(SettingsMenu::new()))),
+            Some("title_quit") => ctx.change(SceneChange::Quit),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// This file contains the world scene, where the game is actually played
+use crate::client::assets::{Handle, Texture};
+use crate::client::ecs::{self, Entity, PathFollower, SpriteRef};
+use crate::client::grafx::{BackgroundLayer, WHITE_TEXTURE_KEY};
+use crate::client::game::events::PlayerAction;
+use crate::client::game::game::capture;
+use crate::client::game::scene::{Scene, SceneChange, SceneContext};
+use crate::client::map::{find_path_towards, Diagonals, TileMap};
+use crate::client::physics::Platformer;
+use super::pause::PauseMenu;
+
+/// How fast the move actions pan the camera, in logical pixels per second
+const CAMERA_PAN_SPEED: f32 = 400.0;
+
+/// Name of the map object the player starts at
+const PLAYER: &str = "player";
+
+/// Size of the player when its map object doesn't give one
+const PLAYER_SIZE: [f32; 2] = [24.0, 40.0];
+
+/// How much one zoom action zooms the camera in or out
+const ZOOM_STEP: f32 = 1.1;
+
+pub struct WorldScene {
+    // Held so the sky textures stay loaded
+    sky: Vec<Handle<Texture>>,
+    // Level loaded by the loading scene, moved into the context on enter
+    map: Option<TileMap>,
+    // Selected tab of the demo panel
+    tab: usize,
+}
+
+impl WorldScene {
+    /// The world is empty without a map
+    pub fn new(sky: Vec<Handle<Texture>>, map: Option<TileMap>) -> Self {
+        Self { sky, map, tab: 0 }
+    }
+}
+
+impl Scene for WorldScene {
+    fn name(&self) -> &str {
+        "world"
+    }
+
+    fn enter(&mut self, ctx: &mut SceneContext) {
+        // The sky fades from day to night, so the night layer sits on top of the day layer
+        if let [day, night] = self.sky.as_slice() {
+            let mut day = BackgroundLayer::new(day.id(), 0.0);
+            let mut night = BackgroundLayer::new(night.id(), 0.0);
+            day.tinted = false;
+            night.tinted = false;
+            ctx.grafx.set_background_layers(vec![day, night]);
+        }
+
+        if let Some(map) = self.map.take() {
+            map.spawn_objects(ctx.world);
+            if let Some(player) = ctx.world.find(PLAYER) {
+                setup_player(ctx, &map, player);
+            }
+            ctx.grafx.camera_mut().set_bounds([0.0, 0.0], map.pixel_size());
+            *ctx.map = Some(map);
+        }
+    }
+
+    fn exit(&mut self, ctx: &mut SceneContext) {
+        ctx.grafx.set_background_layers(Vec::new());
+        ctx.grafx.camera_mut().clear_bounds();
+        ctx.grafx.camera_mut().set_target(None);
+        ctx.world.clear();
+        // Nothing is in the old level's triggers any more, and nothing should hear it leave them
+        ctx.events.reset();
+        *ctx.map = None;
+    }
+
+    fn input(&mut self, ctx: &mut SceneContext) {
+        if ctx.input.is_pressed("pause") {
+            ctx.change(SceneChange::Push(Box::new(PauseMenu)));
+            return;
+        }
+
+        let camera = ctx.grafx.camera_mut();
+        if ctx.input.is_pressed("zoom_in") {
+            camera.set_zoom(camera.zoom() * ZOOM_STEP);
+        }
+        if ctx.input.is_pressed("zoom_out") {
+            camera.set_zoom(camera.zoom() / ZOOM_STEP);
+        }
+
+        // Whatever the player can't do here themselves is sent on for game code to handle
+        if ctx.input.is_pressed("interact") {
+            let at: [f32; 2] = ctx.grafx.screen_to_world(ctx.input.cursor());
+            if !walk_player_to(ctx, at) {
+                let entity: Option<Entity> = ecs::systems::pick(ctx.world, at);
+                ctx.events.actions.push(PlayerAction::Interact { at, entity });
+            }
+        }
+        if ctx.input.is_pressed("open_inventory") {
+            ctx.events.actions.push(PlayerAction::OpenInventory);
+        }
+        if ctx.input.is_pressed("context_menu") {
+            let at: [f32; 2] = ctx.grafx.screen_to_world(ctx.input.cursor());
+            let entity: Option<Entity> = ecs::systems::pick(ctx.world, at);
+            ctx.events.actions.push(PlayerAction::ContextMenu { at, entity });
+        }
+    }
+
+    fn update(&mut self, ctx: &mut SceneContext, dt: f32) {
+        // The camera follows the player, or is panned around the map without one
+        let player = ctx.world.find(PLAYER).and_then(|player| ctx.world.transforms.get(player));
+        if let Some(transform) = player {
+            ctx.grafx.camera_mut().set_target(Some(transform.position));
+            return;
+        }
+
+        // Pan the camera, at the same on screen speed whatever the zoom
+        let camera = ctx.grafx.camera_mut();
+        let pan: [f32; 2] = [ctx.input.axis("move_left", "move_right"), ctx.input.axis("move_up", "move_down")];
+        camera.position[0] += pan[0] * CAMERA_PAN_SPEED * dt / camera.zoom();
+        camera.position[1] += pan[1] * CAMERA_PAN_SPEED * dt / camera.zoom();
+    }
+
+    // Apply the time of day, and declare a small panel to control the clock and capture frames
+    fn render(&mut self, ctx: &mut SceneContext) {
+        ctx.grafx.set_world_tint(ctx.clock.tint());
+        if let [_day, night] = self.sky.as_slice()
+            && let Some(night) = ctx.grafx.background_layer_for_mut(night.id()) {
+            night.opacity = 1.0 - ctx.clock.daylight();
+        }
+
+        let recording: bool = ctx.grafx.is_recording();
+        let mut capture_action: Option<&str> = None;
+
+        let mut frame = ctx.ui.begin(ctx.grafx);
+        let panel: [f32; 4] = [16.0, 16.0, 240.0, 132.0];
+        frame.panel(panel);
+        frame.tabs("tabs", [panel[0] + 8.0, panel[1] + 8.0, panel[2] - 16.0, 32.0], &["Clock", "Capture"], &mut self.tab);
+
+        let row: f32 = panel[1] + 52.0;
+        let button_width: f32 = panel[2] - 16.0;
+
+        match self.tab {
+            0 => {
+                let hours: f32 = ctx.clock.hours();
+                frame.label([panel[0] + 12.0, row], &format!("Time {:02}:{:02}  Tick {}", hours as u32, (hours.fract() * 60.0) as u32, ctx.ticks));
+                if frame.button("clock", [panel[0] + 8.0, row + 28.0, button_width, 32.0], if ctx.clock.is_paused() { "Resume" } else { "Pause" }) {
+                    if ctx.clock.is_paused() {
+                        ctx.clock.resume();
+                    } else {
+                        ctx.clock.pause();
+                    }
+                }
+            }
+            _ => {
+                if frame.button("screenshot", [panel[0] + 8.0, row, button_width, 32.0], "Screenshot") {
+                    capture_action = Some("screenshot");
+                }
+                if frame.button("record", [panel[0] + 8.0, row + 38.0, button_width, 32.0], if recording { "Stop recording" } else { "Record GIF" }) {
+                    capture_action = Some("record");
+                }
+            }
+        }
+
+        if let Some(action) = capture_action {
+            capture(ctx.grafx, action);
+        }
+    }
+
+    fn runs_simulation(&self) -> bool {
+        true
+    }
+}
+
+// Give the map's player object a body and a look
+// Side scrolling maps set their `movement` property to `platformer`, on any other map the player walks tile by tile where clicked
+fn setup_player(ctx: &mut SceneContext, map: &TileMap, player: Entity) {
+    // Map objects get a trigger collider, the player's is solid and its size is the player's size
+    let size: [f32; 2] = match ctx.world.colliders.get_mut(player) {
+        Some(collider) => {
+            collider.trigger = false;
+            collider.shape.size()
+        }
+        None => PLAYER_SIZE
+    };
+
+    if !ctx.world.sprites.contains(player) {
+        ctx.world.insert(player, SpriteRef::new(WHITE_TEXTURE_KEY, size));
+    }
+
+    if map.properties.get("movement").is_some_and(|movement| movement == "platformer") {
+        ctx.world.insert(player, Platformer::new(size));
+    } else if let Some(transform) = ctx.world.transforms.get_mut(player)
+        && let Some(tile) = map.tile_at(transform.position) {
+        // Walkers always stand in the middle of a tile
+        transform.snap(map.tile_center(tile));
+        ctx.world.insert(player, PathFollower::new(tile));
+    }
+
+    if let Some(camera_target) = ctx.world.transforms.get(player).map(|transform| transform.position) {
+        ctx.grafx.camera_mut().snap_to(camera_target);
+    }
+}
+
+// Path the player to the tile under a world position, or as near to it as they can get
+// Returns false if the player doesn't walk by clicking
+fn walk_player_to(ctx: &mut SceneContext, position: [f32; 2]) -> bool {
+    let (map, player) = match (ctx.map.as_ref(), ctx.world.find(PLAYER)) {
+        (Some(map), Some(player)) => (map, player),
+        _ => return false
+    };
+    let follower = match ctx.world.paths.get_mut(player) {
+        Some(follower) => follower,
+        None => return false
+    };
+
+    if let Some(goal) = map.tile_at(position) {
+        follower.walk(find_path_towards(&map.walk_grid, follower.tile, goal, Diagonals::NoCornerCutting));
+    }
+    true
+}
@@ -0,0 +1,185 @@
+// This file contains the fixed timestep, it turns however long a frame took into a whole number of equal simulation steps
+use std::time::Duration;
+use anyhow::Error;
+
+pub struct FixedTimestep {
+    step: Duration,
+    /// Most steps run in one frame, time past this is dropped so a long stall doesn't snowball
+    max_steps: u32,
+    // Time that has passed but not been simulated yet, always less than one step after `advance`
+    accumulator: Duration,
+    steps: u64,
+}
+
+impl FixedTimestep {
+    /// Create a timestep that runs one step per `step` of time, a zero step would never catch up so it is an error
+    pub fn new(step: Duration, max_steps: u32) -> Result<Self, Error> {
+        if step.is_zero() {
+            return Err(anyhow::anyhow!("Step length must be above zero"));
+        }
+        Ok(Self {
+            step,
+            max_steps: max_steps.max(1),
+            accumulator: Duration::ZERO,
+            steps: 0,
+        })
+    }
+
+    /// Create a timestep that runs `rate` steps per second, the rate has to be above zero and finite
+    pub fn from_rate(rate: f64, max_steps: u32) -> Result<Self, Error> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(anyhow::anyhow!("Step rate must be above zero, not {}", rate));
+        }
+        let step: Duration = Duration::try_from_secs_f64(1.0 / rate)
+            .map_err(|err| anyhow::anyhow!("Step rate {} is too slow: {}", rate, err))?;
+        Self::new(step, max_steps)
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Length of one step in seconds, the `dt` every step is simulated with
+    pub fn dt(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Add the time a frame took and get how many steps to run for it
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut count: u32 = 0;
+        while self.accumulator >= self.step && count < self.max_steps {
+            self.accumulator -= self.step;
+            count += 1;
+        }
+
+        // Too far behind to catch up, drop the backlog rather than running ever more steps next frame
+        if count == self.max_steps && self.accumulator >= self.step {
+            println!("Simulation fell behind, skipped {:.0} ms", self.accumulator.as_secs_f64() * 1000.0);
+            self.accumulator = Duration::ZERO;
+        }
+
+        self.steps += count as u64;
+        count
+    }
+
+    /// How far the leftover time is into the next step in 0..1, used to blend the last two simulated states when drawing
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.step.as_secs_f64()) as f32
+    }
+
+    /// Total steps run so far
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+/// A position the simulation moves, remembering where it was last step so drawing can blend between the two
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interpolated {
+    pub previous: [f32; 2],
+    pub current: [f32; 2],
+}
+
+impl Interpolated {
+    pub fn new(position: [f32; 2]) -> Self {
+        Self { previous: position, current: position }
+    }
+
+    /// Start a simulation step, where it is now becomes where it was
+    pub fn begin_step(&mut self) {
+        self.previous = self.current;
+    }
+
+    /// Jump to a position without blending from the old one, e.g. for teleports
+    pub fn snap(&mut self, position: [f32; 2]) {
+        self.previous = position;
+        self.current = position;
+    }
+
+    /// Get the position to draw, `alpha` is from `FixedTimestep::alpha`
+    pub fn lerp(&self, alpha: f32) -> [f32; 2] {
+        [
+            self.previous[0] + (self.current[0] - self.previous[0]) * alpha,
+            self.previous[1] + (self.current[1] - self.previous[1]) * alpha,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    #[test]
+    fn runs_a_step_for_each_step_of_time() {
+        let mut timestep = FixedTimestep::new(ms(10), 5).unwrap();
+
+        assert_eq!(timestep.advance(ms(4)), 0);
+        assert_eq!(timestep.advance(ms(4)), 0);
+        // The leftover time carries over between frames
+        assert_eq!(timestep.advance(ms(4)), 1);
+        assert_eq!(timestep.advance(ms(28)), 3);
+        assert_eq!(timestep.steps(), 4);
+        assert_eq!(timestep.dt(), 0.01);
+    }
+
+    #[test]
+    fn drops_time_past_the_catch_up_cap() {
+        let mut timestep = FixedTimestep::new(ms(10), 3).unwrap();
+
+        assert_eq!(timestep.advance(ms(1000)), 3);
+        // The backlog is gone, not run over the next frames
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(ms(5)), 0);
+
+        // Exactly at the cap with time to spare for a partial step keeps that time
+        let mut timestep = FixedTimestep::new(ms(10), 3).unwrap();
+        assert_eq!(timestep.advance(ms(35)), 3);
+        assert!((timestep.alpha() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn alpha_is_how_far_into_the_next_step() {
+        let mut timestep = FixedTimestep::new(ms(20), 5).unwrap();
+        timestep.advance(ms(5));
+        assert!((timestep.alpha() - 0.25).abs() < 0.0001);
+        timestep.advance(ms(20));
+        assert!((timestep.alpha() - 0.25).abs() < 0.0001);
+        timestep.advance(ms(10));
+        assert!((timestep.alpha() - 0.75).abs() < 0.0001);
+    }
+
+    #[test]
+    fn steps_must_be_longer_than_zero() {
+        assert!(FixedTimestep::new(Duration::ZERO, 1).is_err());
+        assert!(FixedTimestep::new(Duration::from_nanos(1), 1).is_ok());
+        // A rate so high its step rounds down to nothing is rejected too
+        assert!(FixedTimestep::from_rate(1e12, 5).is_err());
+    }
+
+    #[test]
+    fn rates_must_be_above_zero() {
+        let timestep = FixedTimestep::from_rate(50.0, 5).unwrap();
+        assert_eq!(timestep.step(), ms(20));
+
+        for rate in [0.0, -60.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(FixedTimestep::from_rate(rate, 5).is_err(), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn interpolates_between_steps() {
+        let mut position = Interpolated::new([0.0, 0.0]);
+        position.begin_step();
+        position.current = [10.0, -4.0];
+        assert_eq!(position.lerp(0.5), [5.0, -2.0]);
+
+        position.snap([3.0, 3.0]);
+        assert_eq!(position.lerp(0.25), [3.0, 3.0]);
+    }
+}
@@ -0,0 +1,300 @@
+// This file contains frame based sprite animation, it only deals with atlas frame names so it runs without a GPU
+use std::{
+    collections::HashMap,
+    ops::Range
+};
+
+/// Shortest a frame is allowed to last, stops zero length frames from spinning forever
+const MIN_FRAME_DURATION: f32 = 0.001;
+
+/// What a clip does once it reaches its last frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlaybackMode {
+    /// Jump back to the first frame
+    Loop,
+    /// Play backwards to the first frame, then forwards again
+    PingPong,
+    /// Stop on the last frame
+    Once,
+}
+
+/// Fired when an animator enters a frame that has an event attached (footsteps, hits...)
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationEvent<T> {
+    /// What the animator belongs to, e.g. the entity whose sprite it drives
+    pub target: T,
+    pub clip: String,
+    pub frame_index: usize,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    frames: Vec<String>,
+    durations: Vec<f32>,
+    mode: PlaybackMode,
+    events: Vec<(usize, String)>,
+}
+
+impl AnimationClip {
+    /// Create a clip where every frame lasts `frame_duration` seconds
+    pub fn new(name: &str, frames: Vec<String>, frame_duration: f32, mode: PlaybackMode) -> Self {
+        let durations: Vec<f32> = vec![frame_duration.max(MIN_FRAME_DURATION); frames.len()];
+        Self {
+            name: String::from(name),
+            frames,
+            durations,
+            mode,
+            events: Vec::new(),
+        }
+    }
+
+    /// Create a clip from a run of grid atlas frames, which are named by index
+    pub fn from_range(name: &str, frames: Range<u32>, frame_duration: f32, mode: PlaybackMode) -> Self {
+        Self::new(name, frames.map(|index| index.to_string()).collect(), frame_duration, mode)
+    }
+
+    /// Give every frame its own duration in seconds, extra durations are ignored and missing ones keep their old value
+    pub fn with_durations(mut self, durations: &[f32]) -> Self {
+        for (current, duration) in self.durations.iter_mut().zip(durations) {
+            *current = duration.max(MIN_FRAME_DURATION);
+        }
+        self
+    }
+
+    /// Fire an event whenever the animator enters the given frame
+    pub fn with_event(mut self, frame_index: usize, name: &str) -> Self {
+        self.events.push((frame_index, String::from(name)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Get the frame after `frame_index` given the play direction, None once a `Once` clip is done
+    fn next_frame(&self, frame_index: usize, direction: &mut i8) -> Option<usize> {
+        let count = self.frames.len();
+
+        match self.mode {
+            PlaybackMode::Loop => Some((frame_index + 1) % count),
+            PlaybackMode::Once => (frame_index + 1 < count).then_some(frame_index + 1),
+            PlaybackMode::PingPong => {
+                if count == 1 {
+                    return Some(0);
+                }
+                let next = frame_index as isize + *direction as isize;
+                if next < 0 || next >= count as isize {
+                    *direction = -*direction;
+                    return Some((frame_index as isize + *direction as isize) as usize);
+                }
+                Some(next as usize)
+            }
+        }
+    }
+}
+
+/// Plays one of a set of clips, advanced with game time by whoever owns it
+#[derive(Clone, Debug)]
+pub struct Animator {
+    clips: HashMap<String, AnimationClip>,
+    current: Option<String>,
+    frame_index: usize,
+    elapsed: f32,
+    direction: i8,
+    finished: bool,
+    entered: bool,
+    pub speed: f32,
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self {
+            clips: HashMap::new(),
+            current: None,
+            frame_index: 0,
+            elapsed: 0.0,
+            direction: 1,
+            finished: false,
+            entered: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        self.clips.insert(clip.name.clone(), clip);
+    }
+
+    /// Start playing a clip from its first frame, does nothing if it is already playing
+    pub fn play(&mut self, name: &str) -> Result<(), anyhow::Error> {
+        if self.current.as_deref() == Some(name) {
+            return Ok(());
+        }
+        self.restart(name)
+    }
+
+    /// Start playing a clip from its first frame even if it is already playing
+    pub fn restart(&mut self, name: &str) -> Result<(), anyhow::Error> {
+        if !self.clips.contains_key(name) {
+            return Err(anyhow::anyhow!("Animation clip '{}' not found", name));
+        }
+
+        self.current = Some(String::from(name));
+        self.frame_index = 0;
+        self.elapsed = 0.0;
+        self.direction = 1;
+        self.finished = false;
+        // The first frame's events fire on the next update
+        self.entered = false;
+        Ok(())
+    }
+
+    /// Advance the animation by `dt` seconds, returns any events for frames entered along the way
+    /// The events carry `target` so whoever reads them knows which animator fired them
+    pub fn update<T: Copy>(&mut self, dt: f32, target: T) -> Vec<AnimationEvent<T>> {
+        let mut events: Vec<AnimationEvent<T>> = Vec::new();
+
+        let clip = match self.current.as_ref().and_then(|name| self.clips.get(name)) {
+            Some(clip) if !clip.is_empty() => clip,
+            _ => return events,
+        };
+
+        if !self.entered {
+            self.entered = true;
+            push_events(clip, self.frame_index, target, &mut events);
+        }
+
+        if self.finished {
+            return events;
+        }
+
+        self.elapsed += dt * self.speed;
+
+        // Large steps can skip several frames, every skipped frame still fires its events
+        while self.elapsed >= clip.durations[self.frame_index] {
+            self.elapsed -= clip.durations[self.frame_index];
+
+            match clip.next_frame(self.frame_index, &mut self.direction) {
+                Some(frame_index) => {
+                    self.frame_index = frame_index;
+                    push_events(clip, self.frame_index, target, &mut events);
+                }
+                None => {
+                    self.finished = true;
+                    self.elapsed = 0.0;
+                    break;
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Get the atlas frame name that should currently be shown
+    pub fn current_frame(&self) -> Option<&str> {
+        self.current.as_ref()
+            .and_then(|name| self.clips.get(name))
+            .and_then(|clip| clip.frames.get(self.frame_index))
+            .map(|frame| frame.as_str())
+    }
+
+    pub fn current_clip(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// True once a `Once` clip has reached its last frame
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+fn push_events<T: Copy>(clip: &AnimationClip, frame_index: usize, target: T, events: &mut Vec<AnimationEvent<T>>) {
+    for (event_frame, name) in &clip.events {
+        if *event_frame == frame_index {
+            events.push(AnimationEvent {
+                target,
+                clip: clip.name.clone(),
+                frame_index,
+                name: name.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animator(mode: PlaybackMode) -> Animator {
+        let mut animator = Animator::new();
+        animator.add_clip(AnimationClip::from_range("walk", 0..3, 0.1, mode));
+        animator.play("walk").unwrap();
+        animator
+    }
+
+    /// Step the animator one frame at a time and record which frame it lands on
+    fn frames(animator: &mut Animator, steps: usize) -> Vec<String> {
+        (0..steps).map(|_| {
+            animator.update(0.1, ());
+            String::from(animator.current_frame().unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_first_frame() {
+        let mut animator = animator(PlaybackMode::Loop);
+        assert_eq!(animator.current_frame(), Some("0"));
+        assert_eq!(frames(&mut animator, 4), ["1", "2", "0", "1"]);
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_ends() {
+        let mut animator = animator(PlaybackMode::PingPong);
+        assert_eq!(frames(&mut animator, 6), ["1", "2", "1", "0", "1", "2"]);
+    }
+
+    #[test]
+    fn once_stops_on_last_frame() {
+        let mut animator = animator(PlaybackMode::Once);
+        assert_eq!(frames(&mut animator, 4), ["1", "2", "2", "2"]);
+        assert!(animator.is_finished());
+    }
+
+    #[test]
+    fn partial_steps_accumulate() {
+        let mut animator = animator(PlaybackMode::Loop);
+        animator.update(0.06, ());
+        assert_eq!(animator.current_frame(), Some("0"));
+        animator.update(0.06, ());
+        assert_eq!(animator.current_frame(), Some("1"));
+    }
+
+    #[test]
+    fn events_fire_on_entered_frames() {
+        let mut animator = Animator::new();
+        animator.add_clip(AnimationClip::from_range("walk", 0..4, 0.1, PlaybackMode::Loop)
+            .with_event(0, "footstep")
+            .with_event(2, "footstep"));
+        animator.play("walk").unwrap();
+
+        let names = |events: Vec<AnimationEvent<u32>>| events.into_iter().map(|event| (event.target, event.frame_index, event.name)).collect::<Vec<_>>();
+        assert_eq!(names(animator.update(0.0, 7)), [(7, 0, String::from("footstep"))]);
+        // A big step skips over frame 2 but still fires its event
+        assert_eq!(names(animator.update(0.35, 7)), [(7, 2, String::from("footstep"))]);
+    }
+}
@@ -0,0 +1,234 @@
+// This file contains texture atlas support, one texture split into named frames (sprite sheets)
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Error, Result};
+use serde_json;
+
+/// How an atlas image is split into frames
+pub enum AtlasLayout {
+    /// Evenly sized frames read left to right, top to bottom, named "0", "1", "2"...
+    Grid { frame_width: u32, frame_height: u32 },
+    /// Frame rects read from a TexturePacker style JSON file (hash or array format)
+    Json(PathBuf),
+}
+
+impl AtlasLayout {
+    /// Parse a layout from an asset manifest, either `grid <width>x<height>` or the path to a JSON file
+    /// The JSON path is relative to the folder the atlas image is in
+    pub fn parse(options: &str, path_to_img: &Path) -> Result<Self, Error> {
+        let mut parts = options.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("grid"), Some(size)) => {
+                let (frame_width, frame_height) = size.split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid atlas grid size '{}'", size))?;
+                Ok(AtlasLayout::Grid { frame_width, frame_height })
+            }
+            (Some(path_to_json), None) => {
+                let dir: &Path = path_to_img.parent().unwrap_or(Path::new(""));
+                Ok(AtlasLayout::Json(dir.join(path_to_json)))
+            }
+            _ => Err(anyhow::anyhow!("Invalid atlas layout '{}'", options))
+        }
+    }
+}
+
+/// A frame's rect within the atlas image, in pixels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct TextureAtlas {
+    pub bind_group_key: String,
+    width: u32,
+    height: u32,
+    frames: HashMap<String, AtlasFrame>,
+}
+
+impl TextureAtlas {
+    /// Build an atlas for a texture that has already been loaded
+    pub fn new(bind_group_key: String, width: u32, height: u32, layout: &AtlasLayout) -> Result<Self, Error> {
+        let frames: HashMap<String, AtlasFrame> = match layout {
+            AtlasLayout::Grid { frame_width, frame_height } => grid_frames(width, height, *frame_width, *frame_height)?,
+            AtlasLayout::Json(path_to_json) => {
+                let json = std::fs::read_to_string(path_to_json)
+                    .map_err(|err| anyhow::anyhow!("Failed to read atlas '{}': {}", path_to_json.display(), err))?;
+                json_frames(&json)?
+            }
+        };
+        Self::from_frames(bind_group_key, width, height, frames)
+    }
+
+    // Check every frame is inside the image, frames outside it would sample garbage so the whole atlas is rejected
+    fn from_frames(bind_group_key: String, width: u32, height: u32, frames: HashMap<String, AtlasFrame>) -> Result<Self, Error> {
+        for (name, frame) in &frames {
+            let right: Option<u32> = frame.x.checked_add(frame.width);
+            let bottom: Option<u32> = frame.y.checked_add(frame.height);
+            if right.is_none_or(|right| right > width) || bottom.is_none_or(|bottom| bottom > height) {
+                return Err(anyhow::anyhow!("Atlas frame '{}' is outside the {}x{} image", name, width, height));
+            }
+        }
+
+        Ok(Self { bind_group_key, width, height, frames })
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&AtlasFrame> {
+        self.frames.get(name)
+    }
+
+    /// Get the offset (xy) and size (zw) of a frame in texture coordinates
+    pub fn uv_rect(&self, name: &str) -> Option<[f32; 4]> {
+        self.frames.get(name).map(|frame| [
+            frame.x as f32 / self.width as f32,
+            frame.y as f32 / self.height as f32,
+            frame.width as f32 / self.width as f32,
+            frame.height as f32 / self.height as f32,
+        ])
+    }
+}
+
+/// Split an image into evenly sized frames, any leftover pixels on the right or bottom are ignored
+fn grid_frames(width: u32, height: u32, frame_width: u32, frame_height: u32) -> Result<HashMap<String, AtlasFrame>, Error> {
+    if frame_width == 0 || frame_height == 0 {
+        return Err(anyhow::anyhow!("Atlas grid frames must have a non-zero size"));
+    }
+
+    let columns = width / frame_width;
+    let rows = height / frame_height;
+    let mut frames: HashMap<String, AtlasFrame> = HashMap::new();
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let index = row * columns + column;
+            frames.insert(index.to_string(), AtlasFrame {
+                x: column * frame_width,
+                y: row * frame_height,
+                width: frame_width,
+                height: frame_height,
+            });
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Parse frames from either `{"frames": {"name": {"frame": {...}}}}` or `{"frames": [{"filename": "name", "frame": {...}}]}`
+fn json_frames(json: &str) -> Result<HashMap<String, AtlasFrame>, Error> {
+    let root: serde_json::Value = serde_json::from_str(json)?;
+    let mut frames: HashMap<String, AtlasFrame> = HashMap::new();
+
+    match root.get("frames") {
+        Some(serde_json::Value::Object(entries)) => {
+            for (name, entry) in entries {
+                frames.insert(name.clone(), json_rect(name, entry)?);
+            }
+        }
+        Some(serde_json::Value::Array(entries)) => {
+            for entry in entries {
+                let name = entry.get("filename").and_then(|name| name.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Atlas frame is missing a 'filename'"))?;
+                frames.insert(String::from(name), json_rect(name, entry)?);
+            }
+        }
+        _ => return Err(anyhow::anyhow!("Atlas JSON has no 'frames'")),
+    }
+
+    Ok(frames)
+}
+
+fn json_rect(name: &str, entry: &serde_json::Value) -> Result<AtlasFrame, Error> {
+    let rect = entry.get("frame").unwrap_or(entry);
+    let field = |key: &str| -> Result<u32, Error> {
+        let value: u64 = rect.get(key).and_then(|value| value.as_u64())
+            .ok_or_else(|| anyhow::anyhow!("Atlas frame '{}' is missing '{}'", name, key))?;
+        u32::try_from(value).map_err(|_| anyhow::anyhow!("Atlas frame '{}' has too big a '{}'", name, key))
+    };
+
+    Ok(AtlasFrame {
+        x: field("x")?,
+        y: field("y")?,
+        width: field("w")?,
+        height: field("h")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(x: u32, y: u32, width: u32, height: u32) -> AtlasFrame {
+        AtlasFrame { x, y, width, height }
+    }
+
+    /// An atlas of a 64 by 32 image
+    fn sheet(json: &str) -> Result<TextureAtlas, Error> {
+        TextureAtlas::from_frames(String::from("sheet"), 64, 32, json_frames(json)?)
+    }
+
+    #[test]
+    fn parses_layouts() {
+        let image = Path::new("assets/sheets/player.png");
+        assert!(matches!(AtlasLayout::parse("grid 16x8", image), Ok(AtlasLayout::Grid { frame_width: 16, frame_height: 8 })));
+        assert!(AtlasLayout::parse("grid 16", image).is_err());
+        assert!(AtlasLayout::parse("grid axb", image).is_err());
+        assert!(AtlasLayout::parse("", image).is_err());
+    }
+
+    #[test]
+    fn json_layouts_are_found_next_to_the_image() {
+        let layout = AtlasLayout::parse("player.json", Path::new("assets/sheets/player.png"));
+        assert!(matches!(layout, Ok(AtlasLayout::Json(path)) if path == Path::new("assets/sheets/player.json")));
+        let layout = AtlasLayout::parse("frames/player.json", Path::new("player.png"));
+        assert!(matches!(layout, Ok(AtlasLayout::Json(path)) if path == Path::new("frames/player.json")));
+    }
+
+    #[test]
+    fn splits_grids() {
+        // Leftover pixels on the right and bottom are ignored
+        let frames = grid_frames(40, 20, 16, 8).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames.get("0"), Some(&frame(0, 0, 16, 8)));
+        assert_eq!(frames.get("1"), Some(&frame(16, 0, 16, 8)));
+        assert_eq!(frames.get("2"), Some(&frame(0, 8, 16, 8)));
+        assert!(grid_frames(40, 20, 0, 8).is_err());
+    }
+
+    #[test]
+    fn reads_hash_and_array_json() {
+        let hash = json_frames(r#"{"frames": {"idle": {"frame": {"x": 1, "y": 2, "w": 3, "h": 4}}}}"#).unwrap();
+        assert_eq!(hash.get("idle"), Some(&frame(1, 2, 3, 4)));
+
+        let array = json_frames(r#"{"frames": [{"filename": "run", "frame": {"x": 5, "y": 6, "w": 7, "h": 8}}]}"#).unwrap();
+        assert_eq!(array.get("run"), Some(&frame(5, 6, 7, 8)));
+
+        // The rect can also sit straight on the entry
+        let flat = json_frames(r#"{"frames": {"jump": {"x": 0, "y": 0, "w": 2, "h": 2}}}"#).unwrap();
+        assert_eq!(flat.get("jump"), Some(&frame(0, 0, 2, 2)));
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        assert!(json_frames("{}").is_err());
+        assert!(json_frames("not json").is_err());
+        assert!(json_frames(r#"{"frames": [{"frame": {"x": 0, "y": 0, "w": 1, "h": 1}}]}"#).is_err());
+        assert!(json_frames(r#"{"frames": {"idle": {"x": 0, "y": 0, "w": 1}}}"#).is_err());
+        assert!(json_frames(r#"{"frames": {"idle": {"x": -1, "y": 0, "w": 1, "h": 1}}}"#).is_err());
+        // Too big for a u32, instead of wrapping around to a small number
+        assert!(json_frames(r#"{"frames": {"idle": {"x": 4294967296, "y": 0, "w": 1, "h": 1}}}"#).is_err());
+    }
+
+    #[test]
+    fn frames_must_fit_the_image() {
+        let atlas = sheet(r#"{"frames": {"a": {"x": 32, "y": 16, "w": 32, "h": 16}}}"#).unwrap();
+        assert_eq!(atlas.uv_rect("a"), Some([0.5, 0.5, 0.5, 0.5]));
+        assert_eq!(atlas.uv_rect("b"), None);
+
+        assert!(sheet(r#"{"frames": {"a": {"x": 33, "y": 0, "w": 32, "h": 16}}}"#).is_err());
+        // x + w overflows a u32
+        assert!(sheet(r#"{"frames": {"a": {"x": 4294967295, "y": 0, "w": 2, "h": 1}}}"#).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+// This file contains the parallax background layers drawn by the static pipeline
+use bytemuck;
+
+use super::camera::Camera2D;
+
+/// A full screen background image that scrolls with the camera
+#[derive(Clone, Debug)]
+pub struct BackgroundLayer {
+    pub texture_key: String,
+    /// How far the layer moves relative to the camera, 0.0 is fixed to the screen and 1.0 moves with the world
+    pub parallax: f32,
+    /// Tile the image horizontally instead of leaving empty space past its edges
+    pub repeat_x: bool,
+    /// Push the image down (or up when negative) by this many logical pixels
+    pub offset_y: f32,
+    /// 0.0 is invisible and 1.0 fully opaque, used to fade between layers
+    pub opacity: f32,
+    /// Multiply the layer by the world tint, turn off for sky layers that fade between day and night themselves
+    pub tinted: bool,
+}
+
+impl BackgroundLayer {
+    pub fn new(texture_key: &str, parallax: f32) -> Self {
+        Self {
+            texture_key: String::from(texture_key),
+            parallax,
+            repeat_x: true,
+            offset_y: 0.0,
+            opacity: 1.0,
+            tinted: true,
+        }
+    }
+
+    /// Work out which part of the texture is on screen, the image is scaled so its height fills the view
+    pub fn uniform(&self, camera: &Camera2D, texture_width: u32, texture_height: u32, world_tint: [f32; 4]) -> BackgroundUniform {
+        let viewport = camera.viewport();
+        let scale = viewport[1] / texture_height as f32;
+        let texture_width = texture_width as f32 * scale;
+        let texture_height = texture_height as f32 * scale;
+
+        // Scroll distance in logical pixels, zero when the camera is in its starting position
+        let scroll_x = (camera.position[0] - viewport[0] / 2.0) * self.parallax;
+        let scroll_y = (camera.position[1] - viewport[1] / 2.0) * self.parallax - self.offset_y;
+
+        let tint = if self.tinted { world_tint } else { [1.0; 4] };
+
+        BackgroundUniform {
+            uv_offset: [scroll_x / texture_width, scroll_y / texture_height],
+            uv_scale: [viewport[0] / texture_width, viewport[1] / texture_height],
+            tint: [tint[0], tint[1], tint[2], tint[3] * self.opacity],
+            repeat_x: if self.repeat_x { 1.0 } else { 0.0 },
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// Per-layer data passed to the static shader, must match `BackgroundUniform` in static.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BackgroundUniform {
+    pub uv_offset: [f32; 2],
+    pub uv_scale: [f32; 2],
+    pub tint: [f32; 4],
+    pub repeat_x: f32,
+    // Uniform buffers are laid out in 16 byte chunks, so pad the struct out to 48 bytes
+    pub _padding: [f32; 3],
+}
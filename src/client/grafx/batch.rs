@@ -0,0 +1,343 @@
+// This file contains the batched sprite renderer, sprites sharing a texture are drawn with one instanced call
+use std::{
+    collections::HashMap,
+    ops::Range
+};
+use bytemuck;
+use wgpu::util::DeviceExt;
+use wgpu;
+
+use super::grafx::MISSING_TEXTURE_KEY;
+use super::internal::{BlendMode, TextureBindGroup, Vertex};
+
+/// Number of instances the instance buffer starts with, it grows as needed
+const INITIAL_INSTANCE_CAPACITY: usize = 256;
+
+/// Per-instance data passed to the dynamic shader, must match `InstanceInput` in dynamic.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstance {
+    pub position: [f32; 2],
+    pub scale: [f32; 2],
+    pub rotation: f32,
+    // Offset (xy) and size (zw) of the area of the texture to draw
+    pub uv_rect: [f32; 4],
+    // Color the texture is multiplied by
+    pub color: [f32; 4],
+}
+
+impl SpriteInstance {
+    pub fn new(position: [f32; 2], scale: [f32; 2], rotation: f32) -> Self {
+        Self {
+            position,
+            scale,
+            rotation,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    /// Cut an unrotated sprite down to the part inside `clip` (x, y, width, height), trimming its texture area to match
+    /// Returns None if nothing is left. Rotated sprites can't be cut, they are dropped if their rotated bounds miss
+    /// the clip and otherwise kept whole, so they may overflow its edges
+    pub fn clipped(&self, clip: [f32; 4]) -> Option<Self> {
+        let mut half: [f32; 2] = [self.scale[0].abs() / 2.0, self.scale[1].abs() / 2.0];
+        if self.rotation != 0.0 {
+            // Half size of the box around the rotated corners
+            let (sin, cos): (f32, f32) = self.rotation.sin_cos();
+            half = [
+                half[0] * cos.abs() + half[1] * sin.abs(),
+                half[0] * sin.abs() + half[1] * cos.abs(),
+            ];
+        }
+        let min: [f32; 2] = [self.position[0] - half[0], self.position[1] - half[1]];
+        let max: [f32; 2] = [self.position[0] + half[0], self.position[1] + half[1]];
+
+        let clipped_min: [f32; 2] = [min[0].max(clip[0]), min[1].max(clip[1])];
+        let clipped_max: [f32; 2] = [max[0].min(clip[0] + clip[2]), max[1].min(clip[1] + clip[3])];
+
+        if clipped_min[0] >= clipped_max[0] || clipped_min[1] >= clipped_max[1] {
+            return None;
+        }
+        if self.rotation != 0.0 || (clipped_min == min && clipped_max == max) {
+            return Some(*self);
+        }
+
+        // Move the texture area's edges in by the same fraction as the sprite's edges
+        let size: [f32; 2] = [max[0] - min[0], max[1] - min[1]];
+        let mut start: [f32; 2] = [(clipped_min[0] - min[0]) / size[0], (clipped_min[1] - min[1]) / size[1]];
+        let mut end: [f32; 2] = [(clipped_max[0] - min[0]) / size[0], (clipped_max[1] - min[1]) / size[1]];
+
+        // A negative scale flips the texture, so the opposite edge of the texture area moves
+        for axis in 0..2 {
+            if self.scale[axis] < 0.0 {
+                (start[axis], end[axis]) = (1.0 - end[axis], 1.0 - start[axis]);
+            }
+        }
+
+        let mut clipped: Self = *self;
+        clipped.position = [(clipped_min[0] + clipped_max[0]) / 2.0, (clipped_min[1] + clipped_max[1]) / 2.0];
+        clipped.scale = [(clipped_max[0] - clipped_min[0]) * self.scale[0].signum(), (clipped_max[1] - clipped_min[1]) * self.scale[1].signum()];
+        clipped.uv_rect = [
+            self.uv_rect[0] + start[0] * self.uv_rect[2],
+            self.uv_rect[1] + start[1] * self.uv_rect[3],
+            (end[0] - start[0]) * self.uv_rect[2],
+            (end[1] - start[1]) * self.uv_rect[3],
+        ];
+        Some(clipped)
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+            // Advance once per sprite instead of once per vertex
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// A run of instances that all use the same texture, blend mode and view
+struct Batch {
+    bind_group_key: String,
+    blend_mode: BlendMode,
+    screen_space: bool,
+    instances: Range<u32>,
+}
+
+pub struct SpriteBatch {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    instances: Vec<SpriteInstance>,
+    batches: Vec<Batch>,
+}
+
+impl SpriteBatch {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // A unit quad centered on the origin shared by every sprite, instances scale and move it into place
+        // World space y points down, so the top of the quad is at -0.5
+        let vertices = &[
+            Vertex { position: [-0.5, -0.5, 0.0], tex_coords: [0.0, 0.0] }, // Top-left
+            Vertex { position: [-0.5,  0.5, 0.0], tex_coords: [0.0, 1.0] }, // Bottom-left
+            Vertex { position: [ 0.5,  0.5, 0.0], tex_coords: [1.0, 1.0] }, // Bottom-right
+            Vertex { position: [ 0.5, -0.5, 0.0], tex_coords: [1.0, 0.0] }, // Top-right
+        ];
+
+        let indices: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite Batch Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite Batch Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        SpriteBatch {
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+            instance_buffer: create_instance_buffer(device, INITIAL_INSTANCE_CAPACITY),
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
+            instances: Vec::with_capacity(INITIAL_INSTANCE_CAPACITY),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Clear out last frame's instances
+    pub fn begin(&mut self) {
+        self.instances.clear();
+        self.batches.clear();
+    }
+
+    /// Queue a sprite in draw order, consecutive sprites with the same texture, blend mode and view end up in the same batch
+    /// Screen space sprites are positioned in logical pixels and ignore the camera
+    pub fn push(&mut self, bind_group_key: &str, blend_mode: BlendMode, screen_space: bool, instance: SpriteInstance) {
+        let index = self.instances.len() as u32;
+        self.instances.push(instance);
+
+        match self.batches.last_mut() {
+            Some(batch) if batch.bind_group_key == bind_group_key && batch.blend_mode == blend_mode && batch.screen_space == screen_space => {
+                batch.instances.end = index + 1;
+            }
+            _ => {
+                self.batches.push(Batch {
+                    bind_group_key: String::from(bind_group_key),
+                    blend_mode,
+                    screen_space,
+                    instances: index..index + 1,
+                });
+            }
+        }
+    }
+
+    /// Upload the queued instances, must be called before the render pass begins
+    pub fn finish(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.instances.is_empty() {
+            return;
+        }
+
+        // Grow the instance buffer if this frame has more sprites than it can hold
+        if self.instances.len() > self.instance_capacity {
+            self.instance_capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.instance_capacity);
+        }
+
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.instances));
+    }
+
+    /// Draw every batch with a single instanced call each, switching pipelines when the blend mode changes
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, bind_group_map: &'a HashMap<String, TextureBindGroup>, pipelines: &'a HashMap<BlendMode, wgpu::RenderPipeline>, world_view: &'a wgpu::BindGroup, screen_view: &'a wgpu::BindGroup) {
+        if self.batches.is_empty() {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        let mut current_blend_mode: Option<BlendMode> = None;
+        let mut current_screen_space: Option<bool> = None;
+
+        for batch in &self.batches {
+            if current_screen_space != Some(batch.screen_space) {
+                render_pass.set_bind_group(1, if batch.screen_space { screen_view } else { world_view }, &[]);
+                current_screen_space = Some(batch.screen_space);
+            }
+
+            if current_blend_mode != Some(batch.blend_mode) {
+                match pipelines.get(&batch.blend_mode) {
+                    Some(pipeline) => render_pass.set_pipeline(pipeline),
+                    None => continue
+                }
+                current_blend_mode = Some(batch.blend_mode);
+            }
+
+            // Textures that failed to load are drawn with the missing texture so they stand out
+            if let Some(bind_group) = bind_group_map.get(&batch.bind_group_key).or_else(|| bind_group_map.get(MISSING_TEXTURE_KEY)) {
+                render_pass.set_bind_group(0, bind_group.get_bind_group(), &[]);
+                render_pass.draw_indexed(0..self.num_indices, 0, batch.instances.clone());
+            }
+        }
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Sprite Batch Instance Buffer"),
+        size: (capacity * std::mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 4], b: [f32; 4]) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 0.0001)
+    }
+
+    /// A 20x20 sprite centered on (10, 10), covering 0..20 on both axes
+    fn sprite(scale: [f32; 2]) -> SpriteInstance {
+        SpriteInstance::new([10.0, 10.0], scale, 0.0)
+    }
+
+    #[test]
+    fn sprites_inside_the_clip_are_kept_as_is() {
+        let instance = sprite([20.0, 20.0]);
+        assert_eq!(instance.clipped([-5.0, -5.0, 100.0, 100.0]), Some(instance));
+        assert_eq!(instance.clipped([0.0, 0.0, 20.0, 20.0]), Some(instance));
+    }
+
+    #[test]
+    fn sprites_outside_the_clip_are_dropped() {
+        let instance = sprite([20.0, 20.0]);
+        assert_eq!(instance.clipped([30.0, 0.0, 10.0, 10.0]), None);
+        // Touching an edge leaves nothing to draw
+        assert_eq!(instance.clipped([20.0, 0.0, 10.0, 20.0]), None);
+        assert_eq!(instance.clipped([0.0, 0.0, 20.0, 0.0]), None);
+    }
+
+    #[test]
+    fn clipping_trims_the_texture_area() {
+        let clipped = sprite([20.0, 20.0]).clipped([0.0, 0.0, 15.0, 100.0]).unwrap();
+        assert_eq!(clipped.position, [7.5, 10.0]);
+        assert_eq!(clipped.scale, [15.0, 20.0]);
+        assert!(close(clipped.uv_rect, [0.0, 0.0, 0.75, 1.0]));
+
+        // Inside an atlas frame the trim is a fraction of the frame, not the texture
+        let mut framed = sprite([20.0, 20.0]);
+        framed.uv_rect = [0.5, 0.5, 0.25, 0.25];
+        let clipped = framed.clipped([0.0, 5.0, 100.0, 100.0]).unwrap();
+        assert_eq!(clipped.position, [10.0, 12.5]);
+        assert!(close(clipped.uv_rect, [0.5, 0.5625, 0.25, 0.1875]));
+    }
+
+    #[test]
+    fn flipped_sprites_trim_the_other_side_of_the_texture() {
+        let clipped = sprite([-20.0, 20.0]).clipped([0.0, 0.0, 15.0, 100.0]).unwrap();
+        assert_eq!(clipped.scale, [-15.0, 20.0]);
+        assert!(close(clipped.uv_rect, [0.25, 0.0, 0.75, 1.0]));
+
+        let clipped = sprite([20.0, -20.0]).clipped([0.0, 15.0, 100.0, 100.0]).unwrap();
+        assert_eq!(clipped.position, [10.0, 17.5]);
+        assert_eq!(clipped.scale, [20.0, -5.0]);
+        assert!(close(clipped.uv_rect, [0.0, 0.0, 1.0, 0.25]));
+    }
+
+    #[test]
+    fn rotated_sprites_are_kept_whole() {
+        let instance = SpriteInstance::new([10.0, 10.0], [20.0, 20.0], 0.5);
+        assert_eq!(instance.clipped([0.0, 0.0, 15.0, 15.0]), Some(instance));
+        assert_eq!(instance.clipped([40.0, 40.0, 15.0, 15.0]), None);
+    }
+
+    #[test]
+    fn rotated_sprites_are_clipped_by_their_rotated_bounds() {
+        // Turned 45 degrees the corners reach about 14.1 from the center instead of 10
+        let instance = SpriteInstance::new([10.0, 10.0], [20.0, 20.0], std::f32::consts::FRAC_PI_4);
+        assert_eq!(instance.clipped([22.0, 0.0, 10.0, 20.0]), Some(instance));
+        assert_eq!(instance.clipped([25.0, 0.0, 10.0, 20.0]), None);
+
+        // A long thin sprite turned upright no longer reaches sideways
+        let instance = SpriteInstance::new([10.0, 10.0], [40.0, 2.0], std::f32::consts::FRAC_PI_2);
+        assert_eq!(instance.clipped([15.0, 0.0, 10.0, 20.0]), None);
+        assert_eq!(instance.clipped([0.0, 25.0, 20.0, 10.0]), Some(instance));
+    }
+}
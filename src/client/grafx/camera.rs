@@ -0,0 +1,128 @@
+// This file contains the 2D camera, world space is in pixels with y pointing down like the screen
+
+/// Smallest zoom allowed, anything lower would divide by zero when converting to world space
+const MIN_ZOOM: f32 = 0.01;
+
+pub struct Camera2D {
+    /// World position at the center of the view
+    pub position: [f32; 2],
+    zoom: f32,
+    /// Logical size of the view in pixels
+    viewport: [f32; 2],
+    /// Level area the view is kept inside of, as (min, max) corners
+    bounds: Option<([f32; 2], [f32; 2])>,
+    target: Option<[f32; 2]>,
+    /// How quickly the camera catches up to its target, higher is snappier
+    pub follow_speed: f32,
+}
+
+impl Camera2D {
+    pub fn new(viewport_width: f32, viewport_height: f32) -> Self {
+        Self {
+            position: [viewport_width / 2.0, viewport_height / 2.0],
+            zoom: 1.0,
+            viewport: [viewport_width, viewport_height],
+            bounds: None,
+            target: None,
+            follow_speed: 5.0,
+        }
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Set the zoom, 2.0 makes everything twice as big
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(MIN_ZOOM);
+        self.clamp_to_bounds();
+    }
+
+    pub fn viewport(&self) -> [f32; 2] {
+        self.viewport
+    }
+
+    /// Keep the view inside of a level, usually (0, 0) to the level size
+    pub fn set_bounds(&mut self, min: [f32; 2], max: [f32; 2]) {
+        self.bounds = Some((min, max));
+        self.clamp_to_bounds();
+    }
+
+    pub fn clear_bounds(&mut self) {
+        self.bounds = None;
+    }
+
+    /// Set the world position the camera should drift towards, None stops following
+    pub fn set_target(&mut self, target: Option<[f32; 2]>) {
+        self.target = target;
+    }
+
+    /// Jump straight to a position, skipping the smooth follow
+    pub fn snap_to(&mut self, position: [f32; 2]) {
+        self.position = position;
+        self.clamp_to_bounds();
+    }
+
+    /// Move towards the target, the exponential falloff keeps the motion the same at any framerate
+    pub fn update(&mut self, dt: f32) {
+        if let Some(target) = self.target {
+            let t = 1.0 - (-self.follow_speed * dt).exp();
+            self.position[0] += (target[0] - self.position[0]) * t;
+            self.position[1] += (target[1] - self.position[1]) * t;
+        }
+        self.clamp_to_bounds();
+    }
+
+    /// Size of the world area currently in view
+    pub fn visible_size(&self) -> [f32; 2] {
+        [self.viewport[0] / self.zoom, self.viewport[1] / self.zoom]
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return
+        };
+
+        let visible = self.visible_size();
+        for axis in 0..2 {
+            let half = visible[axis] / 2.0;
+            // A level smaller than the view is centered instead of clamped
+            if max[axis] - min[axis] <= visible[axis] {
+                self.position[axis] = (min[axis] + max[axis]) / 2.0;
+            } else {
+                self.position[axis] = self.position[axis].clamp(min[axis] + half, max[axis] - half);
+            }
+        }
+    }
+
+    /// Column major matrix that takes world positions to clip space
+    pub fn view_projection(&self) -> [[f32; 4]; 4] {
+        let scale_x = 2.0 * self.zoom / self.viewport[0];
+        // Clip space y points up, world space y points down
+        let scale_y = -2.0 * self.zoom / self.viewport[1];
+
+        [
+            [scale_x, 0.0, 0.0, 0.0],
+            [0.0, scale_y, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-self.position[0] * scale_x, -self.position[1] * scale_y, 0.0, 1.0],
+        ]
+    }
+
+    /// Convert a position in logical screen pixels (origin top-left) to world space
+    pub fn screen_to_world(&self, screen: [f32; 2]) -> [f32; 2] {
+        [
+            (screen[0] - self.viewport[0] / 2.0) / self.zoom + self.position[0],
+            (screen[1] - self.viewport[1] / 2.0) / self.zoom + self.position[1],
+        ]
+    }
+
+    /// Convert a world position to logical screen pixels (origin top-left)
+    pub fn world_to_screen(&self, world: [f32; 2]) -> [f32; 2] {
+        [
+            (world[0] - self.position[0]) * self.zoom + self.viewport[0] / 2.0,
+            (world[1] - self.position[1]) * self.zoom + self.viewport[1] / 2.0,
+        ]
+    }
+}
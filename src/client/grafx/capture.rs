@@ -0,0 +1,193 @@
+// This file contains frame capture, screenshots and recordings are written to disk with the image crate
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::{self, JoinHandle}
+};
+use anyhow::{Error, Result};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage
+};
+
+/// How a recording is written out
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CaptureFormat {
+    /// A single looping animated GIF
+    Gif,
+    /// A folder of numbered PNGs (frame_00000.png, frame_00001.png...)
+    PngSequence,
+}
+
+/// Frames waiting to be encoded before new ones are dropped, a full HD frame is about 8 MB
+const RECORDING_QUEUE_FRAMES: usize = 16;
+
+/// A recording in progress, frames are encoded on a worker thread so the render loop doesn't stall
+struct Recording {
+    sender: SyncSender<RgbaImage>,
+    worker: JoinHandle<Result<(), Error>>,
+    frames: usize,
+    max_frames: usize,
+    // Frames thrown away because the worker fell behind
+    dropped: usize,
+}
+
+// A stopped recording whose worker is still writing out the queued frames
+struct Finishing {
+    worker: JoinHandle<Result<(), Error>>,
+    dropped: usize,
+}
+
+#[derive(Default)]
+pub struct FrameCapture {
+    screenshot: Option<PathBuf>,
+    recording: Option<Recording>,
+    finishing: Vec<Finishing>,
+}
+
+impl FrameCapture {
+    /// True if the next rendered frame should be copied back from the GPU
+    pub fn wants_frame(&self) -> bool {
+        self.screenshot.is_some() || self.recording.is_some()
+    }
+
+    /// Save the next rendered frame as a PNG
+    pub fn request_screenshot(&mut self, path: &Path) {
+        self.screenshot = Some(path.to_path_buf());
+    }
+
+    /// Start recording every rendered frame, stops by itself after `max_frames`
+    pub fn start_recording(&mut self, path: &Path, format: CaptureFormat, max_frames: usize, frame_delay_ms: u32) -> Result<(), Error> {
+        if self.recording.is_some() {
+            return Err(anyhow::anyhow!("Already recording"));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<RgbaImage>(RECORDING_QUEUE_FRAMES);
+        let path = path.to_path_buf();
+
+        let worker = match format {
+            CaptureFormat::Gif => {
+                create_parent_dir(&path)?;
+                let file = File::create(&path)?;
+                thread::spawn(move || {
+                    let mut encoder = GifEncoder::new(BufWriter::new(file));
+                    encoder.set_repeat(Repeat::Infinite)?;
+                    for image in receiver {
+                        encoder.encode_frame(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(frame_delay_ms, 1)))?;
+                    }
+                    Ok(())
+                })
+            }
+            CaptureFormat::PngSequence => {
+                std::fs::create_dir_all(&path)?;
+                thread::spawn(move || {
+                    for (index, image) in receiver.into_iter().enumerate() {
+                        image.save(path.join(format!("frame_{:05}.png", index)))?;
+                    }
+                    Ok(())
+                })
+            }
+        };
+
+        self.recording = Some(Recording { sender, worker, frames: 0, max_frames, dropped: 0 });
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stop recording, the queued frames are written in the background and `poll_finished` reports when they are done
+    pub fn stop_recording(&mut self) -> Result<(), Error> {
+        let recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => return Err(anyhow::anyhow!("Not recording"))
+        };
+
+        // Dropping the sender ends the worker's loop once it has caught up
+        drop(recording.sender);
+        self.finishing.push(Finishing { worker: recording.worker, dropped: recording.dropped });
+        Ok(())
+    }
+
+    /// Get the result of every stopped recording that finished writing since the last poll
+    /// Successful ones give how many frames were dropped because encoding fell behind
+    pub fn poll_finished(&mut self) -> Vec<Result<usize, Error>> {
+        let mut results: Vec<Result<usize, Error>> = Vec::new();
+        let mut index: usize = 0;
+        while index < self.finishing.len() {
+            if self.finishing[index].worker.is_finished() {
+                let finished: Finishing = self.finishing.swap_remove(index);
+                results.push(join(finished.worker).map(|_| finished.dropped));
+            } else {
+                index += 1;
+            }
+        }
+        results
+    }
+
+    /// Forget a pending screenshot and stop recording, e.g. when frames can't be read back
+    pub fn cancel(&mut self) {
+        self.screenshot = None;
+        let _ = self.stop_recording();
+    }
+
+    /// Hand a rendered frame to whatever asked for it
+    pub fn submit(&mut self, image: RgbaImage) -> Result<(), Error> {
+        let mut result: Result<(), Error> = Ok(());
+
+        if let Some(path) = self.screenshot.take() {
+            result = create_parent_dir(&path).and_then(|_| image.save(&path).map_err(Error::from));
+        }
+
+        let finished = match &mut self.recording {
+            Some(recording) => {
+                recording.frames += 1;
+                match recording.sender.try_send(image) {
+                    Ok(_) => recording.frames >= recording.max_frames,
+                    Err(TrySendError::Full(_)) => {
+                        recording.dropped += 1;
+                        recording.frames >= recording.max_frames
+                    }
+                    // The worker hit an error, poll_finished will report it
+                    Err(TrySendError::Disconnected(_)) => true
+                }
+            }
+            None => false
+        };
+
+        if finished {
+            result = result.and(self.stop_recording());
+        }
+
+        result
+    }
+}
+
+impl Drop for FrameCapture {
+    // Let recordings finish writing when the game closes, otherwise they are cut off
+    fn drop(&mut self) {
+        let _ = self.stop_recording();
+        for finished in self.finishing.drain(..) {
+            if let Err(err) = join(finished.worker) {
+                println!("Failed to save recording: {}", err);
+            }
+        }
+    }
+}
+
+fn join(worker: JoinHandle<Result<(), Error>>) -> Result<(), Error> {
+    match worker.join() {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("Recording worker panicked"))
+    }
+}
+
+fn create_parent_dir(path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
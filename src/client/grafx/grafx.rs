@@ -1,25 +1,111 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::Arc
 };
 use wgpu;
 use winit::window::Window;
 
-use super::internal::{self};
+use super::internal::{self, BlendMode};
+use super::batch::{SpriteBatch, SpriteInstance};
+use super::atlas::{AtlasFrame, AtlasLayout, TextureAtlas};
+use super::animation::Animator;
+use super::layer::Layer;
+use super::camera::Camera2D;
+use super::background::BackgroundLayer;
+use super::viewport::{ScalePolicy, ViewportRect};
+use super::capture::{CaptureFormat, FrameCapture};
+use super::loader::{AssetLoader, LoadProgress};
+use super::watcher::FileWatcher;
+use super::text::{self, FontFace, TextSprite, TextStyle};
+use crate::client::config;
+use crate::client::assets::{self, AssetError, AssetKind, AssetManager, Handle};
+
+/// A background layer and the GPU resources used to draw it
+struct BackgroundLayerState {
+    layer: BackgroundLayer,
+    sprite: internal::Sprite,
+    uniform: internal::UniformBuffer,
+}
+
+/// Key of a plain 1x1 white texture that is always loaded, tint it with a sprite color to draw solid rects
+pub const WHITE_TEXTURE_KEY: &str = "__white";
+
+/// Key of a magenta and black checkerboard that is always loaded, drawn in place of any texture that failed to load
+pub const MISSING_TEXTURE_KEY: &str = "__missing";
+
+/// Size of one square of the missing texture's checkerboard, in pixels
+const MISSING_TEXTURE_CELL: u32 = 8;
+
+/// Most threads the background asset loader will use
+const MAX_LOADER_THREADS: usize = 4;
+
+/// Where the shaders are read from when hot reloading, release builds only use the copies baked in at compile time
+const SHADER_DIR: &str = "src/client/shaders";
+const STATIC_SHADER_FILE: &str = "static.wgsl";
+const DYNAMIC_SHADER_FILE: &str = "dynamic.wgsl";
+
+/// Pixel size TrueType fonts are rasterized at when the manifest doesn't give one
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Something the sprite batch draws, sprites and text are sorted together so they can be layered over each other
+enum Drawable<'a> {
+    Sprite(&'a internal::DynamicSprite),
+    Text(&'a TextSprite, &'a FontFace),
+}
+
+/// Where a loaded texture came from, so it can be loaded again when the file changes
+struct TextureSource {
+    path: PathBuf,
+    layout: Option<AtlasLayout>,
+}
 
 pub struct Grafx {
-    window: Arc<Window>,
+    window: Option<Arc<Window>>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    surface: wgpu::Surface<'static>,
+    // Headless renderers draw into an offscreen texture instead of a window surface
+    surface: Option<wgpu::Surface<'static>>,
+    offscreen: Option<wgpu::Texture>,
+    config: wgpu::SurfaceConfiguration,
+    scale_policy: ScalePolicy,
+    viewport: ViewportRect,
     static_render_pipeline: wgpu::RenderPipeline,
-    dynamic_render_pipeline: wgpu::RenderPipeline,
+    dynamic_render_pipelines: HashMap<BlendMode, wgpu::RenderPipeline>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    assets: AssetManager,
     bind_group_map: HashMap<String, internal::TextureBindGroup>,
-    sprite_map: HashMap<String, internal::Sprite>
+    atlas_map: HashMap<String, TextureAtlas>,
+    background_layers: Vec<BackgroundLayerState>,
+    dynamic_sprite_map: HashMap<String, internal::DynamicSprite>,
+    font_map: HashMap<String, FontFace>,
+    text_map: HashMap<String, TextSprite>,
+    // Sprites drawn for this frame only, sorted in with the dynamic sprites and refilled every frame
+    sprite_queue: Vec<internal::DynamicSprite>,
+    // Immediate mode UI drawn over everything else, refilled every frame
+    ui_queue: Vec<(String, SpriteInstance)>,
+    sprite_batch: SpriteBatch,
+    camera: Camera2D,
+    camera_follow: Option<String>,
+    view_uniform: internal::UniformBuffer,
+    screen_view_uniform: internal::UniformBuffer,
+    world_tint: [f32; 4],
+    capture: FrameCapture,
+    loader: AssetLoader,
+    // Textures queued by file path instead of manifest ID, keyed by texture key
+    queued_files: HashMap<String, PathBuf>,
+    // Manifest assets still being decoded, one acquired again before its result arrives shares it instead of loading twice
+    queued_assets: HashSet<String>,
+    loading_screen: bool,
+    texture_sources: HashMap<String, TextureSource>,
+    // Only set while hot reloading is on
+    watcher: Option<FileWatcher>
 }
 
 impl Grafx {
-    pub async fn new(window: Arc<Window>) -> Self {
+    /// Create a renderer that draws to a window, fails if no GPU can draw to it
+    pub async fn new(window: Arc<Window>) -> Result<Self, anyhow::Error> {
         // The physical size of the winit window
         let size: winit::dpi::PhysicalSize<u32> = window.inner_size();
 
@@ -27,13 +113,13 @@ impl Grafx {
         let instance: wgpu::Instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
 
         // The surface is what is everything is drawn to
-        let surface: wgpu::Surface<'static> = instance.create_surface(window.clone()).unwrap();
+        let surface: wgpu::Surface<'static> = instance.create_surface(window.clone())?;
 
         // The adapter is for actaully handing GPU
-        let adapter: wgpu::Adapter = internal::create_adapter(&instance, &surface).await;
+        let adapter: wgpu::Adapter = internal::create_adapter(&instance, &surface).await?;
 
         // The GPU device and device queue
-        let (device, queue): (wgpu::Device, wgpu::Queue) = internal::create_device_and_queue(&adapter).await;
+        let (device, queue): (wgpu::Device, wgpu::Queue) = internal::create_device_and_queue(&adapter).await?;
 
         // The surface configuration
         let config: wgpu::SurfaceConfiguration = internal::create_surface_configuration(&adapter, &surface, &size);
@@ -41,71 +127,1033 @@ impl Grafx {
         // Configure surface for presentation
         surface.configure(&device, &config);
 
+        Ok(Self::build(Some(window), Some(surface), device, queue, config))
+    }
+
+    /// Create a renderer with no window that draws into an offscreen texture, read frames back with `read_frame`
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self, anyhow::Error> {
+        // Context for all wgpu objects
+        let instance: wgpu::Instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+
+        // No surface to be compatible with, so a software adapter is fine
+        let adapter: wgpu::Adapter = internal::create_headless_adapter(&instance).await?;
+
+        // The GPU device and device queue
+        let (device, queue): (wgpu::Device, wgpu::Queue) = internal::create_device_and_queue(&adapter).await?;
+
+        // Describes the offscreen texture the same way a surface would be described
+        let config: wgpu::SurfaceConfiguration = internal::create_offscreen_configuration(width.max(1), height.max(1));
+
+        Ok(Self::build(None, None, device, queue, config))
+    }
+
+    // Create everything that doesn't depend on where frames end up
+    fn build(window: Option<Arc<Window>>, surface: Option<wgpu::Surface<'static>>, device: wgpu::Device, queue: wgpu::Queue, config: wgpu::SurfaceConfiguration) -> Self {
+        // Without a surface frames are drawn into a texture that can be copied back
+        let offscreen: Option<wgpu::Texture> = match surface {
+            Some(_) => None,
+            None => Some(internal::create_offscreen_texture(&device, &config))
+        };
+
         // Create a single bind group layout to be shared by all static bind groups
         let bind_group_layout: wgpu::BindGroupLayout = internal::create_bind_group_layout(&device);
 
-        // Create a single bind group layout to be shared by all dynamic bind groups
+        // Create a single bind group layout to be shared by all uniform buffers
         let uniform_bind_group_layout: wgpu::BindGroupLayout = internal::create_uniform_bind_group_layout(&device);
 
-        let static_shader: wgpu::ShaderModule = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Static Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/static.wgsl").into()),
-        });
+        // Create a render pipeline for static sprites
+        let static_render_pipeline: wgpu::RenderPipeline = create_static_render_pipeline(&device, &bind_group_layout, &uniform_bind_group_layout, &config, include_str!("../shaders/static.wgsl"));
 
-        let dynamic_shader: wgpu::ShaderModule = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Dynamic Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/dynamic.wgsl").into()),
-        });
+        // Create a render pipeline per blend mode for dynamic sprites (they can move once created), drawn instanced through the sprite batch
+        let dynamic_render_pipelines: HashMap<BlendMode, wgpu::RenderPipeline> = create_dynamic_render_pipelines(&device, &bind_group_layout, &uniform_bind_group_layout, &config, include_str!("../shaders/dynamic.wgsl"));
 
-        // Create a render pipeline layout to be used by static render pipeline
-        let static_render_pipeline_layout: wgpu::PipelineLayout = internal::create_static_render_pipeline_layout(&device, &bind_group_layout);
+        // Shared quad and instance buffer for every dynamic sprite
+        let sprite_batch: SpriteBatch = SpriteBatch::new(&device);
 
-        // Create a render pipeline layout to be used by dynamic render pipeline
-        let dynamic_render_pipeline_layout: wgpu::PipelineLayout = internal::create_dynamic_render_pipeline_layout(&device, &bind_group_layout, &uniform_bind_group_layout);
+        // The camera works in logical pixels so the world looks the same at any window size
+        let camera: Camera2D = Camera2D::new(config::WINDOW_WIDTH as f32, config::WINDOW_HEIGHT as f32);
 
-        // Create a render pipeline for static sprites
-        let static_render_pipeline: wgpu::RenderPipeline = internal::create_render_pipeline(&device, &static_render_pipeline_layout, &static_shader, &config);
+        // Fit the logical resolution into the window
+        let scale_policy: ScalePolicy = ScalePolicy::Letterbox;
+        let viewport: ViewportRect = ViewportRect::fit(scale_policy, [config.width, config.height], camera.viewport());
+
+        let view_uniform: internal::UniformBuffer = internal::UniformBuffer::new(&device, &uniform_bind_group_layout, &internal::ViewUniform { view_proj: camera.view_projection() }, "View Uniform Buffer");
 
-        // Create a render pipeline for dynamic sprites (they can move once created)
-        let dynamic_render_pipeline: wgpu::RenderPipeline = internal::create_render_pipeline(&device, &dynamic_render_pipeline_layout, &dynamic_shader, &config);
+        // Screen space sprites (UI) use a camera that never moves, so world space is just logical pixels
+        let screen_camera: Camera2D = Camera2D::new(camera.viewport()[0], camera.viewport()[1]);
+        let screen_view_uniform: internal::UniformBuffer = internal::UniformBuffer::new(&device, &uniform_bind_group_layout, &internal::ViewUniform { view_proj: screen_camera.view_projection() }, "Screen View Uniform Buffer");
 
-        // Create map of bind groups
+        // Solid color rects (loading bars, UI backgrounds...) are drawn with a tinted white texture
         let mut bind_group_map: HashMap<String, internal::TextureBindGroup> = HashMap::new();
+        let white: image::RgbaImage = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        bind_group_map.insert(String::from(WHITE_TEXTURE_KEY), internal::TextureBindGroup::from_image(&device, &queue, &bind_group_layout, &white, WHITE_TEXTURE_KEY));
 
-        let mut sprite_map: HashMap<String, internal::Sprite> = HashMap::new();
+        // Broken content shows up as a loud checkerboard instead of disappearing
+        let missing: image::RgbaImage = image::RgbaImage::from_fn(MISSING_TEXTURE_CELL * 2, MISSING_TEXTURE_CELL * 2, |x, y| {
+            if (x / MISSING_TEXTURE_CELL + y / MISSING_TEXTURE_CELL).is_multiple_of(2) {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+        bind_group_map.insert(String::from(MISSING_TEXTURE_KEY), internal::TextureBindGroup::from_image(&device, &queue, &bind_group_layout, &missing, MISSING_TEXTURE_KEY));
 
-        let texture_bind_group = internal::TextureBindGroup::new(&device, &queue, &bind_group_layout, String::from("assets/bg_1.png"), String::from("background"));
+        // Decode assets in the background, leaving a core free for the game
+        let loader_threads: usize = std::thread::available_parallelism().map(|count| count.get().saturating_sub(1)).unwrap_or(1).clamp(1, MAX_LOADER_THREADS);
+        let loader: AssetLoader = AssetLoader::new(loader_threads);
 
-        match texture_bind_group {
-            Ok(bind_group) => {
-                let background: internal::Sprite = internal::Sprite::background(&device, String::from("background"));
-                bind_group_map.insert(String::from("background"), bind_group);
-                sprite_map.insert(String::from("background"), background);
-            }
-            Err(_err) => {
-                println!("background not loaded");
-            }
+        // Logical asset IDs and where they live on disk
+        let mut assets: AssetManager = AssetManager::new();
+
+        if let Err(err) = assets.load_manifest(Path::new(assets::MANIFEST_PATH)) {
+            println!("asset manifest not loaded: {}", err);
         }
-     
-        let grafx = Grafx {
+
+        Grafx {
             window,
             device,
             queue,
             surface,
+            offscreen,
+            config,
+            scale_policy,
+            viewport,
             static_render_pipeline,
-            dynamic_render_pipeline,
+            dynamic_render_pipelines,
+            bind_group_layout,
+            uniform_bind_group_layout,
+            assets,
             bind_group_map,
-            sprite_map
+            atlas_map: HashMap::new(),
+            background_layers: Vec::new(),
+            dynamic_sprite_map: HashMap::new(),
+            font_map: HashMap::new(),
+            text_map: HashMap::new(),
+            sprite_queue: Vec::new(),
+            ui_queue: Vec::new(),
+            sprite_batch,
+            camera,
+            camera_follow: None,
+            view_uniform,
+            screen_view_uniform,
+            world_tint: [1.0; 4],
+            capture: FrameCapture::default(),
+            loader,
+            queued_files: HashMap::new(),
+            queued_assets: HashSet::new(),
+            loading_screen: false,
+            texture_sources: HashMap::new(),
+            watcher: None
+        }
+    }
+
+    /// Reconfigure the surface for a new window size, a zero size (minimized) is ignored until the window comes back
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        self.config.width = size.width;
+        self.config.height = size.height;
+        match &self.surface {
+            Some(surface) => surface.configure(&self.device, &self.config),
+            None => self.offscreen = Some(internal::create_offscreen_texture(&self.device, &self.config))
+        }
+        self.viewport = ViewportRect::fit(self.scale_policy, [self.config.width, self.config.height], self.camera.viewport());
+    }
+
+    /// Set how the logical resolution is scaled to fit the window
+    pub fn set_scale_policy(&mut self, scale_policy: ScalePolicy) {
+        self.scale_policy = scale_policy;
+        self.viewport = ViewportRect::fit(self.scale_policy, [self.config.width, self.config.height], self.camera.viewport());
+    }
+
+    /// Replace the background with layers drawn back to front, their textures must already be loaded
+    pub fn set_background_layers(&mut self, layers: Vec<BackgroundLayer>) {
+        self.background_layers = layers.into_iter().map(|layer| {
+            let sprite: internal::Sprite = internal::Sprite::background(&self.device, layer.texture_key.clone());
+            let uniform: internal::UniformBuffer = internal::UniformBuffer::new(&self.device, &self.uniform_bind_group_layout, &layer.uniform(&self.camera, 1, 1, self.world_tint), "Background Uniform Buffer");
+            BackgroundLayerState { layer, sprite, uniform }
+        }).collect();
+    }
+
+    /// Get a background layer by its index (back to front) so it can be changed, e.g. faded out
+    pub fn background_layer_mut(&mut self, index: usize) -> Option<&mut BackgroundLayer> {
+        self.background_layers.get_mut(index).map(|state| &mut state.layer)
+    }
+
+    /// Get the background layer drawing a texture so it can be changed, e.g. faded out
+    pub fn background_layer_for_mut(&mut self, texture_key: &str) -> Option<&mut BackgroundLayer> {
+        self.background_layers.iter_mut()
+            .find(|state| state.layer.texture_key == texture_key)
+            .map(|state| &mut state.layer)
+    }
+
+    /// Set the color the world is multiplied by (time of day, etc), UI sprites are left as is
+    pub fn set_world_tint(&mut self, tint: [f32; 4]) {
+        self.world_tint = tint;
+    }
+
+    pub fn assets(&self) -> &AssetManager {
+        &self.assets
+    }
+
+    pub fn assets_mut(&mut self) -> &mut AssetManager {
+        &mut self.assets
+    }
+
+    /// Load a texture listed in the asset manifest, it is only read from disk the first time
+    /// If it fails the error is logged and sprites using it draw the missing texture
+    pub fn load_texture_asset(&mut self, id: &str) -> Result<Handle<assets::Texture>, anyhow::Error> {
+        let (handle, needs_load) = self.assets.acquire::<assets::Texture>(id)
+            .inspect_err(|err| self.assets.report(AssetError::from_error(id, err)))?;
+
+        if needs_load {
+            let loaded = self.asset_path(id).and_then(|path| self.load_texture(id, &path));
+            if let Err(err) = loaded {
+                self.assets.forget(id);
+                self.assets.report(AssetError::from_error(id, &err));
+                return Err(err);
+            }
+        }
+
+        Ok(handle)
+    }
+
+    /// Load a sprite sheet listed in the asset manifest, it is only read from disk the first time
+    pub fn load_atlas_asset(&mut self, id: &str) -> Result<Handle<assets::Atlas>, anyhow::Error> {
+        let (handle, needs_load) = self.assets.acquire::<assets::Atlas>(id)
+            .inspect_err(|err| self.assets.report(AssetError::from_error(id, err)))?;
+
+        if needs_load {
+            let loaded = self.asset_path(id).and_then(|path| {
+                let options = self.assets.resolve(id).and_then(|entry| entry.options.clone()).unwrap_or_default();
+                self.load_atlas(id, &path, AtlasLayout::parse(&options, Path::new(&path))?)
+            });
+
+            if let Err(err) = loaded {
+                self.assets.forget(id);
+                self.assets.report(AssetError::from_error(id, &err));
+                return Err(err);
+            }
+        }
+
+        Ok(handle)
+    }
+
+    /// Start loading a texture listed in the asset manifest in the background, call `poll_loading` every frame to finish it
+    pub fn queue_texture_asset(&mut self, id: &str) -> Result<Handle<assets::Texture>, anyhow::Error> {
+        let (handle, needs_load) = self.assets.acquire::<assets::Texture>(id)
+            .inspect_err(|err| self.assets.report(AssetError::from_error(id, err)))?;
+        if needs_load {
+            self.queue_asset(id, AssetKind::Texture)?;
+        }
+        Ok(handle)
+    }
+
+    /// Start loading a sprite sheet listed in the asset manifest in the background, call `poll_loading` every frame to finish it
+    pub fn queue_atlas_asset(&mut self, id: &str) -> Result<Handle<assets::Atlas>, anyhow::Error> {
+        let (handle, needs_load) = self.assets.acquire::<assets::Atlas>(id)
+            .inspect_err(|err| self.assets.report(AssetError::from_error(id, err)))?;
+        if needs_load {
+            self.queue_asset(id, AssetKind::Atlas)?;
+        }
+        Ok(handle)
+    }
+
+    /// Start loading a texture from disk in the background, e.g. a map's tileset, call `poll_loading` every frame to finish it
+    /// Textures that are already loaded or queued are skipped, failures are logged and the missing texture is drawn instead
+    pub fn queue_texture(&mut self, key: &str, path_to_img: &str) {
+        if self.bind_group_map.contains_key(key) || self.queued_files.contains_key(key) {
+            return;
+        }
+        self.queued_files.insert(String::from(key), PathBuf::from(path_to_img));
+        self.loader.queue(key, AssetKind::Texture, path_to_img);
+    }
+
+    fn queue_asset(&mut self, id: &str, kind: AssetKind) -> Result<(), anyhow::Error> {
+        if self.queued_assets.contains(id) {
+            return Ok(());
+        }
+
+        match self.asset_path(id) {
+            Ok(path) => {
+                self.queued_assets.insert(String::from(id));
+                self.loader.queue(id, kind, &path);
+                Ok(())
+            }
+            Err(err) => {
+                self.assets.forget(id);
+                self.assets.report(AssetError::from_error(id, &err));
+                Err(err)
+            }
+        }
+    }
+
+    /// Upload any assets that finished decoding in the background, returns how many were uploaded
+    pub fn poll_loading(&mut self) -> usize {
+        let results = self.loader.poll();
+        let count = results.len();
+
+        for result in results {
+            if let Some(path) = self.queued_files.remove(&result.id) {
+                match result.image {
+                    Ok(rgba) => {
+                        let bind_group = internal::TextureBindGroup::from_image(&self.device, &self.queue, &self.bind_group_layout, &rgba, &result.id);
+                        self.bind_group_map.insert(result.id.clone(), bind_group);
+                        self.atlas_map.remove(&result.id);
+                        self.texture_sources.insert(result.id, TextureSource { path, layout: None });
+                    }
+                    Err(err) => self.assets.report(AssetError::from_error(&result.id, &err))
+                }
+                continue;
+            }
+
+            self.queued_assets.remove(&result.id);
+
+            // Everyone let go of the asset while it was loading
+            if !self.assets.is_loaded(&result.id) {
+                continue;
+            }
+
+            let uploaded = result.image.and_then(|rgba| {
+                let bind_group = internal::TextureBindGroup::from_image(&self.device, &self.queue, &self.bind_group_layout, &rgba, &result.id);
+
+                let layout: Option<AtlasLayout> = if result.kind == AssetKind::Atlas {
+                    let options = self.assets.resolve(&result.id).and_then(|entry| entry.options.clone()).unwrap_or_default();
+                    let layout: AtlasLayout = AtlasLayout::parse(&options, Path::new(&self.asset_path(&result.id)?))?;
+                    let atlas: TextureAtlas = TextureAtlas::new(result.id.clone(), bind_group.width, bind_group.height, &layout)?;
+                    self.atlas_map.insert(result.id.clone(), atlas);
+                    Some(layout)
+                } else {
+                    self.atlas_map.remove(&result.id);
+                    None
+                };
+
+                self.bind_group_map.insert(result.id.clone(), bind_group);
+                self.texture_sources.insert(result.id.clone(), TextureSource { path: self.asset_path(&result.id)?.into(), layout });
+                Ok(())
+            });
+
+            if let Err(err) = uploaded {
+                self.assets.forget(&result.id);
+                self.assets.report(AssetError::from_error(&result.id, &err));
+            }
+        }
+
+        count
+    }
+
+    /// True while background loads are still in flight
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_loading()
+    }
+
+    pub fn loading_progress(&self) -> LoadProgress {
+        self.loader.progress()
+    }
+
+    /// Draw a loading bar instead of the world until turned off
+    pub fn set_loading_screen(&mut self, show: bool) {
+        self.loading_screen = show;
+    }
+
+    fn asset_path(&self, id: &str) -> Result<String, anyhow::Error> {
+        match self.assets.resolve(id) {
+            Some(entry) => Ok(entry.path.to_string_lossy().into_owned()),
+            None => Err(AssetError::NotInManifest { id: String::from(id) }.into())
+        }
+    }
+
+    /// Free the GPU resources of assets nobody holds a handle to, textures still drawn by a sprite or background are kept
+    /// Call it after this frame's sprites are queued and before rendering, so textures only drawn with `draw_sprite` count too
+    pub fn collect_unused_assets(&mut self) -> Vec<(String, AssetKind)> {
+        let in_use: HashSet<&str> = self.dynamic_sprite_map.values().chain(&self.sprite_queue).map(|sprite| sprite.bind_group_key.as_str())
+            .chain(self.background_layers.iter().map(|state| state.layer.texture_key.as_str()))
+            .chain(self.text_map.values().map(|text| text.font_key.as_str()))
+            .collect();
+
+        let unused = self.assets.collect_unused(|id| in_use.contains(id));
+
+        for (id, kind) in &unused {
+            match kind {
+                AssetKind::Texture | AssetKind::Atlas => {
+                    self.bind_group_map.remove(id);
+                    self.atlas_map.remove(id);
+                    self.texture_sources.remove(id);
+                }
+                AssetKind::Font => self.remove_font(id),
+                _ => {}
+            }
+        }
+
+        unused
+    }
+
+    /// Load a texture from disk so sprites can reference it by key, failures are logged and the missing texture is drawn instead
+    pub fn load_texture(&mut self, key: &str, path_to_img: &str) -> Result<(), anyhow::Error> {
+        let bind_group = internal::TextureBindGroup::new(&self.device, &self.queue, &self.bind_group_layout, String::from(path_to_img), String::from(key))
+            .inspect_err(|err| self.assets.report(AssetError::from_error(key, err)))?;
+        self.bind_group_map.insert(String::from(key), bind_group);
+        // A plain texture replacing an atlas no longer has frames
+        self.atlas_map.remove(key);
+        self.texture_sources.insert(String::from(key), TextureSource { path: PathBuf::from(path_to_img), layout: None });
+        Ok(())
+    }
+
+    /// Add a texture made in code (UI frames, generated art...) so sprites can reference it by key
+    pub fn insert_texture(&mut self, key: &str, image: &image::RgbaImage) {
+        let bind_group = internal::TextureBindGroup::from_image(&self.device, &self.queue, &self.bind_group_layout, image, key);
+        self.bind_group_map.insert(String::from(key), bind_group);
+        self.atlas_map.remove(key);
+        self.texture_sources.remove(key);
+    }
+
+    /// Get the size of a loaded texture in pixels
+    pub fn get_texture_size(&self, key: &str) -> Option<[u32; 2]> {
+        self.bind_group_map.get(key).map(|bind_group| [bind_group.width, bind_group.height])
+    }
+
+    /// Load a sprite sheet, its frames share one texture so they can be drawn in a single batch
+    pub fn load_atlas(&mut self, key: &str, path_to_img: &str, layout: AtlasLayout) -> Result<(), anyhow::Error> {
+        let loaded = internal::TextureBindGroup::new(&self.device, &self.queue, &self.bind_group_layout, String::from(path_to_img), String::from(key))
+            .and_then(|bind_group| {
+                let atlas: TextureAtlas = TextureAtlas::new(String::from(key), bind_group.width, bind_group.height, &layout)?;
+                Ok((bind_group, atlas))
+            });
+
+        let (bind_group, atlas) = loaded.inspect_err(|err| self.assets.report(AssetError::from_error(key, err)))?;
+        self.bind_group_map.insert(String::from(key), bind_group);
+        self.atlas_map.insert(String::from(key), atlas);
+        self.texture_sources.insert(String::from(key), TextureSource { path: PathBuf::from(path_to_img), layout: Some(layout) });
+        Ok(())
+    }
+
+    /// Load a font listed in the asset manifest, `.fnt` files are BMFonts and anything else is TrueType
+    /// A TrueType font's pixel size comes after its path in the manifest, e.g. `font chat assets/fonts/chat.ttf 16`
+    pub fn load_font_asset(&mut self, id: &str) -> Result<Handle<assets::Font>, anyhow::Error> {
+        let (handle, needs_load) = self.assets.acquire::<assets::Font>(id)
+            .inspect_err(|err| self.assets.report(AssetError::from_error(id, err)))?;
+
+        if needs_load {
+            let loaded = self.asset_path(id).and_then(|path| {
+                let options = self.assets.resolve(id).and_then(|entry| entry.options.clone());
+                let size: f32 = match options {
+                    Some(options) => options.trim().parse().map_err(|_| anyhow::anyhow!("Invalid font size '{}'", options))?,
+                    None => DEFAULT_FONT_SIZE
+                };
+                self.load_font(id, &path, size)
+            });
+
+            if let Err(err) = loaded {
+                self.assets.forget(id);
+                self.assets.report(AssetError::from_error(id, &err));
+                return Err(err);
+            }
+        }
+
+        Ok(handle)
+    }
+
+    /// Load a font so text can be drawn with it, `.fnt` files are BMFonts (text format) and anything else is TrueType rasterized at `size` pixels
+    pub fn load_font(&mut self, key: &str, path_to_font: &str, size: f32) -> Result<(), anyhow::Error> {
+        let path: &Path = Path::new(path_to_font);
+        let is_bitmap: bool = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("fnt"));
+
+        let font: FontFace = if is_bitmap {
+            let (font, pages) = FontFace::bitmap(path).inspect_err(|err| self.assets.report(AssetError::from_error(key, err)))?;
+            // Pages are plain textures, so they hot reload and fall back to the missing texture like any other
+            for (page, page_path) in pages.iter().enumerate() {
+                self.load_texture(&text::page_key(key, page), &page_path.to_string_lossy())?;
+            }
+            font
+        } else {
+            FontFace::truetype(path, size).inspect_err(|err| self.assets.report(AssetError::from_error(key, err)))?
+        };
+
+        self.remove_font(key);
+        self.font_map.insert(String::from(key), font);
+        self.upload_glyph_atlas(key);
+
+        // Text already using the font is laid out again with the new glyphs
+        let texts: Vec<String> = self.text_map.iter().filter(|(_key, text)| text.font_key == key).map(|(key, _text)| key.clone()).collect();
+        for text_key in texts {
+            self.layout_text(&text_key);
+        }
+
+        Ok(())
+    }
+
+    // Drop a font and its page textures
+    fn remove_font(&mut self, key: &str) {
+        if let Some(font) = self.font_map.remove(key) {
+            for page in 0..font.page_count() {
+                let page_key = text::page_key(key, page);
+                self.bind_group_map.remove(&page_key);
+                self.texture_sources.remove(&page_key);
+            }
+        }
+    }
+
+    // Upload a TrueType font's glyph atlas if new glyphs were rasterized into it
+    fn upload_glyph_atlas(&mut self, font_key: &str) {
+        let atlas = self.font_map.get_mut(font_key).and_then(|font| font.take_dirty_atlas());
+        if let Some(atlas) = atlas {
+            let page_key: String = text::page_key(font_key, 0);
+            let bind_group = internal::TextureBindGroup::from_image(&self.device, &self.queue, &self.bind_group_layout, atlas, &page_key);
+            self.bind_group_map.insert(page_key, bind_group);
+        }
+    }
+
+    /// Spawn a string of text, drawn in screen space on the UI layer until moved to another layer
+    pub fn spawn_text(&mut self, key: &str, font_key: &str, text: &str, position: [f32; 2], style: TextStyle) -> Result<(), anyhow::Error> {
+        if !self.font_map.contains_key(font_key) {
+            return Err(anyhow::anyhow!("Font '{}' not loaded", font_key));
+        }
+
+        self.text_map.insert(String::from(key), TextSprite {
+            font_key: String::from(font_key),
+            text: String::from(text),
+            style,
+            position,
+            layer: Layer::Ui,
+            z: 0,
+            quads: Vec::new(),
+            size: [0.0, 0.0],
+        });
+        self.layout_text(key);
+        Ok(())
+    }
+
+    /// Change what a text says
+    pub fn set_text(&mut self, key: &str, text: &str) -> Result<(), anyhow::Error> {
+        match self.text_map.get_mut(key) {
+            Some(text_sprite) => {
+                // Chat and counters set the same string every frame, only lay it out when it changes
+                if text_sprite.text != text {
+                    text_sprite.text = String::from(text);
+                    self.layout_text(key);
+                }
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Text '{}' not found", key))
+        }
+    }
+
+    /// Change a text's color, size, wrapping, alignment or outline
+    pub fn set_text_style(&mut self, key: &str, style: TextStyle) -> Result<(), anyhow::Error> {
+        match self.text_map.get_mut(key) {
+            Some(text_sprite) => {
+                text_sprite.style = style;
+                self.layout_text(key);
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Text '{}' not found", key))
+        }
+    }
+
+    pub fn set_text_position(&mut self, key: &str, position: [f32; 2]) -> Result<(), anyhow::Error> {
+        match self.text_map.get_mut(key) {
+            Some(text_sprite) => {
+                text_sprite.position = position;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Text '{}' not found", key))
+        }
+    }
+
+    /// Set which layer a text is drawn in, text outside the UI layer is in world space and tinted like sprites
+    pub fn set_text_layer(&mut self, key: &str, layer: Layer, z: i32) -> Result<(), anyhow::Error> {
+        match self.text_map.get_mut(key) {
+            Some(text_sprite) => {
+                text_sprite.layer = layer;
+                text_sprite.z = z;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Text '{}' not found", key))
+        }
+    }
+
+    /// Get the size of a text as laid out, in pixels
+    pub fn get_text_size(&self, key: &str) -> Option<[f32; 2]> {
+        self.text_map.get(key).map(|text_sprite| text_sprite.size)
+    }
+
+    /// Measure a string without drawing it, in pixels
+    pub fn measure_text(&mut self, font_key: &str, text: &str, style: &TextStyle) -> Option<[f32; 2]> {
+        let (_quads, size) = self.font_map.get_mut(font_key)?.layout(text, style);
+        self.upload_glyph_atlas(font_key);
+        Some(size)
+    }
+
+    /// Draw part of a texture (`uv_rect`) into `rect` (x, y, width, height in logical pixels) on top of everything, for this frame only
+    /// Anything outside `clip` is cut off, this is what immediate mode UI is built on
+    pub fn draw_ui_rect(&mut self, texture_key: &str, rect: [f32; 4], uv_rect: [f32; 4], color: [f32; 4], clip: Option<[f32; 4]>) {
+        let mut instance: SpriteInstance = SpriteInstance::new([rect[0] + rect[2] / 2.0, rect[1] + rect[3] / 2.0], [rect[2], rect[3]], 0.0);
+        instance.uv_rect = uv_rect;
+        instance.color = color;
+
+        let instance: Option<SpriteInstance> = match clip {
+            Some(clip) => instance.clipped(clip),
+            None => Some(instance)
+        };
+        if let Some(instance) = instance {
+            self.ui_queue.push((String::from(texture_key), instance));
+        }
+    }
+
+    /// Draw a string on top of everything for this frame only, returns its size
+    pub fn draw_ui_text(&mut self, font_key: &str, text: &str, position: [f32; 2], style: &TextStyle, clip: Option<[f32; 4]>) -> Result<[f32; 2], anyhow::Error> {
+        let font = match self.font_map.get_mut(font_key) {
+            Some(font) => font,
+            None => return Err(anyhow::anyhow!("Font '{}' not loaded", font_key))
+        };
+
+        let (quads, size) = font.layout(text, style);
+        let text_sprite: TextSprite = TextSprite {
+            font_key: String::from(font_key),
+            text: String::from(text),
+            style: style.clone(),
+            position,
+            layer: Layer::Ui,
+            z: 0,
+            quads,
+            size,
+        };
+
+        for (page, instance) in text_sprite.instances(font) {
+            let instance: Option<SpriteInstance> = match clip {
+                Some(clip) => instance.clipped(clip),
+                None => Some(instance)
+            };
+            if let Some(instance) = instance {
+                self.ui_queue.push((text::page_key(font_key, page), instance));
+            }
+        }
+
+        self.upload_glyph_atlas(font_key);
+        Ok(size)
+    }
+
+    /// Remove a text, returns false if it didn't exist
+    pub fn remove_text(&mut self, key: &str) -> bool {
+        self.text_map.remove(key).is_some()
+    }
+
+    // Lay a text out again after its string, style or font changed
+    fn layout_text(&mut self, key: &str) {
+        let text_sprite = match self.text_map.get_mut(key) {
+            Some(text_sprite) => text_sprite,
+            None => return
+        };
+
+        if let Some(font) = self.font_map.get_mut(&text_sprite.font_key) {
+            (text_sprite.quads, text_sprite.size) = font.layout(&text_sprite.text, &text_sprite.style);
+            let font_key: String = text_sprite.font_key.clone();
+            self.upload_glyph_atlas(&font_key);
+        }
+    }
+
+    /// Watch the asset and shader folders and reload whatever changes on disk, meant for development
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.watcher = enabled.then(|| FileWatcher::new(&[Path::new(assets::ASSET_DIR), Path::new(SHADER_DIR)]));
+    }
+
+    pub fn is_hot_reloading(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Reload textures, atlases and shaders whose files changed, returns how many were reloaded
+    pub fn poll_hot_reload(&mut self) -> usize {
+        let changed: Vec<PathBuf> = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => return 0
+        };
+        let mut count = 0;
+
+        for path in changed {
+            // A broken shader keeps the old pipeline, so a typo doesn't take the game down
+            if path.starts_with(SHADER_DIR) {
+                match self.reload_shader(&path) {
+                    Ok(true) => count += 1,
+                    Ok(false) => {}
+                    Err(err) => println!("Shader '{}' not reloaded: {}", path.display(), err)
+                }
+                continue;
+            }
+
+            if path == Path::new(assets::MANIFEST_PATH) {
+                match self.assets.load_manifest(&path) {
+                    Ok(_) => count += 1,
+                    Err(err) => println!("Asset manifest not reloaded: {}", err)
+                }
+                continue;
+            }
+
+            // An atlas also changes when its JSON file does
+            let keys: Vec<String> = self.texture_sources.iter()
+                .filter(|(_key, source)| source.path == path || matches!(&source.layout, Some(AtlasLayout::Json(json)) if *json == path))
+                .map(|(key, _source)| key.clone())
+                .collect();
+
+            for key in keys {
+                match self.reload_texture(&key) {
+                    Ok(_) => count += 1,
+                    // The old texture is kept, so the missing texture isn't needed
+                    Err(err) => self.assets.report(AssetError::from_error(&key, &err))
+                }
+            }
+        }
+
+        count
+    }
+
+    // Rebuild a texture's bind group (and atlas) from its file, the old one is kept if the file can't be read
+    fn reload_texture(&mut self, key: &str) -> Result<(), anyhow::Error> {
+        let source = match self.texture_sources.get(key) {
+            Some(source) => source,
+            None => return Err(anyhow::anyhow!("Texture '{}' was not loaded from a file", key))
+        };
+
+        let rgba = internal::decode_image(&source.path.to_string_lossy())?;
+        let bind_group = internal::TextureBindGroup::from_image(&self.device, &self.queue, &self.bind_group_layout, &rgba, key);
+
+        if let Some(layout) = &source.layout {
+            let atlas: TextureAtlas = TextureAtlas::new(String::from(key), bind_group.width, bind_group.height, layout)?;
+            self.atlas_map.insert(String::from(key), atlas);
+        }
+
+        self.bind_group_map.insert(String::from(key), bind_group);
+        println!("Reloaded texture '{}'", key);
+        Ok(())
+    }
+
+    // Recreate the pipelines that use a shader file, returns false if no pipeline uses it
+    fn reload_shader(&mut self, path: &Path) -> Result<bool, anyhow::Error> {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name != Some(STATIC_SHADER_FILE) && file_name != Some(DYNAMIC_SHADER_FILE) {
+            return Ok(false);
+        }
+
+        let source = std::fs::read_to_string(path)?;
+
+        // Invalid WGSL doesn't fail on the spot, the error is caught by the scope instead
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let (static_render_pipeline, dynamic_render_pipelines) = if file_name == Some(STATIC_SHADER_FILE) {
+            (Some(create_static_render_pipeline(&self.device, &self.bind_group_layout, &self.uniform_bind_group_layout, &self.config, &source)), None)
+        } else {
+            (None, Some(create_dynamic_render_pipelines(&self.device, &self.bind_group_layout, &self.uniform_bind_group_layout, &self.config, &source)))
+        };
+
+        if let Some(err) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(anyhow::anyhow!("{}", err));
+        }
+
+        if let Some(pipeline) = static_render_pipeline {
+            self.static_render_pipeline = pipeline;
+        }
+        if let Some(pipelines) = dynamic_render_pipelines {
+            self.dynamic_render_pipelines = pipelines;
+        }
+
+        println!("Reloaded shader '{}'", path.display());
+        Ok(true)
+    }
+
+    /// Get the pixel rect of an atlas frame, handy for sizing sprites
+    pub fn get_atlas_frame(&self, atlas_key: &str, frame: &str) -> Option<AtlasFrame> {
+        self.atlas_map.get(atlas_key).and_then(|atlas| atlas.frame(frame)).copied()
+    }
+
+    /// Spawn a dynamic sprite that can be moved, scaled and rotated after creation
+    /// The texture doesn't have to be loaded yet, the missing texture is drawn until it is
+    pub fn spawn_sprite(&mut self, key: &str, texture_key: &str, position: [f32; 2], scale: [f32; 2], rotation: f32) {
+        let sprite: internal::DynamicSprite = internal::DynamicSprite {
+            bind_group_key: String::from(texture_key),
+            instance: SpriteInstance::new(position, scale, rotation),
+            layer: Layer::World,
+            z: 0,
+            blend_mode: BlendMode::Alpha,
         };
+        self.dynamic_sprite_map.insert(String::from(key), sprite);
+    }
+
+    /// Draw a sprite for this frame only, for things that own their state elsewhere like entities
+    /// `frame` picks a frame of the atlas the texture is from, sprites queued in the same spot draw in the order they were queued
+    pub fn draw_sprite(&mut self, texture_key: &str, frame: Option<&str>, mut instance: SpriteInstance, layer: Layer, z: i32, blend_mode: BlendMode) {
+        if let Some(frame) = frame
+            && let Some(uv_rect) = self.atlas_map.get(texture_key).and_then(|atlas| atlas.uv_rect(frame)) {
+            instance.uv_rect = uv_rect;
+        }
+
+        self.sprite_queue.push(internal::DynamicSprite {
+            bind_group_key: String::from(texture_key),
+            instance,
+            layer,
+            z,
+            blend_mode,
+        });
+    }
+
+    /// Update the transform of a dynamic sprite, takes effect on the next render
+    pub fn set_sprite_transform(&mut self, key: &str, position: [f32; 2], scale: [f32; 2], rotation: f32) -> Result<(), anyhow::Error> {
+        match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => {
+                sprite.instance.position = position;
+                sprite.instance.scale = scale;
+                sprite.instance.rotation = rotation;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        }
+    }
+
+    /// Spawn a dynamic sprite showing a single frame of an atlas
+    pub fn spawn_atlas_sprite(&mut self, key: &str, atlas_key: &str, frame: &str, position: [f32; 2], scale: [f32; 2], rotation: f32) -> Result<(), anyhow::Error> {
+        // Check the frame first so a bad name doesn't leave a sprite showing the whole sheet
+        match self.atlas_map.get(atlas_key) {
+            Some(atlas) if atlas.frame(frame).is_none() => return Err(anyhow::anyhow!("Frame '{}' not found in atlas '{}'", frame, atlas_key)),
+            None if self.bind_group_map.contains_key(atlas_key) => return Err(anyhow::anyhow!("Texture '{}' is not an atlas", atlas_key)),
+            _ => {}
+        }
 
-        grafx
+        self.spawn_sprite(key, atlas_key, position, scale, rotation);
+        self.set_sprite_frame(key, frame)
+    }
+
+    /// Switch a dynamic sprite to another frame of the atlas it was spawned from
+    pub fn set_sprite_frame(&mut self, key: &str, frame: &str) -> Result<(), anyhow::Error> {
+        let sprite = match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => sprite,
+            None => return Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        };
+
+        let atlas = match self.atlas_map.get(&sprite.bind_group_key) {
+            Some(atlas) => atlas,
+            // The atlas is still loading or failed to, the whole missing texture is drawn meanwhile
+            None if !self.bind_group_map.contains_key(&sprite.bind_group_key) => return Ok(()),
+            None => return Err(anyhow::anyhow!("Sprite '{}' does not use an atlas", key))
+        };
+
+        match atlas.uv_rect(frame) {
+            Some(uv_rect) => {
+                sprite.instance.uv_rect = uv_rect;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Frame '{}' not found in atlas '{}'", frame, atlas.bind_group_key))
+        }
+    }
+
+    /// Show the animator's current frame on a dynamic sprite
+    pub fn apply_animator(&mut self, key: &str, animator: &Animator) -> Result<(), anyhow::Error> {
+        match animator.current_frame() {
+            Some(frame) => self.set_sprite_frame(key, frame),
+            None => Ok(())
+        }
+    }
+
+    /// Set which layer a dynamic sprite is drawn in, higher z draws on top within the layer
+    pub fn set_sprite_layer(&mut self, key: &str, layer: Layer, z: i32) -> Result<(), anyhow::Error> {
+        match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => {
+                sprite.layer = layer;
+                sprite.z = z;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        }
+    }
+
+    /// Set how a dynamic sprite blends with what is drawn beneath it
+    pub fn set_sprite_blend_mode(&mut self, key: &str, blend_mode: BlendMode) -> Result<(), anyhow::Error> {
+        match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => {
+                sprite.blend_mode = blend_mode;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        }
+    }
+
+    /// Set the color a dynamic sprite's texture is multiplied by
+    pub fn set_sprite_color(&mut self, key: &str, color: [f32; 4]) -> Result<(), anyhow::Error> {
+        match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => {
+                sprite.instance.color = color;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        }
+    }
+
+    /// Move a dynamic sprite without changing its scale or rotation
+    pub fn set_sprite_position(&mut self, key: &str, position: [f32; 2]) -> Result<(), anyhow::Error> {
+        match self.dynamic_sprite_map.get_mut(key) {
+            Some(sprite) => {
+                sprite.instance.position = position;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Sprite '{}' not found", key))
+        }
+    }
+
+    /// Get the world position of a dynamic sprite
+    pub fn get_sprite_position(&self, key: &str) -> Option<[f32; 2]> {
+        self.dynamic_sprite_map.get(key).map(|sprite| sprite.instance.position)
+    }
+
+    pub fn camera(&self) -> &Camera2D {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera2D {
+        &mut self.camera
+    }
+
+    /// Have the camera smoothly follow a dynamic sprite, None stops following
+    pub fn follow_sprite(&mut self, key: Option<&str>) {
+        self.camera_follow = key.map(String::from);
+        if self.camera_follow.is_none() {
+            self.camera.set_target(None);
+        }
+    }
+
+    /// Advance the camera by `dt` seconds of game time
+    pub fn update_camera(&mut self, dt: f32) {
+        if let Some(key) = &self.camera_follow {
+            let target = self.get_sprite_position(key);
+            self.camera.set_target(target);
+        }
+        self.camera.update(dt);
+    }
+
+    /// Convert a cursor position in physical window pixels to logical pixels, accounting for letterboxing
+    pub fn screen_to_logical(&self, position: (f64, f64)) -> [f32; 2] {
+        self.viewport.physical_to_logical([position.0 as f32, position.1 as f32], self.camera.viewport())
+    }
+
+    /// Convert a cursor position in physical window pixels to world space
+    pub fn screen_to_world(&self, position: (f64, f64)) -> [f32; 2] {
+        self.camera.screen_to_world(self.screen_to_logical(position))
+    }
+
+    /// Remove a dynamic sprite, returns false if it didn't exist
+    pub fn remove_sprite(&mut self, key: &str) -> bool {
+        self.dynamic_sprite_map.remove(key).is_some()
+    }
+
+    // Queue a loading bar centered on the screen
+    fn push_loading_bar(&mut self) {
+        let viewport = self.camera.viewport();
+        let progress = self.loader.progress().fraction();
+        let width = viewport[0] * 0.5;
+        let height = 16.0;
+        let center = [viewport[0] / 2.0, viewport[1] / 2.0];
+
+        let mut track: SpriteInstance = SpriteInstance::new(center, [width, height], 0.0);
+        track.color = [0.2, 0.2, 0.2, 1.0];
+
+        let mut fill: SpriteInstance = SpriteInstance::new([center[0] - width * (1.0 - progress) / 2.0, center[1]], [width * progress, height], 0.0);
+        fill.color = [1.0, 1.0, 1.0, 1.0];
+
+        self.sprite_batch.push(WHITE_TEXTURE_KEY, BlendMode::Alpha, true, track);
+        self.sprite_batch.push(WHITE_TEXTURE_KEY, BlendMode::Alpha, true, fill);
     }
 
     /// Render a single frame
     pub fn render(&mut self) -> Result<(), anyhow::Error> {
-        let output = self.surface.get_current_texture().unwrap();
+        self.sprite_batch.begin();
+
+        if self.loading_screen {
+            self.push_loading_bar();
+        } else {
+            // Sort dynamic sprites and text into draw order, things at the same depth are grouped by texture so they share a draw call
+            // The key is the final tie breaker so the order never depends on HashMap iteration
+            let mut drawables: Vec<(Layer, i32, String, &String, Drawable)> = self.dynamic_sprite_map.iter()
+                .map(|(key, sprite)| (sprite.layer, sprite.z, sprite.bind_group_key.clone(), key, Drawable::Sprite(sprite)))
+                .chain(self.sprite_queue.iter().map(|sprite| (sprite.layer, sprite.z, sprite.bind_group_key.clone(), &sprite.bind_group_key, Drawable::Sprite(sprite))))
+                .chain(self.text_map.iter().filter_map(|(key, text_sprite)| {
+                    let font = self.font_map.get(&text_sprite.font_key)?;
+                    Some((text_sprite.layer, text_sprite.z, text::page_key(&text_sprite.font_key, 0), key, Drawable::Text(text_sprite, font)))
+                }))
+                .collect();
+            // The sort is stable, so queued sprites that tie keep their queue order
+            drawables.sort_by(|a, b| (a.0, a.1, &a.2, a.3).cmp(&(b.0, b.1, &b.2, b.3)));
+
+            for (layer, _z, _texture_key, _key, drawable) in drawables {
+                let screen_space: bool = layer == Layer::Ui;
+
+                let instances: Vec<(String, BlendMode, SpriteInstance)> = match drawable {
+                    Drawable::Sprite(sprite) => vec![(sprite.bind_group_key.clone(), sprite.blend_mode, sprite.instance)],
+                    Drawable::Text(text_sprite, font) => text_sprite.instances(font).into_iter()
+                        .map(|(page, instance)| (text::page_key(&text_sprite.font_key, page), BlendMode::Alpha, instance))
+                        .collect()
+                };
+
+                for (texture_key, blend_mode, mut instance) in instances {
+                    if !screen_space {
+                        for channel in 0..4 {
+                            instance.color[channel] *= self.world_tint[channel];
+                        }
+                    }
+                    self.sprite_batch.push(&texture_key, blend_mode, screen_space, instance);
+                }
+            }
+
+            // Immediate mode UI goes over everything, in the order it was drawn
+            for (texture_key, instance) in &self.ui_queue {
+                self.sprite_batch.push(texture_key, BlendMode::Alpha, true, *instance);
+            }
+        }
+
+        self.sprite_batch.finish(&self.device, &self.queue);
+        self.sprite_queue.clear();
+        self.ui_queue.clear();
 
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.view_uniform.write(&self.queue, &internal::ViewUniform { view_proj: self.camera.view_projection() });
+
+        // Scroll each background layer with the camera
+        for state in &self.background_layers {
+            if let Some(bind_group) = self.texture_or_missing(&state.layer.texture_key) {
+                state.uniform.write(&self.queue, &state.layer.uniform(&self.camera, bind_group.width, bind_group.height, self.world_tint));
+            }
+        }
+
+        // Textures that are neither loaded nor loading are broken, log them once
+        let missing: Vec<String> = self.background_layers.iter().map(|state| &state.layer.texture_key)
+            .chain(self.dynamic_sprite_map.values().chain(&self.sprite_queue).map(|sprite| &sprite.bind_group_key))
+            .filter(|key| !self.bind_group_map.contains_key(*key) && !self.assets.is_loaded(key))
+            .cloned()
+            .collect();
+        for id in missing {
+            self.assets.report(AssetError::Missing { id });
+        }
+
+        let output: Option<wgpu::SurfaceTexture> = match &self.surface {
+            Some(surface) => match surface.get_current_texture() {
+                Ok(output) => Some(output),
+                // The surface no longer matches the window, reconfigure it and try again next frame
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    surface.configure(&self.device, &self.config);
+                    return Ok(());
+                }
+                // The GPU took too long to hand over a frame, just skip this one
+                Err(wgpu::SurfaceError::Timeout) => {
+                    return Ok(());
+                }
+                Err(err) => {
+                    return Err(anyhow::anyhow!("Failed to get surface texture: {}", err));
+                }
+            }
+            None => None
+        };
+
+        let view = match (&output, &self.offscreen) {
+            (Some(output), _) => output.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, Some(offscreen)) => offscreen.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, None) => return Err(anyhow::anyhow!("Nothing to render to"))
+        };
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
@@ -131,33 +1179,233 @@ impl Grafx {
             timestamp_writes: None,
         });
 
+        // Everything is drawn into the letterboxed area, the clear color fills the bars
+        render_pass.set_viewport(self.viewport.x, self.viewport.y, self.viewport.width, self.viewport.height, 0.0, 1.0);
+
         render_pass.set_pipeline(&self.static_render_pipeline);
 
-        let mut rendered = false;
+        // The loading screen hides the world, including the background
+        let background_layers: &[BackgroundLayerState] = if self.loading_screen { &[] } else { &self.background_layers };
 
-        if let Some(sprite) = self.sprite_map.get("background") {
-            if let Some(bind_group) = self.bind_group_map.get(&sprite.bind_group_key) {
-                sprite.draw(&mut render_pass, bind_group.get_bind_group());
-                rendered = true;
+        for state in background_layers {
+            if let Some(bind_group) = self.texture_or_missing(&state.sprite.bind_group_key) {
+                render_pass.set_bind_group(1, &state.uniform.bind_group, &[]);
+                state.sprite.draw(&mut render_pass, bind_group.get_bind_group());
             }
         }
 
+        // Dynamic sprites are drawn on top of the static background, the batch picks the pipeline for each blend mode
+        self.sprite_batch.draw(&mut render_pass, &self.bind_group_map, &self.dynamic_render_pipelines, &self.view_uniform.bind_group, &self.screen_view_uniform.bind_group);
+
         drop(render_pass);
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
 
-        if rendered {
-            return Ok(());
+        // Copy the frame back before it is presented if a screenshot or recording wants it
+        if self.capture.wants_frame() {
+            let texture: Option<&wgpu::Texture> = output.as_ref().map(|output| &output.texture).or(self.offscreen.as_ref());
+            if let Some(texture) = texture {
+                match internal::read_texture(&self.device, &self.queue, texture) {
+                    Ok(image) => if let Err(err) = self.capture.submit(image) {
+                        println!("Failed to capture frame: {}", err);
+                    }
+                    // Every later frame would fail the same way, so give up on the capture
+                    Err(err) => {
+                        println!("Failed to capture frame: {}", err);
+                        self.capture.cancel();
+                    }
+                }
+            }
+        }
+
+        // Recordings finish writing in the background after they stop
+        for finished in self.capture.poll_finished() {
+            match finished {
+                Ok(0) => println!("Recording saved"),
+                Ok(dropped) => println!("Recording saved, {} frames were dropped because encoding fell behind", dropped),
+                Err(err) => println!("Failed to save recording: {}", err)
+            }
         }
 
-        return Err(anyhow::anyhow!("Sprite 'background' not found"));
+        if let Some(output) = output {
+            output.present();
+        }
 
+        Ok(())
     }
 
-    /// Get the game window
-    pub fn get_window(&self) -> &Window {
-        &self.window
+    // Get a texture, or the missing texture if it isn't loaded
+    fn texture_or_missing(&self, key: &str) -> Option<&internal::TextureBindGroup> {
+        self.bind_group_map.get(key).or_else(|| self.bind_group_map.get(MISSING_TEXTURE_KEY))
+    }
+
+    /// Every distinct asset failure so far, oldest first
+    pub fn asset_errors(&self) -> &[AssetError] {
+        self.assets.errors()
+    }
+
+    /// Copy the last rendered frame back as an RGBA image, only headless renderers can do this
+    pub fn read_frame(&self) -> Result<image::RgbaImage, anyhow::Error> {
+        match &self.offscreen {
+            Some(offscreen) => internal::read_texture(&self.device, &self.queue, offscreen),
+            None => Err(anyhow::anyhow!("Only headless renderers can read frames back"))
+        }
     }
-}
\ No newline at end of file
+
+    /// Save the next rendered frame to a PNG
+    pub fn request_screenshot(&mut self, path: &Path) {
+        self.capture.request_screenshot(path);
+    }
+
+    /// Record rendered frames to a GIF or PNG sequence, stops by itself after `max_frames`
+    pub fn start_recording(&mut self, path: &Path, format: CaptureFormat, max_frames: usize) -> Result<(), anyhow::Error> {
+        let frame_delay_ms: u32 = (1000.0 / config::FRAMERATE).round() as u32;
+        self.capture.start_recording(path, format, max_frames, frame_delay_ms)
+    }
+
+    /// Stop recording, the frames already captured keep being written in the background
+    pub fn stop_recording(&mut self) -> Result<(), anyhow::Error> {
+        self.capture.stop_recording()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.capture.is_recording()
+    }
+
+    /// Get the game window, None for headless renderers
+    pub fn get_window(&self) -> Option<&Window> {
+        self.window.as_deref()
+    }
+}
+
+// Create the pipeline that draws background layers from WGSL source
+fn create_static_render_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, uniform_bind_group_layout: &wgpu::BindGroupLayout, config: &wgpu::SurfaceConfiguration, source: &str) -> wgpu::RenderPipeline {
+    let shader: wgpu::ShaderModule = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Static Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let layout: wgpu::PipelineLayout = internal::create_static_render_pipeline_layout(device, bind_group_layout, uniform_bind_group_layout);
+    internal::create_render_pipeline(device, &layout, &shader, &[internal::Vertex::desc()], config, BlendMode::Alpha)
+}
+
+// Create one sprite batch pipeline per blend mode from WGSL source
+fn create_dynamic_render_pipelines(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, uniform_bind_group_layout: &wgpu::BindGroupLayout, config: &wgpu::SurfaceConfiguration, source: &str) -> HashMap<BlendMode, wgpu::RenderPipeline> {
+    let shader: wgpu::ShaderModule = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Dynamic Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let layout: wgpu::PipelineLayout = internal::create_dynamic_render_pipeline_layout(device, bind_group_layout, uniform_bind_group_layout);
+    BlendMode::ALL.iter()
+        .map(|blend_mode| (*blend_mode, internal::create_render_pipeline(device, &layout, &shader, &[internal::Vertex::desc(), SpriteInstance::desc()], config, *blend_mode)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    /// A headless renderer, None on machines with no GPU or software adapter at all
+    fn headless(width: u32, height: u32) -> Option<Grafx> {
+        match pollster::block_on(Grafx::new_headless(width, height)) {
+            Ok(grafx) => Some(grafx),
+            Err(err) => {
+                println!("Skipping, no headless renderer: {}", err);
+                None
+            }
+        }
+    }
+
+    /// A 2x2 texture with a different color in each corner
+    fn quadrants() -> image::RgbaImage {
+        image::RgbaImage::from_fn(2, 2, |x, y| image::Rgba([RED, GREEN, BLUE, WHITE][(y * 2 + x) as usize]))
+    }
+
+    fn pixel(frame: &image::RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        frame.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn draws_sprites_where_the_camera_sees_them() {
+        // A tenth of the logical resolution, so the world fills the frame exactly
+        let Some(mut grafx) = headless(128, 72) else { return };
+        grafx.insert_texture("quadrants", &quadrants());
+
+        let logical: [f32; 2] = [config::WINDOW_WIDTH as f32, config::WINDOW_HEIGHT as f32];
+        let instance = SpriteInstance::new([logical[0] / 2.0, logical[1] / 2.0], logical, 0.0);
+        grafx.draw_sprite("quadrants", None, instance, Layer::World, 0, BlendMode::Alpha);
+        grafx.render().unwrap();
+        let frame = grafx.read_frame().unwrap();
+
+        assert_eq!(frame.dimensions(), (128, 72));
+        assert_eq!(pixel(&frame, 32, 18), RED);
+        assert_eq!(pixel(&frame, 96, 18), GREEN);
+        assert_eq!(pixel(&frame, 32, 54), BLUE);
+        assert_eq!(pixel(&frame, 96, 54), WHITE);
+    }
+
+    #[test]
+    fn letterboxes_frames_of_another_shape() {
+        // Taller than 16:9, so the world is drawn in a band with black bars above and below
+        let Some(mut grafx) = headless(128, 96) else { return };
+        grafx.insert_texture("quadrants", &quadrants());
+
+        let logical: [f32; 2] = [config::WINDOW_WIDTH as f32, config::WINDOW_HEIGHT as f32];
+        let instance = SpriteInstance::new([logical[0] / 2.0, logical[1] / 2.0], logical, 0.0);
+        grafx.draw_sprite("quadrants", None, instance, Layer::World, 0, BlendMode::Alpha);
+        grafx.render().unwrap();
+        let frame = grafx.read_frame().unwrap();
+
+        assert_eq!(pixel(&frame, 64, 4), BLACK);
+        assert_eq!(pixel(&frame, 64, 91), BLACK);
+        assert_eq!(pixel(&frame, 32, 30), RED);
+        assert_eq!(pixel(&frame, 96, 66), WHITE);
+    }
+
+    #[test]
+    fn draws_the_missing_texture_for_unknown_keys() {
+        let Some(mut grafx) = headless(128, 72) else { return };
+
+        let logical: [f32; 2] = [config::WINDOW_WIDTH as f32, config::WINDOW_HEIGHT as f32];
+        let instance = SpriteInstance::new([logical[0] / 2.0, logical[1] / 2.0], logical, 0.0);
+        grafx.draw_sprite("not_loaded", None, instance, Layer::World, 0, BlendMode::Alpha);
+        grafx.render().unwrap();
+        let frame = grafx.read_frame().unwrap();
+
+        let colors: HashSet<[u8; 4]> = frame.pixels().map(|pixel| pixel.0).collect();
+        assert!(colors.contains(&[255, 0, 255, 255]), "expected magenta in {:?}", colors);
+        assert!(colors.contains(&BLACK));
+    }
+
+    #[test]
+    fn assets_acquired_again_while_loading_are_decoded_once() {
+        let Some(mut grafx) = headless(16, 16) else { return };
+        let path = std::env::temp_dir().join(format!("poprustica_requeue_{}.png", std::process::id()));
+        image::RgbaImage::from_pixel(2, 2, image::Rgba(RED)).save(&path).unwrap();
+        grafx.assets_mut().register(AssetKind::Texture, "requeued", &path, None);
+
+        // Let go of the texture and collect it before it arrives, then ask for it again
+        let handle = grafx.queue_texture_asset("requeued").unwrap();
+        drop(handle);
+        grafx.collect_unused_assets();
+        let handle = grafx.queue_texture_asset("requeued").unwrap();
+        assert_eq!(grafx.loading_progress().total, 1);
+
+        while grafx.is_loading() {
+            grafx.poll_loading();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(grafx.bind_group_map.contains_key(handle.id()));
+        assert!(grafx.assets().errors().is_empty());
+    }
+}
@@ -1,25 +1,49 @@
 // This files contains internal functions to be used by the Grafx module (mostly wgpu stuff)
-use std;
+use std::{self, path::PathBuf};
 use bytemuck;
-use image::GenericImageView;
 use anyhow::{Error, Result};
 use wgpu::util::DeviceExt;
 use wgpu;
 
-/// Create a GPU adapter, panics if one can't be created
-pub async fn create_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface<'static>) -> wgpu::Adapter {
+use super::batch::SpriteInstance;
+use super::layer::Layer;
+use crate::client::assets::AssetError;
+
+/// Create a GPU adapter that can draw to the surface
+pub async fn create_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface<'static>) -> Result<wgpu::Adapter, Error> {
     instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
+            compatible_surface: Some(surface),
             force_fallback_adapter: false,
         })
         .await
-        .expect("Failed to create GPU adapter")
+        .ok_or_else(|| anyhow::anyhow!("Failed to create GPU adapter"))
+}
+
+/// Create a GPU adapter with no surface for offscreen rendering, prefers the software fallback adapter
+pub async fn create_headless_adapter(instance: &wgpu::Instance) -> Result<wgpu::Adapter, Error> {
+    // The fallback adapter renders the same on every machine which is what golden image tests want,
+    // but not every platform ships one so fall back to whatever is available
+    for force_fallback_adapter in [true, false] {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await;
+
+        if let Some(adapter) = adapter {
+            return Ok(adapter);
+        }
+    }
+
+    Err(anyhow::anyhow!("Failed to create headless GPU adapter"))
 }
 
-/// Create a device and device queue, panics if they can't be created
-pub async fn create_device_and_queue(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+/// Create a device and device queue
+pub async fn create_device_and_queue(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), Error> {
     adapter
         .request_device(&wgpu::DeviceDescriptor{
             required_features: wgpu::Features::empty(),
@@ -29,12 +53,12 @@ pub async fn create_device_and_queue(adapter: &wgpu::Adapter) -> (wgpu::Device,
         }
         , None)
         .await
-        .expect("Failed to create GPU device and device queue")
+        .map_err(|err| anyhow::anyhow!("Failed to create GPU device and device queue: {}", err))
 }
 
 /// Create a surface configuration
 pub fn create_surface_configuration(adapter: &wgpu::Adapter, surface: &wgpu::Surface<'static>, size: &winit::dpi::PhysicalSize<u32>) -> wgpu::SurfaceConfiguration {
-    let surface_caps = surface.get_capabilities(&adapter);
+    let surface_caps = surface.get_capabilities(adapter);
 
     let surface_format = surface_caps.formats.iter()
         .find(|f| f.is_srgb())
@@ -42,7 +66,8 @@ pub fn create_surface_configuration(adapter: &wgpu::Adapter, surface: &wgpu::Sur
         .unwrap_or(surface_caps.formats[0]);
     
     wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        // Frames can only be captured if the surface texture can be copied from
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
         format: surface_format,
         width: size.width,
         height: size.height,
@@ -53,6 +78,118 @@ pub fn create_surface_configuration(adapter: &wgpu::Adapter, surface: &wgpu::Sur
     }
 }
 
+/// Create a configuration for rendering into an offscreen texture, the format matches what `read_texture` returns
+pub fn create_offscreen_configuration(width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+        desired_maximum_frame_latency: 2,
+    }
+}
+
+/// Create a texture to render into when there is no window
+pub fn create_offscreen_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
+}
+
+/// Copy a texture back from the GPU into an RGBA image, the texture needs `COPY_SRC` usage
+pub fn read_texture(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Result<image::RgbaImage, Error> {
+    // Some platforms don't let the window surface be copied from
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+        return Err(anyhow::anyhow!("This surface can't be read back"));
+    }
+
+    let width = texture.width();
+    let height = texture.height();
+
+    // Surfaces are often BGRA, those get swizzled into RGBA below
+    let bgra = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        format => return Err(anyhow::anyhow!("Can't read back texture format {:?}", format)),
+    };
+
+    // Rows copied into a buffer have to be padded out to a multiple of 256 bytes
+    let unpadded_bytes_per_row = 4 * width;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // Wait for the copy to finish and the buffer to be mapped
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let mut pixels: Vec<u8> = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if bgra {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("Readback buffer is the wrong size"))
+}
+
 /// Create a bind group layout to be used by bind groups for all sprites
 pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -80,13 +217,13 @@ pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout
     })
 }
 
-/// Create a bind group layout to be used by bind groups for dynamic sprites
+/// Create a bind group layout to be used by uniform buffers (camera, etc)
 pub fn create_uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,  // Only needed in vertex shader
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -95,22 +232,22 @@ pub fn create_uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGrou
                 count: None,
             }
         ],
-        label: Some("sprite_uniform_bind_group_layout"),
+        label: Some("uniform_bind_group_layout"),
     })
 }
 
 /// Create a render pipeline layout
-pub fn create_static_render_pipeline_layout(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::PipelineLayout {
+pub fn create_static_render_pipeline_layout(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::PipelineLayout {
     device.create_pipeline_layout(
         &wgpu::PipelineLayoutDescriptor {
             label: Some("Static Render Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[bind_group_layout, uniform_bind_group_layout],
             push_constant_ranges: &[],
         }
     )
 }
 
-pub fn create_dynamic_render_pipeline_layout(device: &wgpu::Device, texture_bind_group_layout: &wgpu::BindGroupLayout,uniform_bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::PipelineLayout {
+pub fn create_dynamic_render_pipeline_layout(device: &wgpu::Device, texture_bind_group_layout: &wgpu::BindGroupLayout, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::PipelineLayout {
     device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Dynamic Render Pipeline Layout"),
         bind_group_layouts: &[
@@ -121,23 +258,46 @@ pub fn create_dynamic_render_pipeline_layout(device: &wgpu::Device, texture_bind
     })
 }
 
+/// How a pipeline combines what it draws with what is already on screen
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Overwrite, transparent pixels come out black
+    Replace,
+    /// Standard alpha blending for textures with straight (non-premultiplied) alpha
+    Alpha,
+    /// Alpha blending for textures whose colors were already multiplied by alpha
+    PremultipliedAlpha,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 3] = [BlendMode::Replace, BlendMode::Alpha, BlendMode::PremultipliedAlpha];
+
+    pub fn blend_state(&self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Replace => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        }
+    }
+}
+
 /// Create a render pipeline
-pub fn create_render_pipeline(device: &wgpu::Device, render_pipeline_layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, config: &wgpu::SurfaceConfiguration) -> wgpu::RenderPipeline {
+pub fn create_render_pipeline(device: &wgpu::Device, render_pipeline_layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, buffers: &[wgpu::VertexBufferLayout], config: &wgpu::SurfaceConfiguration, blend_mode: BlendMode) -> wgpu::RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
+        layout: Some(render_pipeline_layout),
         vertex: wgpu::VertexState {
-            module: &shader,
+            module: shader,
             entry_point: Some("vs_main"),
-            buffers: &[Vertex::desc()],
+            buffers,
             compilation_options: wgpu::PipelineCompilationOptions::default(),
         },
         fragment: Some(wgpu::FragmentState {
-            module: &shader,
+            module: shader,
             entry_point: Some("fs_main"),
             targets: &[Some(wgpu::ColorTargetState {
                 format: config.format,
-                blend: Some(wgpu::BlendState::REPLACE),
+                blend: Some(blend_mode.blend_state()),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
             compilation_options: wgpu::PipelineCompilationOptions::default(),
@@ -146,7 +306,8 @@ pub fn create_render_pipeline(device: &wgpu::Device, render_pipeline_layout: &wg
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            // Sprites can be flipped with a negative scale, so back faces must still be drawn
+            cull_mode: None,
             // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
             polygon_mode: wgpu::PolygonMode::Fill,
             // Requires Features::DEPTH_CLIP_CONTROL
@@ -194,34 +355,77 @@ impl Vertex {
     }
 }
 
+/// Camera data passed to the dynamic shader, must match `ViewUniform` in dynamic.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ViewUniform {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+/// A uniform buffer and the bind group that exposes it to shaders
+pub struct UniformBuffer {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl UniformBuffer {
+    pub fn new<T: bytemuck::Pod>(device: &wgpu::Device, uniform_bind_group_layout: &wgpu::BindGroupLayout, contents: &T, label: &str) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(contents),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: Some(label),
+        });
+
+        Self { buffer, bind_group }
+    }
+
+    pub fn write<T: bytemuck::Pod>(&self, queue: &wgpu::Queue, contents: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(contents));
+    }
+}
+
 pub struct TextureBindGroup {
     pub bind_group: wgpu::BindGroup,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Read and decode an image from disk, this is CPU only work so it can run off the render thread
+pub fn decode_image(path_to_img: &str) -> Result<image::RgbaImage, Error> {
+    // Get image bytes
+    let img_bytes = std::fs::read(path_to_img)
+        .map_err(|err| AssetError::Read { path: PathBuf::from(path_to_img), message: err.to_string() })?;
+
+    if img_bytes.is_empty() {
+        return Err(AssetError::Read { path: PathBuf::from(path_to_img), message: String::from("the file is empty") }.into());
+    }
+
+    let img = image::load_from_memory(&img_bytes)
+        .map_err(|err| AssetError::Decode { path: PathBuf::from(path_to_img), message: err.to_string() })?;
+
+    Ok(img.to_rgba8())
 }
 
 impl TextureBindGroup {
     pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, bind_group_layout: &wgpu::BindGroupLayout, path_to_img: String, label: String) -> Result<Self, Error> {
-        // Get image bytes
-        let img_bytes = match std::fs::read(&path_to_img) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                println!("Failed to read file at '{}': {}", path_to_img, e);
-                return Err(e.into());
-            }
-        };
-    
-        if img_bytes.is_empty() {
-            println!("No data read from file at '{}'", path_to_img);
-            return Err(anyhow::anyhow!("Empty image file"));
-        }
-
-        let img = image::load_from_memory(&img_bytes)
-            .map_err(|err| {
-                println!("Failed to load image: {}", err);
-                err
-            })?;
+        let rgba = decode_image(&path_to_img)?;
+        Ok(Self::from_image(device, queue, bind_group_layout, &rgba, &label))
+    }
 
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+    /// Upload an already decoded image to the GPU
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, bind_group_layout: &wgpu::BindGroupLayout, rgba: &image::RgbaImage, label: &str) -> Self {
+        let dimensions = rgba.dimensions();
         
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -230,7 +434,7 @@ impl TextureBindGroup {
         };
         let texture = device.create_texture(
             &wgpu::TextureDescriptor {
-                label: Some(&label),
+                label: Some(label),
                 size,
                 mip_level_count: 1,
                 sample_count: 1,
@@ -248,7 +452,7 @@ impl TextureBindGroup {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &rgba,
+            rgba,
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * dimensions.0),
@@ -272,7 +476,7 @@ impl TextureBindGroup {
 
         let bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: &bind_group_layout,
+                layout: bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
@@ -283,11 +487,11 @@ impl TextureBindGroup {
                         resource: wgpu::BindingResource::Sampler(&sampler),
                     }
                 ],
-                label: Some(&label),
+                label: Some(label),
             }
         );
 
-        Ok(Self { bind_group })
+        Self { bind_group, width: dimensions.0, height: dimensions.1 }
     }
 
     pub fn get_bind_group(&self) -> &wgpu::BindGroup {
@@ -340,3 +544,12 @@ impl Sprite {
         render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
     }
 }
+
+/// A sprite that can be moved, scaled and rotated after creation, drawn through the sprite batch
+pub struct DynamicSprite {
+    pub bind_group_key: String,
+    pub instance: SpriteInstance,
+    pub layer: Layer,
+    pub z: i32,
+    pub blend_mode: BlendMode,
+}
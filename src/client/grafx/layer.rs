@@ -0,0 +1,12 @@
+// This file contains draw ordering for sprites, lower layers are drawn first
+
+/// Broad draw order buckets, sprites are sorted by layer then by their z within the layer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    Background,
+    World,
+    Character,
+    Effect,
+    /// Drawn in screen space (logical pixels) on top of everything, ignores the camera and world tint
+    Ui,
+}
@@ -0,0 +1,173 @@
+// This file contains the background asset loader, files are read and decoded on worker threads
+// and handed back to the render thread which does the GPU upload
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex
+    },
+    thread::{self, JoinHandle}
+};
+use anyhow::Error;
+
+use super::internal;
+use crate::client::assets::AssetKind;
+
+struct LoadRequest {
+    id: String,
+    kind: AssetKind,
+    path: String,
+}
+
+/// A decoded asset ready to be uploaded
+pub struct LoadResult {
+    pub id: String,
+    pub kind: AssetKind,
+    pub image: Result<image::RgbaImage, Error>,
+}
+
+/// How far along the current batch of loads is
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoadProgress {
+    pub finished: usize,
+    pub total: usize,
+}
+
+impl LoadProgress {
+    /// Fraction of the batch that is done, 1.0 when nothing is loading
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.finished as f32 / self.total as f32
+    }
+}
+
+pub struct AssetLoader {
+    sender: Option<Sender<LoadRequest>>,
+    receiver: Receiver<LoadResult>,
+    workers: Vec<JoinHandle<()>>,
+    progress: LoadProgress,
+}
+
+impl AssetLoader {
+    pub fn new(worker_count: usize) -> Self {
+        let (sender, requests) = mpsc::channel::<LoadRequest>();
+        let (results, receiver) = mpsc::channel::<LoadResult>();
+
+        // Workers take turns pulling requests off the shared queue
+        let requests = Arc::new(Mutex::new(requests));
+
+        let workers: Vec<JoinHandle<()>> = (0..worker_count.max(1)).map(|_| {
+            let requests = requests.clone();
+            let results = results.clone();
+            thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting for the next request, not while decoding
+                    let request = match requests.lock() {
+                        Ok(requests) => requests.recv(),
+                        Err(_) => return
+                    };
+                    let request = match request {
+                        Ok(request) => request,
+                        // The loader was dropped
+                        Err(_) => return
+                    };
+
+                    let image = internal::decode_image(&request.path);
+                    if results.send(LoadResult { id: request.id, kind: request.kind, image }).is_err() {
+                        return;
+                    }
+                }
+            })
+        }).collect();
+
+        Self {
+            sender: Some(sender),
+            receiver,
+            workers,
+            progress: LoadProgress { finished: 0, total: 0 },
+        }
+    }
+
+    /// Queue an image to be decoded in the background
+    pub fn queue(&mut self, id: &str, kind: AssetKind, path: &str) {
+        // A new batch starts once the last one has finished
+        if !self.is_loading() {
+            self.progress = LoadProgress { finished: 0, total: 0 };
+        }
+
+        if let Some(sender) = &self.sender {
+            let request = LoadRequest { id: String::from(id), kind, path: String::from(path) };
+            if sender.send(request).is_ok() {
+                self.progress.total += 1;
+            }
+        }
+    }
+
+    /// Take every asset that finished decoding since the last poll
+    pub fn poll(&mut self) -> Vec<LoadResult> {
+        let results: Vec<LoadResult> = self.receiver.try_iter().collect();
+        self.progress.finished += results.len();
+        results
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.progress.finished < self.progress.total
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Closing the request channel lets each worker finish its current decode and exit
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Poll until the batch is done, failing the test if it takes longer than a few seconds
+    fn wait(loader: &mut AssetLoader) -> Vec<LoadResult> {
+        let started = Instant::now();
+        let mut results = Vec::new();
+        while loader.is_loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "loads never finished");
+            results.extend(loader.poll());
+            thread::sleep(Duration::from_millis(1));
+        }
+        results
+    }
+
+    #[test]
+    fn loads_files_in_the_background_and_reports_failures() {
+        let path = std::env::temp_dir().join(format!("poprustica_loader_{}.png", std::process::id()));
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([255, 0, 0, 255])).save(&path).unwrap();
+
+        let mut loader = AssetLoader::new(2);
+        loader.queue("real", AssetKind::Texture, path.to_str().unwrap());
+        loader.queue("missing", AssetKind::Texture, "assets/does_not_exist.png");
+        assert_eq!(loader.progress().total, 2);
+
+        let results = wait(&mut loader);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!loader.is_loading());
+        assert_eq!(loader.progress().fraction(), 1.0);
+        assert_eq!(results.len(), 2);
+        for result in results {
+            match result.id.as_str() {
+                "real" => assert_eq!(result.image.unwrap().dimensions(), (3, 2)),
+                _ => assert!(result.image.is_err())
+            }
+        }
+    }
+}
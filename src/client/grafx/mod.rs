@@ -1,3 +1,26 @@
+#[allow(clippy::module_inception)]
 mod grafx;
 mod internal;
-pub use self::grafx::Grafx;
+mod batch;
+mod atlas;
+mod animation;
+mod layer;
+mod camera;
+mod background;
+mod viewport;
+mod capture;
+mod loader;
+mod watcher;
+mod text;
+pub use self::grafx::{Grafx, MISSING_TEXTURE_KEY, WHITE_TEXTURE_KEY};
+pub use self::internal::BlendMode;
+pub use self::batch::SpriteInstance;
+pub use self::layer::Layer;
+pub use self::camera::Camera2D;
+pub use self::background::BackgroundLayer;
+pub use self::viewport::ScalePolicy;
+pub use self::capture::CaptureFormat;
+pub use self::loader::LoadProgress;
+pub use self::atlas::{AtlasFrame, AtlasLayout};
+pub use self::text::{TextAlign, TextStyle};
+pub use self::animation::{AnimationClip, AnimationEvent, Animator, PlaybackMode};
@@ -0,0 +1,670 @@
+// This file contains text rendering, glyphs from bitmap (BMFont) and TrueType fonts are laid out into quads for the sprite batch
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf}
+};
+use ab_glyph::{Font, FontVec, PxScaleFont, ScaleFont};
+use anyhow::{Error, Result};
+
+use super::batch::SpriteInstance;
+use super::layer::Layer;
+
+/// Width and starting height of the atlas TrueType glyphs are rasterized into
+const GLYPH_ATLAS_SIZE: u32 = 512;
+/// The glyph atlas doubles in height when full, up to this size
+const MAX_GLYPH_ATLAS_SIZE: u32 = 4096;
+/// Empty pixels between glyphs so they don't bleed into each other
+const GLYPH_PADDING: u32 = 1;
+
+/// How the lines of a text line up, and which edge of the text its position is
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub color: [f32; 4],
+    /// Multiplies the font's own size
+    pub scale: f32,
+    pub align: TextAlign,
+    /// Lines longer than this (in pixels) wrap, at a space where possible
+    pub max_width: Option<f32>,
+    /// Multiplies the font's line height
+    pub line_spacing: f32,
+    /// Color and thickness (in pixels) of an outline drawn around every glyph
+    pub outline: Option<([f32; 4], f32)>,
+    /// Read `<col=rrggbb>` and `</col>` tags in the text as color changes, turn off for text typed by players
+    pub markup: bool,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0, 1.0],
+            scale: 1.0,
+            align: TextAlign::Left,
+            max_width: None,
+            line_spacing: 1.0,
+            outline: None,
+            markup: true,
+        }
+    }
+}
+
+/// Where a glyph is in its page texture and how it sits on the line, in pixels
+#[derive(Copy, Clone, Debug)]
+struct Glyph {
+    page: usize,
+    rect: [u32; 4],
+    // From the pen position at the top of the line to the top left of the glyph
+    offset: [f32; 2],
+    advance: f32,
+}
+
+/// A glyph placed by the layout, relative to the text's position
+#[derive(Copy, Clone, Debug)]
+pub struct GlyphQuad {
+    pub page: usize,
+    pub rect: [u32; 4],
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Rasterizes TrueType glyphs into an atlas image the first time they are used
+struct GlyphRasterizer {
+    font: PxScaleFont<FontVec>,
+    image: image::RgbaImage,
+    // Shelf packing, glyphs fill rows left to right
+    cursor: [u32; 2],
+    row_height: u32,
+    dirty: bool,
+}
+
+impl GlyphRasterizer {
+    // Rasterize a glyph into the atlas, growing it if needed
+    fn rasterize(&mut self, c: char) -> Glyph {
+        let id = self.font.glyph_id(c);
+        let advance = self.font.h_advance(id);
+        let ascent = self.font.ascent();
+
+        let outlined = match self.font.outline_glyph(id.with_scale(self.font.scale())) {
+            Some(outlined) => outlined,
+            // Whitespace has nothing to draw
+            None => return Glyph { page: 0, rect: [0, 0, 0, 0], offset: [0.0, 0.0], advance }
+        };
+
+        let bounds = outlined.px_bounds();
+        let width = bounds.width() as u32;
+        let height = bounds.height() as u32;
+
+        // A glyph wider than the atlas, e.g. from a huge font size, can't fit on any row
+        if width + GLYPH_PADDING > self.image.width() {
+            return Glyph { page: 0, rect: [0, 0, 0, 0], offset: [0.0, 0.0], advance };
+        }
+
+        // Start a new row when this one is full
+        if self.cursor[0] + width + GLYPH_PADDING > self.image.width() {
+            self.cursor = [0, self.cursor[1] + self.row_height + GLYPH_PADDING];
+            self.row_height = 0;
+        }
+
+        while self.cursor[1] + height > self.image.height() {
+            // The atlas is full, the glyph isn't drawn
+            if self.image.height() * 2 > MAX_GLYPH_ATLAS_SIZE {
+                return Glyph { page: 0, rect: [0, 0, 0, 0], offset: [0.0, 0.0], advance };
+            }
+            let mut grown: image::RgbaImage = image::RgbaImage::new(self.image.width(), self.image.height() * 2);
+            image::imageops::replace(&mut grown, &self.image, 0, 0);
+            self.image = grown;
+        }
+
+        let [x, y] = self.cursor;
+        outlined.draw(|glyph_x, glyph_y, coverage| {
+            let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+            if let Some(pixel) = self.image.get_pixel_mut_checked(x + glyph_x, y + glyph_y) {
+                *pixel = image::Rgba([255, 255, 255, alpha]);
+            }
+        });
+
+        self.cursor[0] += width + GLYPH_PADDING;
+        self.row_height = self.row_height.max(height);
+        self.dirty = true;
+
+        Glyph { page: 0, rect: [x, y, width, height], offset: [bounds.min.x, ascent + bounds.min.y], advance }
+    }
+}
+
+/// A font's glyphs and metrics, bitmap fonts come with their page images while TrueType glyphs are rasterized on demand
+pub struct FontFace {
+    line_height: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+    pages: Vec<[u32; 2]>,
+    rasterizer: Option<GlyphRasterizer>,
+}
+
+impl FontFace {
+    /// Read a TrueType (or OpenType) font to be rasterized at `size` pixels
+    pub fn truetype(path: &Path, size: f32) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read font '{}': {}", path.display(), err))?;
+        let font = FontVec::try_from_vec(bytes)
+            .map_err(|err| anyhow::anyhow!("Failed to parse font '{}': {}", path.display(), err))?
+            .into_scaled(size);
+
+        let line_height = (font.height() + font.line_gap()).ceil();
+        let image: image::RgbaImage = image::RgbaImage::new(GLYPH_ATLAS_SIZE, GLYPH_ATLAS_SIZE);
+
+        Ok(Self {
+            line_height,
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+            pages: vec![[image.width(), image.height()]],
+            rasterizer: Some(GlyphRasterizer { font, image, cursor: [0, 0], row_height: 0, dirty: true }),
+        })
+    }
+
+    /// Read an AngelCode BMFont in its text format, returns the font and the paths of its page images
+    pub fn bitmap(path: &Path) -> Result<(Self, Vec<PathBuf>), Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read font '{}': {}", path.display(), err))?;
+        Self::parse_bitmap(&text, path)
+    }
+
+    // Parse the text of a BMFont, page paths are relative to the font's path
+    fn parse_bitmap(text: &str, path: &Path) -> Result<(Self, Vec<PathBuf>), Error> {
+        let folder = path.parent().unwrap_or(Path::new(""));
+
+        let mut line_height: f32 = 0.0;
+        let mut page_size: [u32; 2] = [0, 0];
+        let mut page_paths: Vec<(usize, PathBuf)> = Vec::new();
+        let mut glyphs: HashMap<char, Glyph> = HashMap::new();
+        let mut kerning: HashMap<(char, char), f32> = HashMap::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let (tag, values) = parse_bmfont_line(line);
+            let number = |key: &str| -> Result<i64, Error> {
+                values.get(key).and_then(|value| value.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("{}:{}: '{}' is missing '{}'", path.display(), line_number + 1, tag, key))
+            };
+
+            match tag {
+                "common" => {
+                    line_height = number("lineHeight")? as f32;
+                    page_size = [number("scaleW")? as u32, number("scaleH")? as u32];
+                }
+                "page" => {
+                    let file = values.get("file")
+                        .ok_or_else(|| anyhow::anyhow!("{}:{}: page is missing 'file'", path.display(), line_number + 1))?;
+                    page_paths.push((number("id")? as usize, folder.join(file)));
+                }
+                "char" => {
+                    let c = match char::from_u32(number("id")? as u32) {
+                        Some(c) => c,
+                        None => continue
+                    };
+                    glyphs.insert(c, Glyph {
+                        page: number("page")? as usize,
+                        rect: [number("x")? as u32, number("y")? as u32, number("width")? as u32, number("height")? as u32],
+                        offset: [number("xoffset")? as f32, number("yoffset")? as f32],
+                        advance: number("xadvance")? as f32,
+                    });
+                }
+                "kerning" => {
+                    if let (Some(first), Some(second)) = (char::from_u32(number("first")? as u32), char::from_u32(number("second")? as u32)) {
+                        kerning.insert((first, second), number("amount")? as f32);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if page_paths.is_empty() {
+            return Err(anyhow::anyhow!("Font '{}' has no pages (only the BMFont text format is supported)", path.display()));
+        }
+
+        page_paths.sort_by_key(|(id, _path)| *id);
+        let font = Self {
+            line_height,
+            glyphs,
+            kerning,
+            pages: vec![page_size; page_paths.len()],
+            rasterizer: None,
+        };
+
+        Ok((font, page_paths.into_iter().map(|(_id, path)| path).collect()))
+    }
+
+    /// Number of page textures the font draws from
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Get the glyph atlas if glyphs were rasterized into it since the last call, so it can be uploaded
+    pub fn take_dirty_atlas(&mut self) -> Option<&image::RgbaImage> {
+        match &mut self.rasterizer {
+            Some(rasterizer) if rasterizer.dirty => {
+                rasterizer.dirty = false;
+                self.pages[0] = [rasterizer.image.width(), rasterizer.image.height()];
+                Some(&rasterizer.image)
+            }
+            _ => None
+        }
+    }
+
+    /// Get the offset (xy) and size (zw) of a glyph's rect in texture coordinates
+    pub fn uv_rect(&self, quad: &GlyphQuad) -> [f32; 4] {
+        let [width, height] = self.pages.get(quad.page).copied().unwrap_or([1, 1]);
+        [
+            quad.rect[0] as f32 / width as f32,
+            quad.rect[1] as f32 / height as f32,
+            quad.rect[2] as f32 / width as f32,
+            quad.rect[3] as f32 / height as f32,
+        ]
+    }
+
+    fn glyph(&mut self, c: char) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&c) {
+            return Some(*glyph);
+        }
+
+        match &mut self.rasterizer {
+            Some(rasterizer) => {
+                let glyph = rasterizer.rasterize(c);
+                self.glyphs.insert(c, glyph);
+                Some(glyph)
+            }
+            // Bitmap fonts only have the glyphs they were made with
+            None => self.glyphs.get(&'?').copied()
+        }
+    }
+
+    fn kern(&self, previous: Option<char>, c: char) -> f32 {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return 0.0
+        };
+
+        match &self.rasterizer {
+            Some(rasterizer) => rasterizer.font.kern(rasterizer.font.glyph_id(previous), rasterizer.font.glyph_id(c)),
+            None => self.kerning.get(&(previous, c)).copied().unwrap_or(0.0)
+        }
+    }
+
+    // Width of a line in pixels, before scaling
+    fn line_width(&mut self, line: &[(char, [f32; 4])]) -> f32 {
+        let mut width: f32 = 0.0;
+        let mut previous: Option<char> = None;
+        for (c, _color) in line {
+            width += self.kern(previous, *c) + self.glyph(*c).map(|glyph| glyph.advance).unwrap_or(0.0);
+            previous = Some(*c);
+        }
+        width
+    }
+
+    /// Lay out a string, returns the glyph quads relative to the text's position and the size of the whole text
+    pub fn layout(&mut self, text: &str, style: &TextStyle) -> (Vec<GlyphQuad>, [f32; 2]) {
+        let chars: Vec<(char, [f32; 4])> = if style.markup {
+            parse_markup(text, style.color)
+        } else {
+            text.chars().map(|c| (c, style.color)).collect()
+        };
+
+        // Break into lines at newlines, then wrap each line to the max width
+        let max_width: Option<f32> = style.max_width.map(|max_width| max_width / style.scale);
+        let mut lines: Vec<Vec<(char, [f32; 4])>> = Vec::new();
+
+        for paragraph in chars.split(|(c, _color)| *c == '\n') {
+            let mut line: Vec<(char, [f32; 4])> = Vec::new();
+
+            for (c, color) in paragraph.iter().copied() {
+                if c == '\r' {
+                    continue;
+                }
+
+                line.push((c, color));
+
+                let too_wide = match max_width {
+                    Some(max_width) => c != ' ' && line.len() > 1 && self.line_width(&line) > max_width,
+                    None => false
+                };
+
+                if too_wide {
+                    let next: Vec<(char, [f32; 4])> = match line.iter().rposition(|(c, _color)| *c == ' ') {
+                        // Move the last word down a line, dropping the space it was split at
+                        Some(space) => {
+                            let next = line.split_off(space + 1);
+                            line.pop();
+                            next
+                        }
+                        // A single word longer than the whole line is split wherever it overflows
+                        None => vec![line.pop().unwrap_or((c, color))]
+                    };
+                    lines.push(std::mem::replace(&mut line, next));
+                }
+            }
+
+            lines.push(line);
+        }
+
+        let widths: Vec<f32> = lines.iter().map(|line| self.line_width(line)).collect();
+        let box_width: f32 = max_width.unwrap_or_else(|| widths.iter().copied().fold(0.0, f32::max));
+        let line_height: f32 = self.line_height * style.line_spacing;
+
+        // The position is the left edge, center or right edge of the text
+        let anchor: f32 = match style.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => -box_width / 2.0,
+            TextAlign::Right => -box_width,
+        };
+
+        let mut quads: Vec<GlyphQuad> = Vec::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            let mut x: f32 = anchor + match style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (box_width - widths[index]) / 2.0,
+                TextAlign::Right => box_width - widths[index],
+            };
+            let y: f32 = index as f32 * line_height;
+            let mut previous: Option<char> = None;
+
+            for (c, color) in line {
+                x += self.kern(previous, *c);
+                previous = Some(*c);
+
+                let glyph = match self.glyph(*c) {
+                    Some(glyph) => glyph,
+                    None => continue
+                };
+
+                if glyph.rect[2] > 0 && glyph.rect[3] > 0 {
+                    quads.push(GlyphQuad {
+                        page: glyph.page,
+                        rect: glyph.rect,
+                        position: [(x + glyph.offset[0]) * style.scale, (y + glyph.offset[1]) * style.scale],
+                        size: [glyph.rect[2] as f32 * style.scale, glyph.rect[3] as f32 * style.scale],
+                        color: *color,
+                    });
+                }
+
+                x += glyph.advance;
+            }
+        }
+
+        let height: f32 = (lines.len().saturating_sub(1) as f32 * line_height + self.line_height) * style.scale;
+        (quads, [box_width * style.scale, height])
+    }
+}
+
+/// A laid out string drawn by the renderer, like a dynamic sprite
+pub struct TextSprite {
+    pub font_key: String,
+    pub text: String,
+    pub style: TextStyle,
+    pub position: [f32; 2],
+    pub layer: Layer,
+    pub z: i32,
+    pub quads: Vec<GlyphQuad>,
+    pub size: [f32; 2],
+}
+
+impl TextSprite {
+    /// Get the sprite instances to draw, outlines first so they never cover a neighbouring glyph
+    pub fn instances(&self, font: &FontFace) -> Vec<(usize, SpriteInstance)> {
+        // Snap to whole pixels so glyphs stay crisp
+        let origin: [f32; 2] = [self.position[0].round(), self.position[1].round()];
+        let mut instances: Vec<(usize, SpriteInstance)> = Vec::new();
+
+        let instance = |quad: &GlyphQuad, offset: [f32; 2], color: [f32; 4]| -> (usize, SpriteInstance) {
+            let center: [f32; 2] = [
+                origin[0] + quad.position[0] + quad.size[0] / 2.0 + offset[0],
+                origin[1] + quad.position[1] + quad.size[1] / 2.0 + offset[1],
+            ];
+            let mut instance: SpriteInstance = SpriteInstance::new(center, quad.size, 0.0);
+            instance.uv_rect = font.uv_rect(quad);
+            instance.color = color;
+            (quad.page, instance)
+        };
+
+        if let Some((color, width)) = self.style.outline {
+            for [x, y] in [[-1.0, -1.0], [0.0, -1.0], [1.0, -1.0], [-1.0, 0.0], [1.0, 0.0], [-1.0, 1.0], [0.0, 1.0], [1.0, 1.0]] {
+                for quad in &self.quads {
+                    // Keep the glyph's own alpha so faded text fades its outline too
+                    instances.push(instance(quad, [x * width, y * width], [color[0], color[1], color[2], color[3] * quad.color[3]]));
+                }
+            }
+        }
+
+        for quad in &self.quads {
+            instances.push(instance(quad, [0.0, 0.0], quad.color));
+        }
+
+        instances
+    }
+}
+
+/// The texture key a font's page is stored under
+pub fn page_key(font_key: &str, page: usize) -> String {
+    format!("{}#{}", font_key, page)
+}
+
+// Split a BMFont line into its tag and key=value pairs, values may be quoted
+fn parse_bmfont_line(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mut values: HashMap<&str, &str> = HashMap::new();
+
+    loop {
+        rest = rest.trim_start();
+        let (key, after_key) = match rest.split_once('=') {
+            Some(split) => split,
+            None => break
+        };
+
+        let (value, after_value) = match after_key.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after_key.split_once(char::is_whitespace).unwrap_or((after_key, "")),
+        };
+
+        values.insert(key.trim(), value);
+        rest = after_value;
+    }
+
+    (tag, values)
+}
+
+// Pull `<col=rrggbb>` color tags out of a string, `</col>` goes back to the default color
+fn parse_markup(text: &str, default_color: [f32; 4]) -> Vec<(char, [f32; 4])> {
+    let mut chars: Vec<(char, [f32; 4])> = Vec::new();
+    let mut color: [f32; 4] = default_color;
+    let mut rest: &str = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(after) = rest.strip_prefix("</col>") {
+                color = default_color;
+                rest = after;
+                continue;
+            }
+
+            if let Some(after) = rest.strip_prefix("<col=")
+                && let Some((hex, after)) = after.split_once('>')
+                && let Some(tag_color) = parse_hex_color(hex, default_color[3]) {
+                color = tag_color;
+                rest = after;
+                continue;
+            }
+        }
+
+        chars.push((c, color));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    chars
+}
+
+fn parse_hex_color(hex: &str, alpha: f32) -> Option<[f32; 4]> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([
+        ((value >> 16) & 0xff) as f32 / 255.0,
+        ((value >> 8) & 0xff) as f32 / 255.0,
+        (value & 0xff) as f32 / 255.0,
+        alpha,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+    /// A bitmap font where every glyph is 8 by 10 and advances 10, 'a' then 'b' kerns 2 closer
+    const FONT: &str = "info face=\"Test Font\" size=12
+common lineHeight=12 base=10 scaleW=64 scaleH=32 pages=2
+page id=1 file=\"test_1.png\"
+page id=0 file=\"test_0.png\"
+char id=97 x=0 y=0 width=8 height=10 xoffset=1 yoffset=2 xadvance=10 page=0
+char id=98 x=8 y=0 width=8 height=10 xoffset=1 yoffset=2 xadvance=10 page=1
+char id=63 x=16 y=0 width=8 height=10 xoffset=1 yoffset=2 xadvance=10 page=0
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=10 page=0
+kerning first=97 second=98 amount=-2
+";
+
+    fn font() -> FontFace {
+        FontFace::parse_bitmap(FONT, Path::new("fonts/test.fnt")).unwrap().0
+    }
+
+    /// The text of each laid out line, found by the glyph rows the quads sit on
+    fn lines(font: &mut FontFace, text: &str, style: &TextStyle) -> Vec<usize> {
+        let (quads, _size) = font.layout(text, style);
+        let mut counts: Vec<usize> = Vec::new();
+        for quad in quads {
+            let line = (quad.position[1] / 12.0) as usize;
+            counts.resize(counts.len().max(line + 1), 0);
+            counts[line] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn splits_bmfont_lines() {
+        let (tag, values) = parse_bmfont_line("info face=\"Some Font\" size=12  bold=0");
+        assert_eq!(tag, "info");
+        assert_eq!(values.get("face"), Some(&"Some Font"));
+        assert_eq!(values.get("size"), Some(&"12"));
+        assert_eq!(values.get("bold"), Some(&"0"));
+
+        let (tag, values) = parse_bmfont_line("chars");
+        assert_eq!(tag, "chars");
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn reads_bitmap_fonts() {
+        let (mut font, pages) = FontFace::parse_bitmap(FONT, Path::new("fonts/test.fnt")).unwrap();
+        // Pages come back in id order, next to the font
+        assert_eq!(pages, vec![PathBuf::from("fonts/test_0.png"), PathBuf::from("fonts/test_1.png")]);
+        assert_eq!(font.page_count(), 2);
+
+        let glyph = font.glyph('b').unwrap();
+        assert_eq!(glyph.page, 1);
+        assert_eq!(glyph.rect, [8, 0, 8, 10]);
+        assert_eq!(font.kern(Some('a'), 'b'), -2.0);
+        assert_eq!(font.kern(Some('b'), 'a'), 0.0);
+
+        // Glyphs the font doesn't have show as '?'
+        assert_eq!(font.glyph('z').map(|glyph| glyph.rect), Some([16, 0, 8, 10]));
+    }
+
+    #[test]
+    fn bitmap_font_errors() {
+        assert!(FontFace::parse_bitmap("common lineHeight=12 scaleW=64 scaleH=32", Path::new("a.fnt")).is_err());
+        let missing = FontFace::parse_bitmap("page id=0 file=\"a.png\"\nchar id=97 x=0", Path::new("a.fnt"));
+        assert!(missing.err().is_some_and(|err| err.to_string().contains("'char' is missing")));
+    }
+
+    #[test]
+    fn markup_colors() {
+        let chars = parse_markup("a<col=ff0000>b</col>c", WHITE);
+        assert_eq!(chars, vec![('a', WHITE), ('b', RED), ('c', WHITE)]);
+
+        // Tags that don't parse are plain text
+        let text: String = parse_markup("<col=red>x<b>", WHITE).iter().map(|(c, _color)| *c).collect();
+        assert_eq!(text, "<col=red>x<b>");
+
+        // The color keeps the default alpha
+        assert_eq!(parse_hex_color("00ff00", 0.5), Some([0.0, 1.0, 0.0, 0.5]));
+        assert_eq!(parse_hex_color("00ff0", 1.0), None);
+    }
+
+    #[test]
+    fn markup_can_be_turned_off() {
+        let mut font = font();
+        let style = TextStyle { markup: false, ..Default::default() };
+        let (quads, _size) = font.layout("<col=ff0000>a", &style);
+        assert_eq!(quads.len(), 13);
+        assert!(quads.iter().all(|quad| quad.color == WHITE));
+    }
+
+    #[test]
+    fn lays_out_with_kerning() {
+        let mut font = font();
+        let (quads, size) = font.layout("ab", &TextStyle::default());
+        assert_eq!(quads[0].position, [1.0, 2.0]);
+        assert_eq!(quads[1].position, [9.0, 2.0]);
+        assert_eq!(size, [18.0, 12.0]);
+
+        let style = TextStyle { scale: 2.0, ..Default::default() };
+        let (quads, size) = font.layout("ab", &style);
+        assert_eq!(quads[1].position, [18.0, 4.0]);
+        assert_eq!(quads[1].size, [16.0, 20.0]);
+        assert_eq!(size, [36.0, 24.0]);
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let mut font = font();
+        let style = TextStyle { max_width: Some(45.0), ..Default::default() };
+        // "aaa aaa" is 70 wide, the second word moves down and the space is dropped
+        assert_eq!(lines(&mut font, "aaa aaa", &style), vec![3, 3]);
+        let (_quads, size) = font.layout("aaa aaa", &style);
+        assert_eq!(size, [45.0, 24.0]);
+    }
+
+    #[test]
+    fn splits_long_words() {
+        let mut font = font();
+        let style = TextStyle { max_width: Some(30.0), ..Default::default() };
+        assert_eq!(lines(&mut font, "aaaaaaa", &style), vec![3, 3, 1]);
+    }
+
+    #[test]
+    fn newlines_start_lines() {
+        let mut font = font();
+        assert_eq!(lines(&mut font, "a\r\naa\n\naaa", &TextStyle::default()), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let mut font = font();
+        let style = TextStyle { align: TextAlign::Center, max_width: Some(40.0), ..Default::default() };
+        let (quads, _size) = font.layout("a", &style);
+        // Centered in a 40 wide box around the position
+        assert_eq!(quads[0].position[0], -5.0 + 1.0);
+
+        let style = TextStyle { align: TextAlign::Right, ..Default::default() };
+        let (quads, _size) = font.layout("aa\na", &style);
+        assert_eq!(quads[0].position[0], -20.0 + 1.0);
+        assert_eq!(quads[2].position[0], -10.0 + 1.0);
+    }
+}
@@ -0,0 +1,120 @@
+// This file contains how the game's logical resolution is fit into a window of any size
+
+/// How the logical resolution is scaled to fit the window
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScalePolicy {
+    /// Keep the aspect ratio and fill the leftover space with black bars
+    Letterbox,
+    /// Like letterbox but only scale by whole numbers so pixel art stays crisp
+    IntegerScale,
+    /// Fill the whole window, distorting the image if the aspect ratio differs
+    Stretch,
+}
+
+/// The area of the window the game is drawn into, in physical pixels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    /// Work out where the logical resolution ends up in a window of `surface_size` physical pixels
+    pub fn fit(policy: ScalePolicy, surface_size: [u32; 2], logical_size: [f32; 2]) -> Self {
+        let surface = [surface_size[0] as f32, surface_size[1] as f32];
+
+        let scale = match policy {
+            ScalePolicy::Stretch => {
+                return Self { x: 0.0, y: 0.0, width: surface[0], height: surface[1] };
+            }
+            ScalePolicy::Letterbox => (surface[0] / logical_size[0]).min(surface[1] / logical_size[1]),
+            ScalePolicy::IntegerScale => {
+                let scale = (surface[0] / logical_size[0]).min(surface[1] / logical_size[1]);
+                // Windows smaller than the logical resolution can't use a whole number, so shrink smoothly instead
+                if scale >= 1.0 { scale.floor() } else { scale }
+            }
+        };
+
+        // Rounding can leave the scaled size a hair bigger than the window, and wgpu rejects viewports that stick out
+        let x = ((surface[0] - logical_size[0] * scale) / 2.0).floor().max(0.0);
+        let y = ((surface[1] - logical_size[1] * scale) / 2.0).floor().max(0.0);
+
+        Self {
+            x,
+            y,
+            width: (logical_size[0] * scale).min(surface[0] - x),
+            height: (logical_size[1] * scale).min(surface[1] - y),
+        }
+    }
+
+    /// Convert a position in physical window pixels to logical pixels
+    pub fn physical_to_logical(&self, position: [f32; 2], logical_size: [f32; 2]) -> [f32; 2] {
+        [
+            (position[0] - self.x) * logical_size[0] / self.width,
+            (position[1] - self.y) * logical_size[1] / self.height,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGICAL: [f32; 2] = [1280.0, 720.0];
+
+    fn inside(rect: ViewportRect, surface: [u32; 2]) -> bool {
+        rect.x >= 0.0 && rect.y >= 0.0
+            && rect.x + rect.width <= surface[0] as f32
+            && rect.y + rect.height <= surface[1] as f32
+    }
+
+    #[test]
+    fn stretch_fills_the_window() {
+        let rect = ViewportRect::fit(ScalePolicy::Stretch, [800, 800], LOGICAL);
+        assert_eq!(rect, ViewportRect { x: 0.0, y: 0.0, width: 800.0, height: 800.0 });
+    }
+
+    #[test]
+    fn letterbox_adds_bars() {
+        // Too tall, bars above and below
+        let rect = ViewportRect::fit(ScalePolicy::Letterbox, [1280, 1000], LOGICAL);
+        assert_eq!(rect, ViewportRect { x: 0.0, y: 140.0, width: 1280.0, height: 720.0 });
+
+        // Too wide, bars left and right
+        let rect = ViewportRect::fit(ScalePolicy::Letterbox, [2000, 720], LOGICAL);
+        assert_eq!(rect, ViewportRect { x: 360.0, y: 0.0, width: 1280.0, height: 720.0 });
+    }
+
+    #[test]
+    fn integer_scale_uses_whole_numbers() {
+        let rect = ViewportRect::fit(ScalePolicy::IntegerScale, [3000, 1500], LOGICAL);
+        assert_eq!(rect, ViewportRect { x: 220.0, y: 30.0, width: 2560.0, height: 1440.0 });
+
+        // Smaller than the logical size shrinks smoothly
+        let rect = ViewportRect::fit(ScalePolicy::IntegerScale, [640, 720], LOGICAL);
+        assert_eq!(rect.width, 640.0);
+        assert_eq!(rect.height, 360.0);
+    }
+
+    #[test]
+    fn never_sticks_out_of_the_window() {
+        for policy in [ScalePolicy::Letterbox, ScalePolicy::IntegerScale, ScalePolicy::Stretch] {
+            for width in (100..2000).step_by(7) {
+                for height in [1, 2, 359, 480, 719, 720, 729, 736, 768, 1080, 1199] {
+                    let surface: [u32; 2] = [width, height];
+                    let rect = ViewportRect::fit(policy, surface, LOGICAL);
+                    assert!(inside(rect, surface), "{:?} {:?} {:?}", policy, surface, rect);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn converts_window_positions() {
+        let rect = ViewportRect::fit(ScalePolicy::Letterbox, [2560, 1640], LOGICAL);
+        assert_eq!(rect.physical_to_logical([0.0, 100.0], LOGICAL), [0.0, 0.0]);
+        assert_eq!(rect.physical_to_logical([2560.0, 1540.0], LOGICAL), LOGICAL);
+    }
+}
@@ -0,0 +1,73 @@
+// This file contains a polling file watcher, used to hot reload assets and shaders during development
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime}
+};
+
+/// How often the watched folders are scanned
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct FileWatcher {
+    dirs: Vec<PathBuf>,
+    // Last modified time of every file seen in the watched folders
+    files: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    /// Watch every file in the given folders (and their subfolders)
+    pub fn new(dirs: &[&Path]) -> Self {
+        let dirs: Vec<PathBuf> = dirs.iter().map(|dir| dir.to_path_buf()).collect();
+
+        // Files that already exist are the baseline, only changes after this count
+        let mut files: HashMap<PathBuf, SystemTime> = HashMap::new();
+        for dir in &dirs {
+            scan(dir, &mut files);
+        }
+
+        Self { dirs, files, last_poll: Instant::now() }
+    }
+
+    /// Get every file that was created or modified since the last scan, scans at most every `POLL_INTERVAL`
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut files: HashMap<PathBuf, SystemTime> = HashMap::new();
+        for dir in &self.dirs {
+            scan(dir, &mut files);
+        }
+
+        let changed: Vec<PathBuf> = files.iter()
+            .filter(|(path, modified)| self.files.get(*path) != Some(*modified))
+            .map(|(path, _modified)| path.clone())
+            .collect();
+
+        self.files = files;
+        changed
+    }
+}
+
+// Record the modified time of every file under a folder, unreadable entries are skipped
+fn scan(dir: &Path, files: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => scan(&path, files),
+            Ok(metadata) => {
+                if let Ok(modified) = metadata.modified() {
+                    files.insert(path, modified);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
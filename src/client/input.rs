@@ -0,0 +1,455 @@
+// Input mapping, raw keyboard and mouse state is tracked per frame and looked up through named actions
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path
+};
+use anyhow::{Error, Result};
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey}
+};
+
+/// Default bindings file, each line is `<action> <input> [<input>...]`
+pub const BINDINGS_PATH: &str = "assets/bindings.txt";
+
+/// Pixels of trackpad scrolling that count as one mouse wheel line
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
+
+/// Keys that can be named in a bindings file, by their `KeyCode` name (e.g. `KeyW`, `ArrowLeft`, `F12`)
+const BINDABLE_KEYS: [KeyCode; 90] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowLeft, KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::ArrowDown,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash,
+    KeyCode::Semicolon, KeyCode::Quote, KeyCode::Backquote, KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+];
+
+/// Bindings used when there is no bindings file, or for actions it leaves out
+const DEFAULT_BINDINGS: [(&str, &str); 14] = [
+    ("move_left", "KeyA ArrowLeft"),
+    ("move_right", "KeyD ArrowRight"),
+    ("move_up", "KeyW ArrowUp"),
+    ("move_down", "KeyS ArrowDown"),
+    ("jump", "Space KeyW ArrowUp"),
+    ("interact", "MouseLeft"),
+    ("context_menu", "MouseRight"),
+    ("open_inventory", "KeyI Tab"),
+    ("zoom_in", "WheelUp"),
+    ("zoom_out", "WheelDown"),
+    ("screenshot", "F12"),
+    ("record", "F11"),
+    ("pause", "Escape"),
+    ("confirm", "Enter Space"),
+];
+
+/// Anything an action can be bound to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+}
+
+impl InputSource {
+    /// Parse a name from a bindings file, keys use their `KeyCode` name and the mouse is `MouseLeft`, `MouseRight`, `MouseMiddle`, `WheelUp` or `WheelDown`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(InputSource::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(InputSource::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(InputSource::Mouse(MouseButton::Middle)),
+            "WheelUp" => Some(InputSource::WheelUp),
+            "WheelDown" => Some(InputSource::WheelDown),
+            _ => BINDABLE_KEYS.iter().find(|key| format!("{:?}", key) == name).map(|key| InputSource::Key(*key))
+        }
+    }
+
+    /// The name used in bindings files
+    pub fn name(&self) -> String {
+        match self {
+            InputSource::Key(key) => format!("{:?}", key),
+            InputSource::Mouse(MouseButton::Left) => String::from("MouseLeft"),
+            InputSource::Mouse(MouseButton::Right) => String::from("MouseRight"),
+            InputSource::Mouse(MouseButton::Middle) => String::from("MouseMiddle"),
+            InputSource::Mouse(button) => format!("{:?}", button),
+            InputSource::WheelUp => String::from("WheelUp"),
+            InputSource::WheelDown => String::from("WheelDown"),
+        }
+    }
+}
+
+/// Which inputs trigger each named action
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    actions: HashMap<String, Vec<InputSource>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings: Bindings = Bindings { actions: HashMap::new() };
+        for (action, sources) in DEFAULT_BINDINGS {
+            let sources: Vec<InputSource> = sources.split_whitespace().filter_map(InputSource::parse).collect();
+            bindings.actions.insert(String::from(action), sources);
+        }
+        bindings
+    }
+}
+
+impl Bindings {
+    /// Read bindings from a file, blank lines and `#` comments are skipped
+    /// Actions in the file replace the default bindings for that action, an action listed with no inputs is unbound
+    /// Lines with an unknown input are skipped and reported together once the rest are applied, returns how many were applied
+    pub fn load(&mut self, path: &Path) -> Result<usize, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read bindings '{}': {}", path.display(), err))?;
+        self.parse(&text, path)
+    }
+
+    // Apply every line of a bindings file, `path` is only used in error messages
+    fn parse(&mut self, text: &str, path: &Path) -> Result<usize, Error> {
+        let mut count = 0;
+        let mut skipped: Vec<String> = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let action = match parts.next() {
+                Some(action) => action,
+                None => continue
+            };
+
+            let sources: Vec<InputSource> = match parts.map(|name| InputSource::parse(name).ok_or(name)).collect() {
+                Ok(sources) => sources,
+                Err(name) => {
+                    skipped.push(format!("{}:{}: unknown input '{}'", path.display(), line_number + 1, name));
+                    continue;
+                }
+            };
+
+            self.actions.insert(String::from(action), sources);
+            count += 1;
+        }
+
+        if !skipped.is_empty() {
+            return Err(anyhow::anyhow!("Skipped {} of {} bindings, {}", skipped.len(), count + skipped.len(), skipped.join(", ")));
+        }
+        Ok(count)
+    }
+
+    /// Write every binding to a file that `load` can read back
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut text: String = String::from("# <action> <input> [<input>...]\n");
+        for action in self.actions() {
+            let names: Vec<String> = std::iter::once(String::from(action)).chain(self.sources(action).iter().map(|source| source.name())).collect();
+            text.push_str(&names.join(" "));
+            text.push('\n');
+        }
+
+        std::fs::write(path, text)
+            .map_err(|err| anyhow::anyhow!("Failed to write bindings '{}': {}", path.display(), err))
+    }
+
+    /// Replace an action's inputs
+    pub fn bind(&mut self, action: &str, sources: &[InputSource]) {
+        self.actions.insert(String::from(action), sources.to_vec());
+    }
+
+    /// Add another input to an action, keeping the ones it already has
+    pub fn add(&mut self, action: &str, source: InputSource) {
+        let sources = self.actions.entry(String::from(action)).or_default();
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    /// Remove an input from every action it is bound to
+    pub fn unbind(&mut self, source: InputSource) {
+        for sources in self.actions.values_mut() {
+            sources.retain(|bound| *bound != source);
+        }
+    }
+
+    /// Get every action, sorted by name
+    pub fn actions(&self) -> Vec<&str> {
+        let mut actions: Vec<&str> = self.actions.keys().map(|action| action.as_str()).collect();
+        actions.sort();
+        actions
+    }
+
+    /// Get the inputs bound to an action
+    pub fn sources(&self, action: &str) -> &[InputSource] {
+        self.actions.get(action).map(|sources| sources.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get every action an input triggers
+    pub fn actions_for(&self, source: InputSource) -> Vec<&str> {
+        self.actions.iter()
+            .filter(|(_action, sources)| sources.contains(&source))
+            .map(|(action, _sources)| action.as_str())
+            .collect()
+    }
+}
+
+/// Keyboard and mouse state for the current frame
+#[derive(Default)]
+pub struct Input {
+    bindings: Bindings,
+    held: HashSet<InputSource>,
+    // Inputs that went down or up since the last frame, an input can be both if it was tapped quickly
+    pressed: HashSet<InputSource>,
+    released: HashSet<InputSource>,
+    // Mouse wheel lines this frame, positive is up
+    wheel: f32,
+    // Cursor position in physical window pixels
+    cursor: (f64, f64),
+    cursor_delta: (f64, f64),
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            ..Self::default()
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    /// Feed a window event, anything that isn't keyboard or mouse input is ignored
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            // Key repeat is not a new press
+            WindowEvent::KeyboardInput { event: KeyEvent { physical_key: PhysicalKey::Code(key), state, repeat: false, .. }, .. } => {
+                self.set_state(InputSource::Key(*key), *state == ElementState::Pressed);
+            }
+            WindowEvent::MouseInput { button, state, .. } => {
+                self.set_state(InputSource::Mouse(*button), *state == ElementState::Pressed);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll(scroll_lines(delta));
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_delta.0 += position.x - self.cursor.0;
+                self.cursor_delta.1 += position.y - self.cursor.1;
+                self.cursor = (position.x, position.y);
+            }
+            // Keys let go of while the window is in the background would otherwise stay held
+            WindowEvent::Focused(false) => {
+                self.released.extend(self.held.drain());
+            }
+            _ => {}
+        }
+    }
+
+    /// Scroll by a number of wheel lines, positive is up
+    pub fn scroll(&mut self, lines: f32) {
+        self.wheel += lines;
+        if lines > 0.0 {
+            self.pressed.insert(InputSource::WheelUp);
+        } else if lines < 0.0 {
+            self.pressed.insert(InputSource::WheelDown);
+        }
+    }
+
+    /// Press or release an input directly
+    pub fn set_state(&mut self, source: InputSource, down: bool) {
+        if down {
+            if self.held.insert(source) {
+                self.pressed.insert(source);
+            }
+        } else if self.held.remove(&source) {
+            self.released.insert(source);
+        }
+    }
+
+    /// Forget this frame's presses, releases, scrolling and cursor movement, call once game systems have seen them
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.wheel = 0.0;
+        self.cursor_delta = (0.0, 0.0);
+    }
+
+    /// True on the frame any of the action's inputs went down
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.bindings.sources(action).iter().any(|source| self.pressed.contains(source))
+    }
+
+    /// True while any of the action's inputs is down
+    pub fn is_held(&self, action: &str) -> bool {
+        self.bindings.sources(action).iter().any(|source| self.held.contains(source))
+    }
+
+    /// True on the frame the last of the action's held inputs went up
+    pub fn is_released(&self, action: &str) -> bool {
+        let sources = self.bindings.sources(action);
+        sources.iter().any(|source| self.released.contains(source)) && !sources.iter().any(|source| self.held.contains(source))
+    }
+
+    /// -1.0, 0.0 or 1.0 depending on which of two opposing actions is held, e.g. `axis("move_left", "move_right")`
+    pub fn axis(&self, negative: &str, positive: &str) -> f32 {
+        (self.is_held(positive) as i32 - self.is_held(negative) as i32) as f32
+    }
+
+    pub fn is_source_pressed(&self, source: InputSource) -> bool {
+        self.pressed.contains(&source)
+    }
+
+    pub fn is_source_held(&self, source: InputSource) -> bool {
+        self.held.contains(&source)
+    }
+
+    pub fn is_source_released(&self, source: InputSource) -> bool {
+        self.released.contains(&source)
+    }
+
+    /// Mouse wheel lines scrolled this frame, positive is up
+    pub fn wheel(&self) -> f32 {
+        self.wheel
+    }
+
+    /// Cursor position in physical window pixels
+    pub fn cursor(&self) -> (f64, f64) {
+        self.cursor
+    }
+
+    /// How far the cursor moved this frame, in physical window pixels
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+}
+
+/// Convert a mouse wheel or trackpad scroll to wheel lines, positive is up
+pub fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_x, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_SCROLL_LINE) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: InputSource = InputSource::Key(KeyCode::KeyW);
+    const UP: InputSource = InputSource::Key(KeyCode::ArrowUp);
+    const SPACE: InputSource = InputSource::Key(KeyCode::Space);
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let mut bindings = Bindings::default();
+        bindings.bind("jump", &[InputSource::Key(KeyCode::KeyK), InputSource::Mouse(MouseButton::Middle)]);
+        bindings.bind("pause", &[]);
+        bindings.add("dance", InputSource::WheelUp);
+
+        let path = std::env::temp_dir().join(format!("poprustica_bindings_{}.txt", std::process::id()));
+        bindings.save(&path).unwrap();
+        let mut loaded = Bindings { actions: HashMap::new() };
+        let count = loaded.load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(count.unwrap(), bindings.actions().len());
+        assert_eq!(loaded.actions(), bindings.actions());
+        for action in bindings.actions() {
+            assert_eq!(loaded.sources(action), bindings.sources(action), "{}", action);
+        }
+        assert!(loaded.sources("pause").is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_skipped_and_reported_together() {
+        let mut bindings = Bindings::default();
+        let text = "# comment\n\njump KeyK\nmove_left KeyNope ArrowLeft\nmove_right Pedal\npause F1\n";
+        let error = bindings.parse(text, Path::new("bindings.txt")).unwrap_err().to_string();
+
+        assert!(error.contains("Skipped 2 of 4"), "{}", error);
+        assert!(error.contains("bindings.txt:4: unknown input 'KeyNope'"), "{}", error);
+        assert!(error.contains("bindings.txt:5: unknown input 'Pedal'"), "{}", error);
+        // Lines before and after the bad ones still apply, the bad ones keep their defaults
+        assert_eq!(bindings.sources("jump"), &[InputSource::Key(KeyCode::KeyK)]);
+        assert_eq!(bindings.sources("pause"), &[InputSource::Key(KeyCode::F1)]);
+        assert_eq!(bindings.sources("move_left"), Bindings::default().sources("move_left"));
+        assert_eq!(bindings.parse("jump Space", Path::new("")).unwrap(), 1);
+    }
+
+    #[test]
+    fn names_parse_back() {
+        for source in [W, SPACE, InputSource::Key(KeyCode::Numpad9), InputSource::Mouse(MouseButton::Left), InputSource::WheelDown] {
+            assert_eq!(InputSource::parse(&source.name()), Some(source));
+        }
+        assert_eq!(InputSource::parse("Keyw"), None);
+    }
+
+    #[test]
+    fn pressed_held_and_released() {
+        let mut input = Input::new(Bindings::default());
+
+        input.set_state(W, true);
+        assert!(input.is_pressed("jump") && input.is_held("jump") && !input.is_released("jump"));
+        assert_eq!(input.axis("move_down", "move_up"), 1.0);
+
+        input.end_frame();
+        input.set_state(UP, true);
+        // Already held through W, the second key is a new press for the source but the action stays held
+        assert!(input.is_source_pressed(UP) && input.is_pressed("jump"));
+        input.end_frame();
+        assert!(!input.is_pressed("jump") && input.is_held("jump"));
+
+        // Released only once the last of its inputs is up
+        input.set_state(W, false);
+        assert!(input.is_source_released(W) && !input.is_released("jump"));
+        input.end_frame();
+        input.set_state(UP, false);
+        assert!(input.is_released("jump") && !input.is_held("jump"));
+        input.end_frame();
+        assert!(!input.is_released("jump"));
+    }
+
+    #[test]
+    fn tapped_in_one_frame_is_pressed_and_released() {
+        let mut input = Input::new(Bindings::default());
+        input.set_state(SPACE, true);
+        input.set_state(SPACE, false);
+
+        assert!(input.is_pressed("confirm") && input.is_released("confirm") && !input.is_held("confirm"));
+        // Releasing something that was never down does nothing
+        input.end_frame();
+        input.set_state(SPACE, false);
+        assert!(!input.is_released("confirm"));
+    }
+
+    #[test]
+    fn wheel_and_focus_loss() {
+        let mut input = Input::new(Bindings::default());
+        input.scroll(-2.0);
+        assert!(input.is_pressed("zoom_out") && !input.is_pressed("zoom_in"));
+        assert_eq!(input.wheel(), -2.0);
+
+        input.set_state(W, true);
+        input.end_frame();
+        input.handle_event(&WindowEvent::Focused(false));
+        assert!(input.is_released("move_up") && !input.is_held("move_up"));
+        assert_eq!(input.wheel(), 0.0);
+    }
+}
@@ -0,0 +1,202 @@
+// This file contains the solid parts of a map, movement is stopped at their edges
+
+/// Solid cells on the map's tile grid, plus any free standing solid boxes
+/// One way floors and slopes only hold things up from above, movement through them is never blocked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollisionGrid {
+    size: [u32; 2],
+    cell_size: [f32; 2],
+    solid: Vec<bool>,
+    one_way: Vec<bool>,
+    // Boxes as (min, max) corners in map pixels
+    boxes: Vec<([f32; 2], [f32; 2])>,
+    one_way_boxes: Vec<([f32; 2], [f32; 2])>,
+    // Walkable lines as (left, right) ends
+    slopes: Vec<([f32; 2], [f32; 2])>,
+}
+
+impl CollisionGrid {
+    /// An empty grid `size` cells across, a size too big to count in memory gives a grid with no cells
+    pub fn new(size: [u32; 2], cell_size: [f32; 2]) -> Self {
+        let (size, cells) = match (size[0] as usize).checked_mul(size[1] as usize) {
+            Some(cells) => (size, cells),
+            None => ([0, 0], 0)
+        };
+        Self {
+            size,
+            cell_size,
+            solid: vec![false; cells],
+            one_way: vec![false; cells],
+            boxes: Vec::new(),
+            one_way_boxes: Vec::new(),
+            slopes: Vec::new(),
+        }
+    }
+
+    pub fn set_solid(&mut self, x: u32, y: u32, solid: bool) {
+        if let Some(index) = self.index(x as i64, y as i64) {
+            self.solid[index] = solid;
+        }
+    }
+
+    /// True if a cell is solid, cells outside the map are open
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.index(x as i64, y as i64).is_some_and(|index| self.solid[index])
+    }
+
+    /// Make a cell a floor that can be jumped up through and dropped down through
+    pub fn set_one_way(&mut self, x: u32, y: u32, one_way: bool) {
+        if let Some(index) = self.index(x as i64, y as i64) {
+            self.one_way[index] = one_way;
+        }
+    }
+
+    pub fn is_one_way(&self, x: i32, y: i32) -> bool {
+        self.index(x as i64, y as i64).is_some_and(|index| self.one_way[index])
+    }
+
+    /// Add a solid box, as (min, max) corners in map pixels
+    pub fn add_box(&mut self, min: [f32; 2], max: [f32; 2]) {
+        self.boxes.push((min, max));
+    }
+
+    /// Add a one way floor along the top of a box
+    pub fn add_one_way_box(&mut self, min: [f32; 2], max: [f32; 2]) {
+        self.one_way_boxes.push((min, max));
+    }
+
+    /// Add a walkable line between two points, e.g. a ramp, vertical lines are ignored
+    pub fn add_slope(&mut self, a: [f32; 2], b: [f32; 2]) {
+        if (a[0] - b[0]).abs() < f32::EPSILON {
+            return;
+        }
+        self.slopes.push(if a[0] < b[0] { (a, b) } else { (b, a) });
+    }
+
+    /// Get the cell a point is in
+    pub fn cell_at(&self, point: [f32; 2]) -> [i32; 2] {
+        [
+            (point[0] / self.cell_size[0].max(f32::EPSILON)).floor() as i32,
+            (point[1] / self.cell_size[1].max(f32::EPSILON)).floor() as i32,
+        ]
+    }
+
+    /// True if any solid area overlaps the box, touching edges don't count
+    pub fn overlaps(&self, min: [f32; 2], max: [f32; 2]) -> bool {
+        !self.solids_overlapping(min, max).is_empty()
+    }
+
+    /// Move a box by `delta`, one axis at a time so it slides along walls
+    /// Returns how far it actually moved, it stops flush against anything solid in the way
+    pub fn move_box(&self, min: [f32; 2], max: [f32; 2], delta: [f32; 2]) -> [f32; 2] {
+        let mut min: [f32; 2] = min;
+        let mut max: [f32; 2] = max;
+        let mut moved: [f32; 2] = [0.0, 0.0];
+
+        for axis in 0..2 {
+            if delta[axis] == 0.0 {
+                continue;
+            }
+
+            let mut target_min: [f32; 2] = min;
+            let mut target_max: [f32; 2] = max;
+            target_min[axis] += delta[axis];
+            target_max[axis] += delta[axis];
+
+            // Stop at the nearest solid edge in the direction of travel
+            let mut step: f32 = delta[axis];
+            for (solid_min, solid_max) in self.solids_overlapping(target_min, target_max) {
+                if delta[axis] > 0.0 && solid_min[axis] >= max[axis] {
+                    step = step.min(solid_min[axis] - max[axis]);
+                } else if delta[axis] < 0.0 && solid_max[axis] <= min[axis] {
+                    step = step.max(solid_max[axis] - min[axis]);
+                }
+            }
+
+            min[axis] += step;
+            max[axis] += step;
+            moved[axis] = step;
+        }
+
+        moved
+    }
+
+    /// Find the highest floor between the heights `from` and `to` under the span `min_x..max_x`, y points down
+    /// Tops of solid areas and slopes always count, one way floors only if `one_way` is set
+    /// Slopes are measured at the middle of the span, so things stand on them by their center
+    pub fn floor_below(&self, min_x: f32, max_x: f32, from: f32, to: f32, one_way: bool) -> Option<f32> {
+        let mut floor: Option<f32> = None;
+        let mut consider = |y: f32| {
+            if y >= from && y <= to && floor.is_none_or(|floor| y < floor) {
+                floor = Some(y);
+            }
+        };
+
+        if !self.solid.is_empty() {
+            let first: [i32; 2] = self.cell_at([min_x, from]);
+            let last: [i32; 2] = self.cell_at([max_x, to]);
+            for y in first[1]..=last[1] {
+                for x in first[0]..=last[0] {
+                    let cell_min_x: f32 = x as f32 * self.cell_size[0];
+                    let overlapping: bool = cell_min_x < max_x && min_x < cell_min_x + self.cell_size[0];
+                    if overlapping && (self.is_solid(x, y) || (one_way && self.is_one_way(x, y))) {
+                        consider(y as f32 * self.cell_size[1]);
+                    }
+                }
+            }
+        }
+
+        let one_way_boxes = self.one_way_boxes.iter().filter(|_| one_way);
+        for (box_min, box_max) in self.boxes.iter().chain(one_way_boxes) {
+            if box_min[0] < max_x && min_x < box_max[0] {
+                consider(box_min[1]);
+            }
+        }
+
+        let center: f32 = (min_x + max_x) / 2.0;
+        for (left, right) in &self.slopes {
+            if left[0] <= center && center <= right[0] {
+                consider(left[1] + (right[1] - left[1]) * (center - left[0]) / (right[0] - left[0]));
+            }
+        }
+
+        floor
+    }
+
+    // Every solid cell and box overlapping a box, as (min, max) corners
+    fn solids_overlapping(&self, min: [f32; 2], max: [f32; 2]) -> Vec<([f32; 2], [f32; 2])> {
+        let mut solids: Vec<([f32; 2], [f32; 2])> = Vec::new();
+
+        if !self.solid.is_empty() {
+            let first: [i32; 2] = self.cell_at(min);
+            let last: [i32; 2] = self.cell_at(max);
+            for y in first[1]..=last[1] {
+                for x in first[0]..=last[0] {
+                    if !self.is_solid(x, y) {
+                        continue;
+                    }
+                    let cell_min: [f32; 2] = [x as f32 * self.cell_size[0], y as f32 * self.cell_size[1]];
+                    let cell_max: [f32; 2] = [cell_min[0] + self.cell_size[0], cell_min[1] + self.cell_size[1]];
+                    if overlap(min, max, cell_min, cell_max) {
+                        solids.push((cell_min, cell_max));
+                    }
+                }
+            }
+        }
+
+        solids.extend(self.boxes.iter().filter(|(box_min, box_max)| overlap(min, max, *box_min, *box_max)));
+        solids
+    }
+
+    // Index of a cell in the flags, None off the grid
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.size[0] as i64 || y >= self.size[1] as i64 {
+            return None;
+        }
+        Some(y as usize * self.size[0] as usize + x as usize)
+    }
+}
+
+fn overlap(a_min: [f32; 2], a_max: [f32; 2], b_min: [f32; 2], b_max: [f32; 2]) -> bool {
+    a_min[0] < b_max[0] && b_min[0] < a_max[0] && a_min[1] < b_max[1] && b_min[1] < a_max[1]
+}
@@ -0,0 +1,864 @@
+// This file contains Tiled maps, their tile layers are drawn through the sprite batch and their object layers become entities
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf}
+};
+use anyhow::Error;
+use base64::Engine;
+
+use super::collision::CollisionGrid;
+use super::pathfinding::WalkGrid;
+use super::{tmj, tmx};
+use crate::client::collision::Shape;
+use crate::client::ecs::{Collider, Entity, SpriteRef, Tag, Transform, World};
+use crate::client::grafx::{BlendMode, Grafx, Layer, SpriteInstance};
+
+/// Bits Tiled sets on a tile ID to flip it, the rest of the ID is the tile
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+const TILE_ID_MASK: u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+
+/// Most tiles a map can have, 4096 by 4096
+const MAX_MAP_TILES: usize = 16_777_216;
+
+/// Draw depth of the first tile layer in the world layer, low enough that dynamic sprites at z 0 go over the map
+const TILE_LAYER_Z: i32 = -1000;
+
+/// Custom properties from Tiled, every value is kept as the text Tiled wrote
+pub type Properties = HashMap<String, String>;
+
+/// A tileset image cut into tiles, `first_gid` is the map wide ID of its first tile
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tileset {
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_size: [u32; 2],
+    pub columns: u32,
+    pub tile_count: u32,
+    /// Pixels around the edge of the image, and between tiles
+    pub margin: u32,
+    pub spacing: u32,
+    pub image: PathBuf,
+    pub image_size: [u32; 2],
+}
+
+impl Tileset {
+    /// The texture the tileset is drawn from, its image path so maps sharing a tileset share the texture
+    pub fn texture_key(&self) -> String {
+        self.image.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Get the texture area of a tile in the tileset, `id` counts from 0
+    pub fn uv_rect(&self, id: u32) -> [f32; 4] {
+        let columns = self.columns.max(1);
+        let x = self.margin + (id % columns) * (self.tile_size[0] + self.spacing);
+        let y = self.margin + (id / columns) * (self.tile_size[1] + self.spacing);
+        let width = self.image_size[0].max(1) as f32;
+        let height = self.image_size[1].max(1) as f32;
+        [x as f32 / width, y as f32 / height, self.tile_size[0] as f32 / width, self.tile_size[1] as f32 / height]
+    }
+}
+
+/// A grid of tile IDs, 0 is empty
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub size: [u32; 2],
+    /// Tile IDs row by row, including Tiled's flip bits
+    pub tiles: Vec<u32>,
+    /// Offset from the map origin in pixels
+    pub offset: [f32; 2],
+    pub opacity: f32,
+    pub visible: bool,
+    pub properties: Properties,
+}
+
+impl TileLayer {
+    /// Get the tile ID at a cell, with its flip bits
+    pub fn tile(&self, x: u32, y: u32) -> u32 {
+        if x >= self.size[0] || y >= self.size[1] {
+            return 0;
+        }
+        self.tiles.get(y as usize * self.size[0] as usize + x as usize).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    /// Points relative to the object's position
+    Polygon(Vec<[f32; 2]>),
+    Polyline(Vec<[f32; 2]>),
+}
+
+/// Something placed in an object layer, like an NPC, a door or a spawn point
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    /// What kind of object it is, Tiled calls this the class (or type in older versions)
+    pub class: String,
+    /// Top left corner, or bottom left for tile objects
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Clockwise, in degrees
+    pub rotation: f32,
+    /// Set on tile objects, the tile they show with its flip bits
+    pub gid: Option<u32>,
+    pub shape: ObjectShape,
+    pub visible: bool,
+    pub properties: Properties,
+}
+
+impl MapObject {
+    /// Center of the object in map pixels, accounting for its rotation
+    pub fn center(&self) -> [f32; 2] {
+        // Tile objects hang up from their position, everything else down from it
+        let local: [f32; 2] = match self.gid {
+            Some(_) => [self.size[0] / 2.0, -self.size[1] / 2.0],
+            None => [self.size[0] / 2.0, self.size[1] / 2.0],
+        };
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        [
+            self.position[0] + local[0] * cos - local[1] * sin,
+            self.position[1] + local[0] * sin + local[1] * cos,
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub objects: Vec<MapObject>,
+    pub visible: bool,
+    pub properties: Properties,
+}
+
+/// Layers in the order Tiled draws them, bottom first
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapLayer {
+    Tiles(TileLayer),
+    Objects(ObjectLayer),
+}
+
+impl MapLayer {
+    pub fn name(&self) -> &str {
+        match self {
+            MapLayer::Tiles(layer) => &layer.name,
+            MapLayer::Objects(layer) => &layer.name,
+        }
+    }
+
+    pub fn properties(&self) -> &Properties {
+        match self {
+            MapLayer::Tiles(layer) => &layer.properties,
+            MapLayer::Objects(layer) => &layer.properties,
+        }
+    }
+
+    /// Collision layers are named "collision" or have a `collision` property set to true, they aren't drawn or spawned
+    pub fn is_collision(&self) -> bool {
+        self.name().eq_ignore_ascii_case("collision")
+            || self.properties().get("collision").is_some_and(|value| value == "true")
+    }
+}
+
+/// An orthogonal Tiled map
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMap {
+    /// Size in tiles
+    pub size: [u32; 2],
+    pub tile_size: [u32; 2],
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<MapLayer>,
+    pub properties: Properties,
+    /// Solid areas from the collision layers
+    pub collision: CollisionGrid,
+    /// Tiles that can be walked on for click to move, from the collision layers
+    pub walk_grid: WalkGrid,
+}
+
+impl TileMap {
+    /// Read a map saved by Tiled as JSON (.tmj or .json) or XML (.tmx), external tilesets are read too
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read map '{}': {}", path.display(), err))?;
+        let dir: &Path = path.parent().unwrap_or(Path::new(""));
+
+        let extension: String = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let map: TileMap = match extension.as_str() {
+            "tmx" => tmx::parse_map(&text, dir),
+            _ => tmj::parse_map(&text, dir)
+        }.map_err(|err| anyhow::anyhow!("Failed to load map '{}': {}", path.display(), err))?;
+
+        Ok(map)
+    }
+
+    /// Size of the map in pixels
+    pub fn pixel_size(&self) -> [f32; 2] {
+        [self.size[0] as f32 * self.tile_size[0] as f32, self.size[1] as f32 * self.tile_size[1] as f32]
+    }
+
+    /// Get the tile a position in map pixels is on, None off the map
+    pub fn tile_at(&self, position: [f32; 2]) -> Option<[u32; 2]> {
+        let x: f32 = (position[0] / self.tile_size[0] as f32).floor();
+        let y: f32 = (position[1] / self.tile_size[1] as f32).floor();
+        if x < 0.0 || y < 0.0 || x >= self.size[0] as f32 || y >= self.size[1] as f32 {
+            return None;
+        }
+        Some([x as u32, y as u32])
+    }
+
+    /// Center of a tile in map pixels
+    pub fn tile_center(&self, tile: [u32; 2]) -> [f32; 2] {
+        [(tile[0] as f32 + 0.5) * self.tile_size[0] as f32, (tile[1] as f32 + 0.5) * self.tile_size[1] as f32]
+    }
+
+    /// Find the tileset a tile ID belongs to, and the tile's index in it
+    pub fn tileset_for(&self, gid: u32) -> Option<(&Tileset, u32)> {
+        let id = gid & TILE_ID_MASK;
+        if id == 0 {
+            return None;
+        }
+
+        // Tilesets are sorted by first ID, the tile is in the last one that starts at or before it
+        let tileset = self.tilesets.iter().rev().find(|tileset| tileset.first_gid <= id)?;
+        Some((tileset, id - tileset.first_gid))
+    }
+
+    /// Every object in every object layer that isn't a collision layer
+    pub fn objects(&self) -> impl Iterator<Item = &MapObject> {
+        self.layers.iter()
+            .filter(|layer| !layer.is_collision())
+            .filter_map(|layer| match layer {
+                MapLayer::Objects(objects) => Some(objects.objects.iter()),
+                MapLayer::Tiles(_) => None
+            })
+            .flatten()
+    }
+
+    /// Find an object by name, e.g. a spawn point
+    pub fn find_object(&self, name: &str) -> Option<&MapObject> {
+        self.objects().find(|object| object.name == name)
+    }
+
+    /// Start loading every tileset's image in the background, tiles are drawn with the missing texture until theirs is loaded
+    pub fn queue_tilesets(&self, grafx: &mut Grafx) {
+        for tileset in &self.tilesets {
+            let key: String = tileset.texture_key();
+            grafx.queue_texture(&key, &key);
+        }
+    }
+
+    /// Queue the tiles the camera can see for this frame, layers are stacked in Tiled's order under the dynamic sprites
+    pub fn draw(&self, grafx: &mut Grafx) {
+        let camera = grafx.camera();
+        let half: [f32; 2] = [camera.visible_size()[0] / 2.0, camera.visible_size()[1] / 2.0];
+        let view_min: [f32; 2] = [camera.position[0] - half[0], camera.position[1] - half[1]];
+        let view_max: [f32; 2] = [camera.position[0] + half[0], camera.position[1] + half[1]];
+        let tile_size: [f32; 2] = [self.tile_size[0] as f32, self.tile_size[1] as f32];
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            let layer: &TileLayer = match layer {
+                MapLayer::Tiles(tiles) if tiles.visible && !layer.is_collision() => tiles,
+                _ => continue
+            };
+
+            // Only the cells on screen, with a tile of slack for tiles bigger than the grid
+            let first: [u32; 2] = [
+                ((view_min[0] - layer.offset[0]) / tile_size[0] - 1.0).max(0.0) as u32,
+                ((view_min[1] - layer.offset[1]) / tile_size[1] - 1.0).max(0.0) as u32,
+            ];
+            let last: [u32; 2] = [
+                (((view_max[0] - layer.offset[0]) / tile_size[0]).max(0.0) as u32 + 1).min(layer.size[0]),
+                (((view_max[1] - layer.offset[1]) / tile_size[1]).max(0.0) as u32 + 2).min(layer.size[1]),
+            ];
+
+            for y in first[1]..last[1] {
+                for x in first[0]..last[0] {
+                    let gid = layer.tile(x, y);
+                    let (tileset, id) = match self.tileset_for(gid) {
+                        Some(found) => found,
+                        None => continue
+                    };
+
+                    // Tiles bigger than the grid stick up out of their cell, anchored at its bottom left
+                    let size: [f32; 2] = [tileset.tile_size[0] as f32, tileset.tile_size[1] as f32];
+                    let center: [f32; 2] = [
+                        layer.offset[0] + x as f32 * tile_size[0] + size[0] / 2.0,
+                        layer.offset[1] + (y + 1) as f32 * tile_size[1] - size[1] / 2.0,
+                    ];
+
+                    let mut instance: SpriteInstance = tile_instance(gid, center, size);
+                    instance.uv_rect = tileset.uv_rect(id);
+                    instance.color[3] = layer.opacity;
+                    grafx.draw_sprite(&tileset.texture_key(), None, instance, Layer::World, TILE_LAYER_Z + index as i32, BlendMode::Alpha);
+                }
+            }
+        }
+    }
+
+    /// Spawn an entity for every object, tile objects get a sprite and areas get a trigger collider, e.g. for doors
+    /// Areas with a `solid` property set to true get a solid collider instead, e.g. for crates
+    /// Each gets a tag with the object's name and class so game code can find its NPCs, doors and spawn points
+    pub fn spawn_objects(&self, world: &mut World) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = Vec::new();
+
+        for object in self.objects() {
+            let entity: Entity = world.spawn();
+            world.insert(entity, Transform::new(object.center()));
+            world.insert(entity, Tag::new(&object.name, &object.class));
+
+            if let Some(gid) = object.gid
+                && let Some((tileset, id)) = self.tileset_for(gid) {
+                let instance: SpriteInstance = tile_instance(gid, [0.0, 0.0], object.size);
+                let mut sprite: SpriteRef = SpriteRef::new(&tileset.texture_key(), instance.scale);
+                sprite.uv_rect = tileset.uv_rect(id);
+                sprite.visible = object.visible;
+                world.insert(entity, sprite);
+                if let Some(transform) = world.transforms.get_mut(entity) {
+                    transform.rotation = object.rotation.to_radians() + instance.rotation;
+                }
+            } else if let Some(collider) = object_collider(object) {
+                world.insert(entity, collider);
+            }
+
+            entities.push(entity);
+        }
+
+        entities
+    }
+}
+
+// Collider for an area object around its center, turned boxes become polygons and concave polygons fall back to the box around their points
+fn object_collider(object: &MapObject) -> Option<Collider> {
+    let center: [f32; 2] = object.center();
+    let has_size: bool = object.size[0] > 0.0 && object.size[1] > 0.0;
+    let mut collider: Collider = match &object.shape {
+        ObjectShape::Rectangle if has_size && object.rotation == 0.0 => Collider::new(object.size),
+        ObjectShape::Ellipse if has_size => Collider::from_shape(Shape::circle(object.size[0].min(object.size[1]) / 2.0)),
+        ObjectShape::Rectangle if has_size => polygon_collider(object, center),
+        ObjectShape::Polygon(_) => polygon_collider(object, center),
+        _ => return None
+    };
+
+    collider.trigger = object.properties.get("solid").is_none_or(|solid| solid != "true");
+    Some(collider)
+}
+
+fn polygon_collider(object: &MapObject, center: [f32; 2]) -> Collider {
+    let points: Vec<[f32; 2]> = outline(object).iter()
+        .map(|point| [point[0] - center[0], point[1] - center[1]])
+        .collect();
+    match Shape::polygon(points.clone()) {
+        Some(shape) => Collider::from_shape(shape),
+        None => {
+            let (min, max) = Shape::Polygon { points }.bounds([0.0, 0.0]);
+            let mut collider: Collider = Collider::new([max[0] - min[0], max[1] - min[1]]);
+            collider.offset = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+            collider
+        }
+    }
+}
+
+// Points of an object's outline in map pixels, turned around its position by its rotation the way Tiled does
+// Rectangles and ellipses give the corners of their box, points have no outline
+fn outline(object: &MapObject) -> Vec<[f32; 2]> {
+    let points: Vec<[f32; 2]> = match &object.shape {
+        ObjectShape::Polygon(points) | ObjectShape::Polyline(points) => points.clone(),
+        ObjectShape::Rectangle | ObjectShape::Ellipse => vec![[0.0, 0.0], [object.size[0], 0.0], object.size, [0.0, object.size[1]]],
+        ObjectShape::Point => Vec::new(),
+    };
+
+    let (sin, cos) = object.rotation.to_radians().sin_cos();
+    points.into_iter()
+        .map(|[x, y]| [object.position[0] + x * cos - y * sin, object.position[1] + x * sin + y * cos])
+        .collect()
+}
+
+// Size and rotation of a tile with Tiled's flip bits applied
+// A diagonal flip swaps x and y, which is a quarter turn and a flip of the other axis
+fn tile_instance(gid: u32, center: [f32; 2], size: [f32; 2]) -> SpriteInstance {
+    let horizontal: f32 = if gid & FLIPPED_HORIZONTALLY != 0 { -1.0 } else { 1.0 };
+    let vertical: f32 = if gid & FLIPPED_VERTICALLY != 0 { -1.0 } else { 1.0 };
+
+    if gid & FLIPPED_DIAGONALLY != 0 {
+        SpriteInstance::new(center, [size[0] * vertical, -size[1] * horizontal], std::f32::consts::FRAC_PI_2)
+    } else {
+        SpriteInstance::new(center, [size[0] * horizontal, size[1] * vertical], 0.0)
+    }
+}
+
+/// Refuse maps with more tiles than `MAX_MAP_TILES`, their collision and walk grids would be too big to allocate
+pub(super) fn check_size(size: [u32; 2]) -> Result<(), Error> {
+    match (size[0] as usize).checked_mul(size[1] as usize) {
+        Some(tiles) if tiles <= MAX_MAP_TILES => Ok(()),
+        _ => Err(anyhow::anyhow!("Map is {}x{} tiles, at most {} tiles are supported", size[0], size[1], MAX_MAP_TILES))
+    }
+}
+
+/// Decode a layer's tile IDs, stored as CSV or little endian base64 that may be zlib or gzip compressed
+pub(super) fn decode_tiles(data: &str, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>, Error> {
+    match encoding {
+        Some("csv") => data.split(',')
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<u32>().map_err(|err| anyhow::anyhow!("Bad tile ID '{}': {}", value, err)))
+            .collect(),
+        Some("base64") => {
+            let cleaned: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            let bytes: Vec<u8> = base64::engine::general_purpose::STANDARD.decode(cleaned)
+                .map_err(|err| anyhow::anyhow!("Bad base64 tile data: {}", err))?;
+
+            let mut decompressed: Vec<u8> = Vec::new();
+            let bytes: Vec<u8> = match compression {
+                None | Some("") => bytes,
+                Some("zlib") => {
+                    flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+                    decompressed
+                }
+                Some("gzip") => {
+                    flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+                    decompressed
+                }
+                Some(other) => return Err(anyhow::anyhow!("Unsupported tile compression '{}'", other))
+            };
+
+            Ok(bytes.chunks_exact(4).map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect())
+        }
+        Some(other) => Err(anyhow::anyhow!("Unsupported tile encoding '{}'", other)),
+        None => Err(anyhow::anyhow!("Tile data has no encoding"))
+    }
+}
+
+/// Build the collision grid from a map's collision layers, tile layers mark whole cells and object layers add boxes
+/// Layers or objects with a `one_way` property set to true are floors that can be jumped up through
+/// Polygons and turned boxes add their upper edges as slopes and polylines add every segment, both can be walked up and down
+pub(super) fn build_collision(size: [u32; 2], tile_size: [u32; 2], layers: &[MapLayer]) -> CollisionGrid {
+    let tile_size: [f32; 2] = [tile_size[0] as f32, tile_size[1] as f32];
+    let mut collision: CollisionGrid = CollisionGrid::new(size, tile_size);
+    let is_one_way = |properties: &Properties| properties.get("one_way").is_some_and(|value| value == "true");
+
+    for layer in layers.iter().filter(|layer| layer.is_collision()) {
+        let one_way_layer: bool = is_one_way(layer.properties());
+
+        match layer {
+            MapLayer::Tiles(tiles) => {
+                // Layers moved off the grid add a box per tile instead of marking cells
+                let aligned: bool = tiles.offset == [0.0, 0.0];
+
+                for y in 0..tiles.size[1].min(size[1]) {
+                    for x in 0..tiles.size[0].min(size[0]) {
+                        if tiles.tile(x, y) & TILE_ID_MASK == 0 {
+                            continue;
+                        }
+
+                        let min: [f32; 2] = [tiles.offset[0] + x as f32 * tile_size[0], tiles.offset[1] + y as f32 * tile_size[1]];
+                        let max: [f32; 2] = [min[0] + tile_size[0], min[1] + tile_size[1]];
+                        match (aligned, one_way_layer) {
+                            (true, true) => collision.set_one_way(x, y, true),
+                            (true, false) => collision.set_solid(x, y, true),
+                            (false, true) => collision.add_one_way_box(min, max),
+                            (false, false) => collision.add_box(min, max),
+                        }
+                    }
+                }
+            }
+            MapLayer::Objects(objects) => {
+                for object in &objects.objects {
+                    let points: Vec<[f32; 2]> = outline(object);
+
+                    match &object.shape {
+                        ObjectShape::Point => {}
+                        ObjectShape::Polyline(_) => {
+                            for pair in points.windows(2) {
+                                collision.add_slope(pair[0], pair[1]);
+                            }
+                        }
+                        ObjectShape::Rectangle | ObjectShape::Ellipse if object.rotation == 0.0 => {
+                            let max: [f32; 2] = [object.position[0] + object.size[0], object.position[1] + object.size[1]];
+                            if one_way_layer || is_one_way(&object.properties) {
+                                collision.add_one_way_box(object.position, max);
+                            } else {
+                                collision.add_box(object.position, max);
+                            }
+                        }
+                        _ => {
+                            for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+                                // An upper edge has the inside of the polygon just below it
+                                let middle: [f32; 2] = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0 + 0.5];
+                                if contains(&points, middle) {
+                                    collision.add_slope(*a, *b);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    collision
+}
+
+// True if a point is inside a polygon, by counting the edges a ray to the right crosses
+fn contains(points: &[[f32; 2]], point: [f32; 2]) -> bool {
+    let mut inside: bool = false;
+    for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+        if (a[1] > point[1]) != (b[1] > point[1]) {
+            let crossing: f32 = a[0] + (point[1] - a[1]) * (b[0] - a[0]) / (b[1] - a[1]);
+            if point[0] < crossing {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Build the walk grid from a map's collision layers and solid objects, solid tiles and areas block the tiles they cover
+/// Polylines along tile edges are walls and fences that block the edge between two tiles, one way floors don't block anything
+/// Tile layers moved off the grid block the cells their tiles are nearest to
+pub(super) fn build_walk_grid(size: [u32; 2], tile_size: [u32; 2], layers: &[MapLayer]) -> WalkGrid {
+    let mut grid: WalkGrid = WalkGrid::new(size);
+    let tile_size: [f32; 2] = [tile_size[0] as f32, tile_size[1] as f32];
+    let is_one_way = |properties: &Properties| properties.get("one_way").is_some_and(|value| value == "true");
+    // Areas outside collision layers with a `solid` property get solid colliders when spawned, walkers go around them too
+    let is_solid = |object: &MapObject| object.gid.is_none() && !matches!(object.shape, ObjectShape::Polyline(_))
+        && object.properties.get("solid").is_some_and(|value| value == "true");
+
+    for layer in layers.iter().filter(|layer| !is_one_way(layer.properties())) {
+        let collision_layer: bool = layer.is_collision();
+
+        match layer {
+            MapLayer::Tiles(_) if !collision_layer => {}
+            MapLayer::Tiles(tiles) => {
+                let shift: [i64; 2] = [
+                    (tiles.offset[0] / tile_size[0].max(1.0)).round() as i64,
+                    (tiles.offset[1] / tile_size[1].max(1.0)).round() as i64,
+                ];
+                for y in 0..tiles.size[1].min(size[1]) {
+                    for x in 0..tiles.size[0].min(size[0]) {
+                        let cell: [i64; 2] = [x as i64 + shift[0], y as i64 + shift[1]];
+                        let on_map: bool = cell[0] >= 0 && cell[1] >= 0 && cell[0] < size[0] as i64 && cell[1] < size[1] as i64;
+                        if on_map && tiles.tile(x, y) & TILE_ID_MASK != 0 {
+                            grid.set_blocked(cell[0] as u32, cell[1] as u32, true);
+                        }
+                    }
+                }
+            }
+            MapLayer::Objects(objects) => {
+                let blocks = |object: &&MapObject| if collision_layer { !is_one_way(&object.properties) } else { is_solid(object) };
+                for object in objects.objects.iter().filter(blocks) {
+                    let points: Vec<[f32; 2]> = outline(object);
+
+                    if let ObjectShape::Polyline(_) = &object.shape {
+                        for pair in points.windows(2) {
+                            block_edges(&mut grid, tile_size, pair[0], pair[1]);
+                        }
+                        continue;
+                    }
+                    if points.is_empty() {
+                        continue;
+                    }
+
+                    // Block every tile whose center is inside the area, only looking at the tiles around it
+                    let (min, max) = Shape::Polygon { points: points.clone() }.bounds([0.0, 0.0]);
+                    let first: [u32; 2] = [(min[0] / tile_size[0]).floor().max(0.0) as u32, (min[1] / tile_size[1]).floor().max(0.0) as u32];
+                    let last: [u32; 2] = [
+                        ((max[0] / tile_size[0]).ceil().max(0.0) as u32).min(size[0]),
+                        ((max[1] / tile_size[1]).ceil().max(0.0) as u32).min(size[1]),
+                    ];
+                    for y in first[1]..last[1] {
+                        for x in first[0]..last[0] {
+                            let center: [f32; 2] = [(x as f32 + 0.5) * tile_size[0], (y as f32 + 0.5) * tile_size[1]];
+                            if contains(&points, center) {
+                                grid.set_blocked(x, y, true);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    grid
+}
+
+// Put walls along the tile edges a line runs over, lines that don't follow the grid are ignored
+fn block_edges(grid: &mut WalkGrid, tile_size: [f32; 2], a: [f32; 2], b: [f32; 2]) {
+    // Axis 0 runs along x and walls off the rows above and below it, axis 1 runs along y and walls off columns
+    for axis in 0..2 {
+        let across: usize = 1 - axis;
+        if (a[across] - b[across]).abs() > 0.5 {
+            continue;
+        }
+
+        let line: i64 = (a[across] / tile_size[across]).round() as i64;
+        let start: i64 = (a[axis].min(b[axis]) / tile_size[axis] + 0.01).floor() as i64;
+        let end: i64 = (a[axis].max(b[axis]) / tile_size[axis] - 0.01).ceil() as i64;
+        if line < 1 {
+            continue;
+        }
+
+        for along in start.max(0)..end {
+            let mut before: [u32; 2] = [0, 0];
+            before[axis] = along as u32;
+            before[across] = (line - 1) as u32;
+            let mut after: [u32; 2] = before;
+            after[across] = line as u32;
+            grid.block_between(before, after);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+    use crate::client::map::Diagonals;
+
+    /// A layer named "collision" from rows of text, '#' is a tile
+    fn collision_tiles(rows: &[&str], offset: [f32; 2], properties: &[(&str, &str)]) -> MapLayer {
+        MapLayer::Tiles(TileLayer {
+            name: String::from("collision"),
+            size: [rows[0].len() as u32, rows.len() as u32],
+            tiles: rows.iter().flat_map(|row| row.chars().map(|cell| if cell == '#' { 1 } else { 0 })).collect(),
+            offset,
+            opacity: 1.0,
+            visible: true,
+            properties: properties.iter().map(|(name, value)| (String::from(*name), String::from(*value))).collect(),
+        })
+    }
+
+    fn objects(name: &str, objects: Vec<MapObject>) -> MapLayer {
+        MapLayer::Objects(ObjectLayer { name: String::from(name), objects, visible: true, properties: Properties::new() })
+    }
+
+    fn object(shape: ObjectShape, position: [f32; 2], size: [f32; 2], rotation: f32) -> MapObject {
+        MapObject {
+            id: 1,
+            name: String::new(),
+            class: String::new(),
+            position,
+            size,
+            rotation,
+            gid: None,
+            shape,
+            visible: true,
+            properties: Properties::new(),
+        }
+    }
+
+    fn little_endian(ids: &[u32]) -> Vec<u8> {
+        ids.iter().flat_map(|id| id.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decodes_csv_and_base64() {
+        assert_eq!(decode_tiles("1,2,\n0,3\n", Some("csv"), None).unwrap(), vec![1, 2, 0, 3]);
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(little_endian(&[5, 0, 7]));
+        assert_eq!(decode_tiles(&format!("\n  {}\n", encoded), Some("base64"), None).unwrap(), vec![5, 0, 7]);
+
+        assert!(decode_tiles("1,x", Some("csv"), None).is_err());
+        assert!(decode_tiles("AAAA", Some("base64"), Some("zstd")).is_err());
+        assert!(decode_tiles("1,2", Some("hex"), None).is_err());
+    }
+
+    #[test]
+    fn decodes_compressed_base64() {
+        let bytes = little_endian(&[1, 2, 3, 4]);
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&bytes).unwrap();
+        let zlib = base64::engine::general_purpose::STANDARD.encode(zlib.finish().unwrap());
+        assert_eq!(decode_tiles(&zlib, Some("base64"), Some("zlib")).unwrap(), vec![1, 2, 3, 4]);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&bytes).unwrap();
+        let gzip = base64::engine::general_purpose::STANDARD.encode(gzip.finish().unwrap());
+        assert_eq!(decode_tiles(&gzip, Some("base64"), Some("gzip")).unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn keeps_flip_bits_and_masks_them_off_the_tile() {
+        let flipped = FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY | 12;
+        let tiles = decode_tiles(&format!("{},{}", flipped, FLIPPED_VERTICALLY | 3), Some("csv"), None).unwrap();
+        assert_eq!(tiles, vec![flipped, FLIPPED_VERTICALLY | 3]);
+
+        let tileset = |first_gid: u32| Tileset {
+            first_gid,
+            name: String::new(),
+            tile_size: [16, 16],
+            columns: 4,
+            tile_count: 8,
+            margin: 0,
+            spacing: 0,
+            image: PathBuf::from("tiles.png"),
+            image_size: [64, 32],
+        };
+        let map = TileMap {
+            size: [1, 1],
+            tile_size: [16, 16],
+            tilesets: vec![tileset(1), tileset(9)],
+            layers: Vec::new(),
+            properties: Properties::new(),
+            collision: CollisionGrid::default(),
+            walk_grid: WalkGrid::default(),
+        };
+
+        let (found, id) = map.tileset_for(tiles[0]).unwrap();
+        assert_eq!((found.first_gid, id), (9, 3));
+        let (found, id) = map.tileset_for(tiles[1]).unwrap();
+        assert_eq!((found.first_gid, id), (1, 2));
+        assert!(map.tileset_for(FLIPPED_HORIZONTALLY).is_none());
+
+        let instance = tile_instance(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | 1, [0.0, 0.0], [16.0, 8.0]);
+        assert_eq!((instance.scale, instance.rotation), ([-16.0, -8.0], 0.0));
+    }
+
+    #[test]
+    fn refuses_maps_too_big_to_allocate() {
+        assert!(check_size([4096, 4096]).is_ok());
+        assert!(check_size([4097, 4096]).is_err());
+        assert!(check_size([u32::MAX, u32::MAX]).is_err());
+    }
+
+    #[test]
+    fn collision_tiles_mark_cells() {
+        let layers = vec![
+            collision_tiles(&["#..", "..#"], [0.0, 0.0], &[]),
+            collision_tiles(&["...", ".#."], [0.0, 0.0], &[("one_way", "true")]),
+        ];
+        let collision = build_collision([3, 2], [16, 16], &layers);
+
+        assert!(collision.is_solid(0, 0));
+        assert!(collision.is_solid(2, 1));
+        assert!(!collision.is_solid(1, 1));
+        assert!(collision.is_one_way(1, 1));
+        assert!(!collision.is_one_way(0, 0));
+    }
+
+    #[test]
+    fn offset_collision_tiles_become_boxes() {
+        let layers = vec![collision_tiles(&["#."], [8.0, 4.0], &[])];
+        let collision = build_collision([2, 1], [16, 16], &layers);
+
+        assert!(!collision.is_solid(0, 0));
+        assert!(!collision.overlaps([0.0, 0.0], [8.0, 16.0]));
+        assert!(collision.overlaps([20.0, 10.0], [30.0, 12.0]));
+        assert_eq!(collision.floor_below(10.0, 20.0, 0.0, 16.0, false), Some(4.0));
+    }
+
+    #[test]
+    fn polygons_and_turned_boxes_add_their_upper_edges() {
+        // A ramp up to the right, and a 32x16 box turned a quarter turn to stand up 16 wide and 32 tall
+        let ramp = object(ObjectShape::Polygon(vec![[0.0, 32.0], [32.0, 0.0], [32.0, 32.0]]), [0.0, 0.0], [0.0, 0.0], 0.0);
+        let turned = object(ObjectShape::Rectangle, [80.0, 16.0], [32.0, 16.0], 90.0);
+        let collision = build_collision([8, 4], [16, 16], &[objects("collision", vec![ramp, turned])]);
+
+        assert_eq!(collision.floor_below(12.0, 20.0, 0.0, 32.0, false), Some(16.0));
+        let floor = collision.floor_below(68.0, 76.0, 0.0, 32.0, false).unwrap();
+        assert!((floor - 16.0).abs() < 0.001);
+        // The turned box's original footprint is empty
+        assert_eq!(collision.floor_below(84.0, 108.0, 0.0, 64.0, false), None);
+    }
+
+    #[test]
+    fn boxes_and_one_way_objects() {
+        let mut floor = object(ObjectShape::Rectangle, [0.0, 32.0], [32.0, 8.0], 0.0);
+        floor.properties.insert(String::from("one_way"), String::from("true"));
+        let wall = object(ObjectShape::Rectangle, [48.0, 0.0], [16.0, 48.0], 0.0);
+        let collision = build_collision([4, 4], [16, 16], &[objects("collision", vec![floor, wall])]);
+
+        assert!(collision.overlaps([50.0, 10.0], [52.0, 12.0]));
+        assert!(!collision.overlaps([10.0, 34.0], [12.0, 36.0]));
+        assert_eq!(collision.floor_below(0.0, 16.0, 0.0, 48.0, false), None);
+        assert_eq!(collision.floor_below(0.0, 16.0, 0.0, 48.0, true), Some(32.0));
+    }
+
+    #[test]
+    fn walk_grid_blocks_collision_tiles_and_solid_objects() {
+        let mut crate_object = object(ObjectShape::Rectangle, [64.0, 0.0], [32.0, 16.0], 0.0);
+        crate_object.properties.insert(String::from("solid"), String::from("true"));
+        let trigger = object(ObjectShape::Rectangle, [0.0, 32.0], [32.0, 16.0], 0.0);
+        let layers = vec![
+            collision_tiles(&["#.....", "......", "......"], [0.0, 0.0], &[]),
+            collision_tiles(&["......", "..#...", "......"], [0.0, 0.0], &[("one_way", "true")]),
+            collision_tiles(&["......", "......", "#....."], [16.0, -16.0], &[]),
+            objects("things", vec![crate_object, trigger]),
+        ];
+        let grid = build_walk_grid([6, 3], [16, 16], &layers);
+
+        assert!(!grid.is_walkable(0, 0));
+        assert!(grid.is_walkable(2, 1));
+        // The offset layer's tile lands one cell right and one up
+        assert!(!grid.is_walkable(1, 1));
+        assert!(grid.is_walkable(0, 2));
+        assert!(!grid.is_walkable(4, 0));
+        assert!(!grid.is_walkable(5, 0));
+        assert!(grid.is_walkable(1, 2));
+    }
+
+    #[test]
+    fn walk_grid_blocks_turned_areas() {
+        // A 48x16 box turned a quarter turn stands in the column left of its position
+        let wall = object(ObjectShape::Rectangle, [32.0, 0.0], [48.0, 16.0], 90.0);
+        let grid = build_walk_grid([4, 4], [16, 16], &[objects("collision", vec![wall])]);
+
+        for y in 0..3 {
+            assert!(!grid.is_walkable(1, y));
+            assert!(grid.is_walkable(2, y));
+        }
+        assert!(grid.is_walkable(1, 3));
+    }
+
+    #[test]
+    fn fences_block_steps_between_tiles() {
+        let fence = object(ObjectShape::Polyline(vec![[0.0, 0.0], [32.0, 0.0]]), [0.0, 16.0], [0.0, 0.0], 0.0);
+        let grid = build_walk_grid([3, 2], [16, 16], &[objects("collision", vec![fence])]);
+
+        assert!(grid.is_walkable(0, 0) && grid.is_walkable(0, 1));
+        assert!(!grid.can_step([0, 0], [0, 1], Diagonals::Never));
+        assert!(!grid.can_step([1, 1], [0, -1], Diagonals::Never));
+        assert!(grid.can_step([2, 0], [0, 1], Diagonals::Never));
+        assert!(grid.can_step([0, 0], [1, 0], Diagonals::Never));
+    }
+
+    #[test]
+    fn block_edges_follows_the_grid() {
+        let mut grid = WalkGrid::new([3, 3]);
+        block_edges(&mut grid, [16.0, 16.0], [32.0, 0.0], [32.0, 32.0]);
+        // Lines off the grid lines and along the map edge block nothing
+        block_edges(&mut grid, [16.0, 16.0], [0.0, 0.0], [48.0, 48.0]);
+        block_edges(&mut grid, [16.0, 16.0], [0.0, 0.0], [48.0, 0.0]);
+
+        assert!(!grid.can_step([1, 0], [1, 0], Diagonals::Never));
+        assert!(!grid.can_step([2, 1], [-1, 0], Diagonals::Never));
+        assert!(grid.can_step([1, 2], [1, 0], Diagonals::Never));
+        assert!(grid.can_step([0, 0], [1, 0], Diagonals::Never));
+        assert!(grid.can_step([0, 0], [0, 1], Diagonals::Never));
+    }
+
+    #[test]
+    fn turned_boxes_get_polygon_colliders() {
+        let mut turned = object(ObjectShape::Rectangle, [0.0, 0.0], [32.0, 16.0], 90.0);
+        turned.properties.insert(String::from("solid"), String::from("true"));
+        let collider = object_collider(&turned).unwrap();
+
+        assert!(!collider.trigger);
+        let (min, max) = collider.shape.bounds(turned.center());
+        assert!((min[0] + 16.0).abs() < 0.001 && (max[0] - 0.0).abs() < 0.001);
+        assert!((min[1] - 0.0).abs() < 0.001 && (max[1] - 32.0).abs() < 0.001);
+
+        let upright = object(ObjectShape::Rectangle, [0.0, 0.0], [32.0, 16.0], 0.0);
+        let collider = object_collider(&upright).unwrap();
+        assert!(collider.trigger);
+        assert_eq!(collider.shape, Shape::aabb([32.0, 16.0]));
+    }
+}
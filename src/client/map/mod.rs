@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod map;
+mod collision;
+mod tmj;
+mod tmx;
+mod pathfinding;
+pub use self::map::{MapLayer, MapObject, ObjectLayer, ObjectShape, Properties, TileLayer, TileMap, Tileset};
+pub use self::collision::CollisionGrid;
+pub use self::pathfinding::{find_path, find_path_towards, Diagonals, WalkGrid};
\ No newline at end of file
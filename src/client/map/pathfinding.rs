@@ -0,0 +1,343 @@
+// This file contains the pathfinder for click to move, A* over a grid of tiles that can or can't be walked on
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap
+};
+
+/// Costs of a step, diagonals are about √2 times longer
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// Bits for the walls along each side of a tile, y points down so north is up the screen
+const WALL_NORTH: u8 = 1;
+const WALL_EAST: u8 = 2;
+const WALL_SOUTH: u8 = 4;
+const WALL_WEST: u8 = 8;
+
+/// Every step a path can take, straight ones first so ties prefer them
+const STEPS: [[i32; 2]; 8] = [[0, -1], [1, 0], [0, 1], [-1, 0], [1, -1], [1, 1], [-1, 1], [-1, -1]];
+
+/// When a path may step diagonally
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Diagonals {
+    /// Only straight steps
+    Never,
+    /// Only if both straight ways around the corner are open, so paths never clip the corner of a wall
+    #[default]
+    NoCornerCutting,
+    /// If either straight way around the corner is open, paths still can't squeeze between two diagonal walls
+    CutCorners,
+}
+
+/// Which tiles can be walked on, and walls along tile edges like fences that block a step but not the tiles
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WalkGrid {
+    size: [u32; 2],
+    blocked: Vec<bool>,
+    walls: Vec<u8>,
+}
+
+impl WalkGrid {
+    /// A grid `size` tiles across with every tile open, a size too big to count in memory gives a grid with no tiles
+    pub fn new(size: [u32; 2]) -> Self {
+        let (size, tiles) = match (size[0] as usize).checked_mul(size[1] as usize) {
+            Some(tiles) => (size, tiles),
+            None => ([0, 0], 0)
+        };
+        Self {
+            size,
+            blocked: vec![false; tiles],
+            walls: vec![0; tiles],
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn set_blocked(&mut self, x: u32, y: u32, blocked: bool) {
+        if let Some(index) = self.index(x as i32, y as i32) {
+            self.blocked[index] = blocked;
+        }
+    }
+
+    /// True if a tile is on the grid and open
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| !self.blocked[index])
+    }
+
+    /// Put a wall between two tiles that share an edge, tiles that don't are ignored
+    pub fn block_between(&mut self, a: [u32; 2], b: [u32; 2]) {
+        let (a_wall, b_wall) = match [b[0] as i64 - a[0] as i64, b[1] as i64 - a[1] as i64] {
+            [0, -1] => (WALL_NORTH, WALL_SOUTH),
+            [1, 0] => (WALL_EAST, WALL_WEST),
+            [0, 1] => (WALL_SOUTH, WALL_NORTH),
+            [-1, 0] => (WALL_WEST, WALL_EAST),
+            _ => return
+        };
+
+        if let (Some(a), Some(b)) = (self.index(a[0] as i32, a[1] as i32), self.index(b[0] as i32, b[1] as i32)) {
+            self.walls[a] |= a_wall;
+            self.walls[b] |= b_wall;
+        }
+    }
+
+    /// True if a single step from a tile is allowed, `step` is -1, 0 or 1 on each axis
+    pub fn can_step(&self, from: [u32; 2], step: [i32; 2], diagonals: Diagonals) -> bool {
+        let from: [i32; 2] = [from[0] as i32, from[1] as i32];
+        let to: [i32; 2] = [from[0] + step[0], from[1] + step[1]];
+
+        match step {
+            [0, 0] => false,
+            [_, 0] | [0, _] => self.can_step_straight(from, step),
+            _ => {
+                // Going around the corner horizontally first, or vertically first
+                let horizontal: bool = self.can_step_straight(from, [step[0], 0])
+                    && self.can_step_straight([to[0], from[1]], [0, step[1]]);
+                let vertical: bool = self.can_step_straight(from, [0, step[1]])
+                    && self.can_step_straight([from[0], to[1]], [step[0], 0]);
+
+                match diagonals {
+                    Diagonals::Never => false,
+                    Diagonals::NoCornerCutting => horizontal && vertical,
+                    Diagonals::CutCorners => horizontal || vertical,
+                }
+            }
+        }
+    }
+
+    fn can_step_straight(&self, from: [i32; 2], step: [i32; 2]) -> bool {
+        let wall: u8 = match step {
+            [0, -1] => WALL_NORTH,
+            [1, 0] => WALL_EAST,
+            [0, 1] => WALL_SOUTH,
+            [-1, 0] => WALL_WEST,
+            _ => return false
+        };
+
+        match self.index(from[0], from[1]) {
+            Some(index) => self.walls[index] & wall == 0 && self.is_walkable(from[0] + step[0], from[1] + step[1]),
+            None => false
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.size[0] as i32 || y >= self.size[1] as i32 {
+            return None;
+        }
+        Some(y as usize * self.size[0] as usize + x as usize)
+    }
+}
+
+/// Find the shortest path between two tiles, the path leaves out `start` and ends on `goal`
+/// None if the goal can't be reached, an empty path if already there
+pub fn find_path(grid: &WalkGrid, start: [u32; 2], goal: [u32; 2], diagonals: Diagonals) -> Option<Vec<[u32; 2]>> {
+    let (path, reached) = search(grid, start, goal, diagonals);
+    reached.then_some(path)
+}
+
+/// Find a path to a tile, or to the tile nearest it that can be reached when it can't be, like clicking on a wall
+pub fn find_path_towards(grid: &WalkGrid, start: [u32; 2], goal: [u32; 2], diagonals: Diagonals) -> Vec<[u32; 2]> {
+    search(grid, start, goal, diagonals).0
+}
+
+// A* from start to goal, returns the path to the goal and true, or the path to the closest tile found and false
+fn search(grid: &WalkGrid, start: [u32; 2], goal: [u32; 2], diagonals: Diagonals) -> (Vec<[u32; 2]>, bool) {
+    let start_index = match grid.index(start[0] as i32, start[1] as i32) {
+        Some(index) => index,
+        None => return (Vec::new(), false)
+    };
+
+    let width: usize = grid.size[0] as usize;
+    let tile = |index: usize| [(index % width) as u32, (index / width) as u32];
+
+    let mut cost: Vec<u32> = vec![u32::MAX; grid.blocked.len()];
+    let mut came_from: Vec<usize> = vec![usize::MAX; grid.blocked.len()];
+    // Ordered by estimated total cost, then by distance left so paths run straight at the goal
+    let mut open: BinaryHeap<Reverse<(u32, u32, usize)>> = BinaryHeap::new();
+
+    cost[start_index] = 0;
+    open.push(Reverse((heuristic(start, goal, diagonals), heuristic(start, goal, diagonals), start_index)));
+    let mut closest: (u32, u32, usize) = (heuristic(start, goal, diagonals), 0, start_index);
+    let mut reached: bool = false;
+
+    while let Some(Reverse((estimate, left, index))) = open.pop() {
+        let current: [u32; 2] = tile(index);
+        if current == goal {
+            closest = (0, cost[index], index);
+            reached = true;
+            break;
+        }
+        // Skip stale entries for tiles already reached more cheaply
+        if cost[index] + left < estimate {
+            continue;
+        }
+        if (left, cost[index]) < (closest.0, closest.1) {
+            closest = (left, cost[index], index);
+        }
+
+        for step in STEPS {
+            if !grid.can_step(current, step, diagonals) {
+                continue;
+            }
+            let next: [u32; 2] = [(current[0] as i32 + step[0]) as u32, (current[1] as i32 + step[1]) as u32];
+            let next_index: usize = next[1] as usize * width + next[0] as usize;
+            let next_cost: u32 = cost[index] + if step[0] != 0 && step[1] != 0 { DIAGONAL_COST } else { STRAIGHT_COST };
+
+            if next_cost < cost[next_index] {
+                cost[next_index] = next_cost;
+                came_from[next_index] = index;
+                let left: u32 = heuristic(next, goal, diagonals);
+                open.push(Reverse((next_cost + left, left, next_index)));
+            }
+        }
+    }
+
+    let mut path: Vec<[u32; 2]> = Vec::new();
+    let mut index: usize = closest.2;
+    while index != start_index {
+        path.push(tile(index));
+        index = came_from[index];
+    }
+    path.reverse();
+    (path, reached)
+}
+
+// Cheapest possible cost between two tiles with nothing in the way
+fn heuristic(from: [u32; 2], to: [u32; 2], diagonals: Diagonals) -> u32 {
+    let dx: u32 = from[0].abs_diff(to[0]);
+    let dy: u32 = from[1].abs_diff(to[1]);
+    match diagonals {
+        Diagonals::Never => (dx + dy) * STRAIGHT_COST,
+        _ => dx.min(dy) * DIAGONAL_COST + dx.abs_diff(dy) * STRAIGHT_COST,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a grid from rows of text, '#' is blocked
+    fn parse(rows: &[&str]) -> WalkGrid {
+        let mut grid = WalkGrid::new([rows[0].len() as u32, rows.len() as u32]);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                grid.set_blocked(x as u32, y as u32, cell == '#');
+            }
+        }
+        grid
+    }
+
+    fn cost(path: &[[u32; 2]], start: [u32; 2]) -> u32 {
+        let mut previous = start;
+        path.iter().map(|tile| {
+            let diagonal = tile[0] != previous[0] && tile[1] != previous[1];
+            previous = *tile;
+            if diagonal { DIAGONAL_COST } else { STRAIGHT_COST }
+        }).sum()
+    }
+
+    #[test]
+    fn straight_line() {
+        let grid = parse(&["....."]);
+        assert_eq!(find_path(&grid, [0, 0], [4, 0], Diagonals::NoCornerCutting), Some(vec![[1, 0], [2, 0], [3, 0], [4, 0]]));
+    }
+
+    #[test]
+    fn already_there() {
+        let grid = parse(&["..."]);
+        assert_eq!(find_path(&grid, [1, 0], [1, 0], Diagonals::NoCornerCutting), Some(Vec::new()));
+    }
+
+    #[test]
+    fn diagonal_shortcut() {
+        let grid = parse(&[
+            "....",
+            "....",
+            "....",
+            "....",
+        ]);
+        let path = find_path(&grid, [0, 0], [3, 3], Diagonals::NoCornerCutting).unwrap();
+        assert_eq!(path, vec![[1, 1], [2, 2], [3, 3]]);
+
+        let path = find_path(&grid, [0, 0], [3, 3], Diagonals::Never).unwrap();
+        assert_eq!(path.len(), 6);
+        assert!(path.windows(2).all(|pair| pair[0][0] == pair[1][0] || pair[0][1] == pair[1][1]));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let grid = parse(&[
+            "..#..",
+            "..#..",
+            "..#..",
+            ".....",
+        ]);
+        let path = find_path(&grid, [0, 0], [4, 0], Diagonals::NoCornerCutting).unwrap();
+        assert_eq!(path.last(), Some(&[4, 0]));
+        assert!(path.iter().all(|tile| grid.is_walkable(tile[0] as i32, tile[1] as i32)));
+        // Down and under the wall, a diagonal at each end but none past the wall's bottom corners
+        assert_eq!(cost(&path, [0, 0]), 2 * DIAGONAL_COST + 6 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn corners_are_not_cut() {
+        let grid = parse(&[
+            ".#",
+            "..",
+        ]);
+        assert!(!grid.can_step([0, 0], [1, 1], Diagonals::NoCornerCutting));
+        assert!(grid.can_step([0, 0], [1, 1], Diagonals::CutCorners));
+        assert_eq!(find_path(&grid, [0, 0], [1, 1], Diagonals::NoCornerCutting), Some(vec![[0, 1], [1, 1]]));
+        assert_eq!(find_path(&grid, [0, 0], [1, 1], Diagonals::CutCorners), Some(vec![[1, 1]]));
+
+        // Two walls meeting at a corner can't be squeezed between either way
+        let grid = parse(&[
+            ".#",
+            "#.",
+        ]);
+        assert!(!grid.can_step([0, 0], [1, 1], Diagonals::CutCorners));
+        assert_eq!(find_path(&grid, [0, 0], [1, 1], Diagonals::CutCorners), None);
+    }
+
+    #[test]
+    fn walls_block_edges_but_not_tiles() {
+        let mut grid = parse(&[
+            "...",
+            "...",
+        ]);
+        // A fence between the left two columns, along the whole height
+        grid.block_between([0, 0], [1, 0]);
+        grid.block_between([1, 1], [0, 1]);
+
+        assert!(!grid.can_step([0, 0], [1, 0], Diagonals::NoCornerCutting));
+        assert!(!grid.can_step([1, 0], [-1, 0], Diagonals::NoCornerCutting));
+        assert!(!grid.can_step([0, 0], [1, 1], Diagonals::CutCorners));
+        assert_eq!(find_path(&grid, [0, 0], [2, 0], Diagonals::NoCornerCutting), None);
+
+        // The tiles either side are still walkable from elsewhere
+        assert_eq!(find_path(&grid, [2, 1], [1, 0], Diagonals::NoCornerCutting), Some(vec![[1, 0]]));
+    }
+
+    #[test]
+    fn unreachable_goal_walks_to_the_nearest_tile() {
+        let grid = parse(&[
+            "....#..",
+            "....#..",
+            "....#..",
+        ]);
+        assert_eq!(find_path(&grid, [0, 1], [6, 1], Diagonals::NoCornerCutting), None);
+        assert_eq!(find_path_towards(&grid, [0, 1], [6, 1], Diagonals::NoCornerCutting), vec![[1, 1], [2, 1], [3, 1]]);
+
+        // Clicking on a blocked tile walks up to it
+        assert_eq!(find_path_towards(&grid, [0, 0], [4, 0], Diagonals::NoCornerCutting), vec![[1, 0], [2, 0], [3, 0]]);
+    }
+
+    #[test]
+    fn off_the_grid() {
+        let grid = parse(&["..."]);
+        assert_eq!(find_path(&grid, [0, 0], [5, 0], Diagonals::NoCornerCutting), None);
+        assert_eq!(find_path(&grid, [5, 0], [0, 0], Diagonals::NoCornerCutting), None);
+        assert_eq!(find_path_towards(&grid, [0, 0], [9, 0], Diagonals::NoCornerCutting), vec![[1, 0], [2, 0]]);
+    }
+}
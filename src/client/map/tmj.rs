@@ -0,0 +1,275 @@
+// This file contains the reader for maps and tilesets Tiled saves as JSON (.tmj and .tsj)
+use std::path::Path;
+use anyhow::Error;
+use serde_json::Value;
+
+use super::map::{self, MapLayer, MapObject, ObjectLayer, ObjectShape, Properties, TileLayer, TileMap, Tileset};
+
+pub(super) fn parse_map(json: &str, dir: &Path) -> Result<TileMap, Error> {
+    let root: Value = serde_json::from_str(json)?;
+
+    let orientation = root["orientation"].as_str().unwrap_or("orthogonal");
+    if orientation != "orthogonal" {
+        return Err(anyhow::anyhow!("Only orthogonal maps are supported, not {}", orientation));
+    }
+    if root["infinite"].as_bool() == Some(true) {
+        return Err(anyhow::anyhow!("Infinite maps are not supported"));
+    }
+
+    let size: [u32; 2] = [uint(&root, "width")?, uint(&root, "height")?];
+    let tile_size: [u32; 2] = [uint(&root, "tilewidth")?, uint(&root, "tileheight")?];
+    map::check_size(size)?;
+
+    let mut tilesets: Vec<Tileset> = Vec::new();
+    for tileset in root["tilesets"].as_array().into_iter().flatten() {
+        tilesets.push(parse_tileset_ref(tileset, dir)?);
+    }
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+    let mut layers: Vec<MapLayer> = Vec::new();
+    parse_layers(&root["layers"], [0.0, 0.0], &mut layers)?;
+
+    Ok(TileMap {
+        size,
+        tile_size,
+        tilesets,
+        collision: map::build_collision(size, tile_size, &layers),
+        walk_grid: map::build_walk_grid(size, tile_size, &layers),
+        layers,
+        properties: parse_properties(&root["properties"]),
+    })
+}
+
+// A tileset in the map, either embedded or a reference to a .tsj or .tsx file
+fn parse_tileset_ref(value: &Value, dir: &Path) -> Result<Tileset, Error> {
+    let first_gid: u32 = uint(value, "firstgid")?;
+
+    match value["source"].as_str() {
+        Some(source) => {
+            let path = dir.join(source);
+            let text = std::fs::read_to_string(&path)
+                .map_err(|err| anyhow::anyhow!("Failed to read tileset '{}': {}", path.display(), err))?;
+            let tileset_dir: &Path = path.parent().unwrap_or(Path::new(""));
+
+            if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tsx")) {
+                super::tmx::parse_tileset(&text, first_gid, tileset_dir)
+            } else {
+                parse_tileset_file(&text, first_gid, tileset_dir)
+            }
+        }
+        None => parse_tileset(value, first_gid, dir)
+    }
+}
+
+/// Read a .tsj tileset file, `first_gid` comes from the map that uses it
+pub(super) fn parse_tileset_file(json: &str, first_gid: u32, dir: &Path) -> Result<Tileset, Error> {
+    parse_tileset(&serde_json::from_str(json)?, first_gid, dir)
+}
+
+fn parse_tileset(value: &Value, first_gid: u32, dir: &Path) -> Result<Tileset, Error> {
+    let image = value["image"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("Tileset '{}' has no image, image collection tilesets are not supported", value["name"].as_str().unwrap_or("")))?;
+
+    Ok(Tileset {
+        first_gid,
+        name: String::from(value["name"].as_str().unwrap_or("")),
+        tile_size: [uint(value, "tilewidth")?, uint(value, "tileheight")?],
+        columns: uint(value, "columns")?,
+        tile_count: uint(value, "tilecount")?,
+        margin: value["margin"].as_u64().unwrap_or(0) as u32,
+        spacing: value["spacing"].as_u64().unwrap_or(0) as u32,
+        image: dir.join(image),
+        image_size: [uint(value, "imagewidth")?, uint(value, "imageheight")?],
+    })
+}
+
+// Read a list of layers, group layers are flattened into it with their offsets added on
+fn parse_layers(value: &Value, offset: [f32; 2], layers: &mut Vec<MapLayer>) -> Result<(), Error> {
+    for layer in value.as_array().into_iter().flatten() {
+        let name = String::from(layer["name"].as_str().unwrap_or(""));
+        let layer_offset: [f32; 2] = [
+            offset[0] + layer["offsetx"].as_f64().unwrap_or(0.0) as f32,
+            offset[1] + layer["offsety"].as_f64().unwrap_or(0.0) as f32,
+        ];
+        let visible: bool = layer["visible"].as_bool().unwrap_or(true);
+        let properties: Properties = parse_properties(&layer["properties"]);
+
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                if layer["chunks"].is_array() {
+                    return Err(anyhow::anyhow!("Layer '{}' is chunked, infinite maps are not supported", name));
+                }
+
+                let tiles: Vec<u32> = match &layer["data"] {
+                    Value::Array(data) => data.iter().map(|id| id.as_u64().unwrap_or(0) as u32).collect(),
+                    Value::String(data) => map::decode_tiles(data, layer["encoding"].as_str(), layer["compression"].as_str())?,
+                    _ => return Err(anyhow::anyhow!("Layer '{}' has no tile data", name))
+                };
+
+                layers.push(MapLayer::Tiles(TileLayer {
+                    name,
+                    size: [uint(layer, "width")?, uint(layer, "height")?],
+                    tiles,
+                    offset: layer_offset,
+                    opacity: layer["opacity"].as_f64().unwrap_or(1.0) as f32,
+                    visible,
+                    properties,
+                }));
+            }
+            Some("objectgroup") => {
+                let objects: Vec<MapObject> = layer["objects"].as_array().into_iter().flatten()
+                    .map(|object| parse_object(object, layer_offset))
+                    .collect();
+                layers.push(MapLayer::Objects(ObjectLayer { name, objects, visible, properties }));
+            }
+            Some("group") => parse_layers(&layer["layers"], layer_offset, layers)?,
+            // Image layers are left to the background layers
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_object(value: &Value, offset: [f32; 2]) -> MapObject {
+    let number = |key: &str| value[key].as_f64().unwrap_or(0.0) as f32;
+    let points = |key: &str| -> Vec<[f32; 2]> {
+        value[key].as_array().into_iter().flatten()
+            .map(|point| [point["x"].as_f64().unwrap_or(0.0) as f32, point["y"].as_f64().unwrap_or(0.0) as f32])
+            .collect()
+    };
+
+    let shape: ObjectShape = if value["ellipse"].as_bool() == Some(true) {
+        ObjectShape::Ellipse
+    } else if value["point"].as_bool() == Some(true) {
+        ObjectShape::Point
+    } else if value["polygon"].is_array() {
+        ObjectShape::Polygon(points("polygon"))
+    } else if value["polyline"].is_array() {
+        ObjectShape::Polyline(points("polyline"))
+    } else {
+        ObjectShape::Rectangle
+    };
+
+    MapObject {
+        id: value["id"].as_u64().unwrap_or(0) as u32,
+        name: String::from(value["name"].as_str().unwrap_or("")),
+        // Tiled 1.9 renamed type to class
+        class: String::from(value["class"].as_str().or(value["type"].as_str()).unwrap_or("")),
+        position: [offset[0] + number("x"), offset[1] + number("y")],
+        size: [number("width"), number("height")],
+        rotation: number("rotation"),
+        gid: value["gid"].as_u64().map(|gid| gid as u32),
+        shape,
+        visible: value["visible"].as_bool().unwrap_or(true),
+        properties: parse_properties(&value["properties"]),
+    }
+}
+
+// Properties are a list of name, type and value
+fn parse_properties(value: &Value) -> Properties {
+    value.as_array().into_iter().flatten()
+        .filter_map(|property| {
+            let name = property["name"].as_str()?;
+            let value = match &property["value"] {
+                Value::String(text) => text.clone(),
+                Value::Null => return None,
+                other => other.to_string()
+            };
+            Some((String::from(name), value))
+        })
+        .collect()
+}
+
+fn uint(value: &Value, key: &str) -> Result<u32, Error> {
+    value[key].as_u64()
+        .map(|number| number as u32)
+        .ok_or_else(|| anyhow::anyhow!("Missing '{}'", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 map of 16 pixel tiles around the given layers
+    fn map_json(extra: &str, layers: &str) -> String {
+        format!(r#"{{
+            "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, {}
+            "tilesets": [{{
+                "firstgid": 1, "name": "ground", "image": "ground.png", "imagewidth": 64, "imageheight": 32,
+                "tilewidth": 16, "tileheight": 16, "columns": 4, "tilecount": 8, "spacing": 1
+            }}],
+            "properties": [{{ "name": "movement", "type": "string", "value": "platformer" }}],
+            "layers": [{}]
+        }}"#, extra, layers)
+    }
+
+    #[test]
+    fn reads_layers_tilesets_and_properties() {
+        let layers = r#"
+            { "type": "tilelayer", "name": "ground", "width": 3, "height": 2, "data": [1, 0, 2, 0, 2147483651, 0], "opacity": 0.5 },
+            { "type": "tilelayer", "name": "collision", "width": 3, "height": 2, "data": [0, 0, 0, 1, 1, 1] },
+            { "type": "imagelayer", "name": "sky" }"#;
+        let map = parse_map(&map_json("", layers), Path::new("maps")).unwrap();
+
+        assert_eq!(map.size, [3, 2]);
+        assert_eq!(map.tile_size, [16, 16]);
+        assert_eq!(map.properties.get("movement").map(String::as_str), Some("platformer"));
+        assert_eq!(map.tilesets.len(), 1);
+        assert_eq!(map.tilesets[0].image, Path::new("maps").join("ground.png"));
+        assert_eq!(map.tilesets[0].spacing, 1);
+
+        assert_eq!(map.layers.len(), 2);
+        let MapLayer::Tiles(ground) = &map.layers[0] else { panic!("expected a tile layer") };
+        assert_eq!(ground.opacity, 0.5);
+        assert_eq!(ground.tile(1, 1), 0x8000_0003);
+        assert!(map.layers[1].is_collision());
+        assert!(map.collision.is_solid(0, 1));
+        assert!(!map.collision.is_solid(0, 0));
+        assert!(!map.walk_grid.is_walkable(2, 1));
+    }
+
+    #[test]
+    fn reads_objects_with_group_offsets() {
+        let layers = r#"
+            { "type": "group", "name": "things", "offsetx": 10, "offsety": 20, "layers": [
+                { "type": "objectgroup", "name": "spawns", "offsety": 5, "objects": [
+                    { "id": 1, "name": "player", "type": "spawn", "x": 1, "y": 2, "width": 8, "height": 4, "rotation": 45,
+                      "properties": [{ "name": "solid", "type": "bool", "value": true }] },
+                    { "id": 2, "name": "door", "class": "door", "x": 0, "y": 0, "ellipse": true },
+                    { "id": 3, "x": 0, "y": 0, "point": true },
+                    { "id": 4, "x": 0, "y": 0, "polygon": [{ "x": 0, "y": 0 }, { "x": 4, "y": 0 }, { "x": 0, "y": 4 }] },
+                    { "id": 5, "x": 0, "y": 0, "polyline": [{ "x": 0, "y": 0 }, { "x": 4, "y": 0 }] },
+                    { "id": 6, "x": 3, "y": 4, "gid": 2, "visible": false }
+                ]}
+            ]}"#;
+        let map = parse_map(&map_json("", layers), Path::new("")).unwrap();
+        let objects: Vec<&MapObject> = map.objects().collect();
+
+        assert_eq!(objects.len(), 6);
+        assert_eq!(objects[0].name, "player");
+        assert_eq!(objects[0].class, "spawn");
+        assert_eq!(objects[0].position, [11.0, 27.0]);
+        assert_eq!(objects[0].size, [8.0, 4.0]);
+        assert_eq!(objects[0].rotation, 45.0);
+        assert_eq!(objects[0].properties.get("solid").map(String::as_str), Some("true"));
+        assert_eq!(objects[1].class, "door");
+        assert_eq!(objects[1].shape, ObjectShape::Ellipse);
+        assert_eq!(objects[2].shape, ObjectShape::Point);
+        assert_eq!(objects[3].shape, ObjectShape::Polygon(vec![[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]]));
+        assert_eq!(objects[4].shape, ObjectShape::Polyline(vec![[0.0, 0.0], [4.0, 0.0]]));
+        assert_eq!(objects[5].gid, Some(2));
+        assert!(!objects[5].visible);
+    }
+
+    #[test]
+    fn refuses_maps_it_cant_show() {
+        let error = |json: String| parse_map(&json, Path::new("")).unwrap_err().to_string();
+
+        assert!(error(map_json(r#""orientation": "isometric","#, "")).contains("orthogonal"));
+        assert!(error(map_json(r#""infinite": true,"#, "")).contains("Infinite"));
+        assert!(error(map_json("", r#"{ "type": "tilelayer", "name": "big", "width": 3, "height": 2, "chunks": [] }"#)).contains("chunked"));
+        assert!(error(map_json("", r#"{ "type": "tilelayer", "name": "empty", "width": 3, "height": 2 }"#)).contains("no tile data"));
+        assert!(error(String::from(r#"{ "width": 100000, "height": 100000, "tilewidth": 16, "tileheight": 16 }"#)).contains("at most"));
+        assert!(error(String::from(r#"{ "height": 2, "tilewidth": 16, "tileheight": 16 }"#)).contains("'width'"));
+    }
+}
@@ -0,0 +1,300 @@
+// This file contains the reader for maps and tilesets Tiled saves as XML (.tmx and .tsx)
+use std::path::Path;
+use anyhow::Error;
+use roxmltree::{Document, Node};
+
+use super::map::{self, MapLayer, MapObject, ObjectLayer, ObjectShape, Properties, TileLayer, TileMap, Tileset};
+
+pub(super) fn parse_map(xml: &str, dir: &Path) -> Result<TileMap, Error> {
+    let document: Document = Document::parse(xml)?;
+    let root: Node = document.root_element();
+    if root.tag_name().name() != "map" {
+        return Err(anyhow::anyhow!("Expected a <map> element, found <{}>", root.tag_name().name()));
+    }
+
+    let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+    if orientation != "orthogonal" {
+        return Err(anyhow::anyhow!("Only orthogonal maps are supported, not {}", orientation));
+    }
+    if root.attribute("infinite") == Some("1") {
+        return Err(anyhow::anyhow!("Infinite maps are not supported"));
+    }
+
+    let size: [u32; 2] = [uint(root, "width")?, uint(root, "height")?];
+    let tile_size: [u32; 2] = [uint(root, "tilewidth")?, uint(root, "tileheight")?];
+    map::check_size(size)?;
+
+    let mut tilesets: Vec<Tileset> = Vec::new();
+    for tileset in root.children().filter(|node| node.has_tag_name("tileset")) {
+        tilesets.push(parse_tileset_ref(tileset, dir)?);
+    }
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+    let mut layers: Vec<MapLayer> = Vec::new();
+    parse_layers(root, [0.0, 0.0], &mut layers)?;
+
+    Ok(TileMap {
+        size,
+        tile_size,
+        tilesets,
+        collision: map::build_collision(size, tile_size, &layers),
+        walk_grid: map::build_walk_grid(size, tile_size, &layers),
+        layers,
+        properties: parse_properties(root),
+    })
+}
+
+/// Read a .tsx tileset file, `first_gid` comes from the map that uses it
+pub(super) fn parse_tileset(xml: &str, first_gid: u32, dir: &Path) -> Result<Tileset, Error> {
+    let document: Document = Document::parse(xml)?;
+    tileset_from_node(document.root_element(), first_gid, dir)
+}
+
+// A tileset in the map, either embedded or a reference to a .tsx or .tsj file
+fn parse_tileset_ref(node: Node, dir: &Path) -> Result<Tileset, Error> {
+    let first_gid: u32 = uint(node, "firstgid")?;
+
+    match node.attribute("source") {
+        Some(source) => {
+            let path = dir.join(source);
+            let text = std::fs::read_to_string(&path)
+                .map_err(|err| anyhow::anyhow!("Failed to read tileset '{}': {}", path.display(), err))?;
+            let tileset_dir: &Path = path.parent().unwrap_or(Path::new(""));
+
+            if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tsx")) {
+                parse_tileset(&text, first_gid, tileset_dir)
+            } else {
+                super::tmj::parse_tileset_file(&text, first_gid, tileset_dir)
+            }
+        }
+        None => tileset_from_node(node, first_gid, dir)
+    }
+}
+
+fn tileset_from_node(node: Node, first_gid: u32, dir: &Path) -> Result<Tileset, Error> {
+    let name: &str = node.attribute("name").unwrap_or("");
+    let image: Node = node.children().find(|child| child.has_tag_name("image"))
+        .ok_or_else(|| anyhow::anyhow!("Tileset '{}' has no image, image collection tilesets are not supported", name))?;
+    let source = image.attribute("source")
+        .ok_or_else(|| anyhow::anyhow!("Tileset '{}' image has no source", name))?;
+
+    Ok(Tileset {
+        first_gid,
+        name: String::from(name),
+        tile_size: [uint(node, "tilewidth")?, uint(node, "tileheight")?],
+        columns: uint(node, "columns")?,
+        tile_count: uint(node, "tilecount")?,
+        margin: node.attribute("margin").and_then(|margin| margin.parse().ok()).unwrap_or(0),
+        spacing: node.attribute("spacing").and_then(|spacing| spacing.parse().ok()).unwrap_or(0),
+        image: dir.join(source),
+        image_size: [uint(image, "width")?, uint(image, "height")?],
+    })
+}
+
+// Read the layers inside a map or group, group layers are flattened with their offsets added on
+fn parse_layers(parent: Node, offset: [f32; 2], layers: &mut Vec<MapLayer>) -> Result<(), Error> {
+    for layer in parent.children().filter(|node| node.is_element()) {
+        let name = String::from(layer.attribute("name").unwrap_or(""));
+        let layer_offset: [f32; 2] = [offset[0] + float(layer, "offsetx"), offset[1] + float(layer, "offsety")];
+        let visible: bool = layer.attribute("visible") != Some("0");
+        let properties: Properties = parse_properties(layer);
+
+        match layer.tag_name().name() {
+            "layer" => {
+                let data: Node = layer.children().find(|child| child.has_tag_name("data"))
+                    .ok_or_else(|| anyhow::anyhow!("Layer '{}' has no tile data", name))?;
+                if data.children().any(|child| child.has_tag_name("chunk")) {
+                    return Err(anyhow::anyhow!("Layer '{}' is chunked, infinite maps are not supported", name));
+                }
+
+                // Without an encoding every tile is its own element
+                let tiles: Vec<u32> = match data.attribute("encoding") {
+                    None => data.children()
+                        .filter(|child| child.has_tag_name("tile"))
+                        .map(|tile| tile.attribute("gid").and_then(|gid| gid.parse().ok()).unwrap_or(0))
+                        .collect(),
+                    encoding => map::decode_tiles(data.text().unwrap_or(""), encoding, data.attribute("compression"))?
+                };
+
+                layers.push(MapLayer::Tiles(TileLayer {
+                    name,
+                    size: [uint(layer, "width")?, uint(layer, "height")?],
+                    tiles,
+                    offset: layer_offset,
+                    opacity: layer.attribute("opacity").and_then(|opacity| opacity.parse().ok()).unwrap_or(1.0),
+                    visible,
+                    properties,
+                }));
+            }
+            "objectgroup" => {
+                let objects: Vec<MapObject> = layer.children()
+                    .filter(|child| child.has_tag_name("object"))
+                    .map(|object| parse_object(object, layer_offset))
+                    .collect();
+                layers.push(MapLayer::Objects(ObjectLayer { name, objects, visible, properties }));
+            }
+            "group" => parse_layers(layer, layer_offset, layers)?,
+            // Image layers are left to the background layers
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_object(node: Node, offset: [f32; 2]) -> MapObject {
+    let child = |tag: &str| node.children().find(|child| child.has_tag_name(tag));
+    let points = |child: Node| -> Vec<[f32; 2]> {
+        child.attribute("points").unwrap_or("").split_whitespace()
+            .filter_map(|pair| {
+                let (x, y) = pair.split_once(',')?;
+                Some([x.parse().ok()?, y.parse().ok()?])
+            })
+            .collect()
+    };
+
+    let shape: ObjectShape = if child("ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if child("point").is_some() {
+        ObjectShape::Point
+    } else if let Some(polygon) = child("polygon") {
+        ObjectShape::Polygon(points(polygon))
+    } else if let Some(polyline) = child("polyline") {
+        ObjectShape::Polyline(points(polyline))
+    } else {
+        ObjectShape::Rectangle
+    };
+
+    MapObject {
+        id: node.attribute("id").and_then(|id| id.parse().ok()).unwrap_or(0),
+        name: String::from(node.attribute("name").unwrap_or("")),
+        // Tiled 1.9 renamed type to class
+        class: String::from(node.attribute("class").or(node.attribute("type")).unwrap_or("")),
+        position: [offset[0] + float(node, "x"), offset[1] + float(node, "y")],
+        size: [float(node, "width"), float(node, "height")],
+        rotation: float(node, "rotation"),
+        gid: node.attribute("gid").and_then(|gid| gid.parse().ok()),
+        shape,
+        visible: node.attribute("visible") != Some("0"),
+        properties: parse_properties(node),
+    }
+}
+
+// Properties are <property name value> elements in a <properties> child, long strings are the element's text instead
+fn parse_properties(node: Node) -> Properties {
+    node.children()
+        .filter(|child| child.has_tag_name("properties"))
+        .flat_map(|properties| properties.children().filter(|child| child.has_tag_name("property")))
+        .filter_map(|property| {
+            let name = property.attribute("name")?;
+            let value = property.attribute("value").or(property.text()).unwrap_or("");
+            Some((String::from(name), String::from(value)))
+        })
+        .collect()
+}
+
+fn uint(node: Node, key: &str) -> Result<u32, Error> {
+    node.attribute(key)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Missing '{}' on <{}>", key, node.tag_name().name()))
+}
+
+fn float(node: Node, key: &str) -> f32 {
+    node.attribute(key).and_then(|value| value.parse().ok()).unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 map of 16 pixel tiles around the given layers
+    fn map_xml(extra: &str, layers: &str) -> String {
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+            <map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" {}>
+                <properties>
+                    <property name="movement" value="platformer"/>
+                    <property name="intro">Long text
+in the element</property>
+                </properties>
+                <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" tilecount="8" columns="4" margin="2">
+                    <image source="ground.png" width="64" height="32"/>
+                </tileset>
+                {}
+            </map>"#, extra, layers)
+    }
+
+    #[test]
+    fn reads_layers_tilesets_and_properties() {
+        let layers = r#"
+            <layer name="ground" width="3" height="2" opacity="0.5">
+                <data encoding="csv">1,0,2,
+0,1073741827,0</data>
+            </layer>
+            <layer name="walls" width="3" height="2">
+                <properties><property name="collision" type="bool" value="true"/></properties>
+                <data><tile gid="1"/><tile/><tile/><tile/><tile/><tile gid="1"/></data>
+            </layer>
+            <imagelayer name="sky"/>"#;
+        let map = parse_map(&map_xml("", layers), Path::new("maps")).unwrap();
+
+        assert_eq!(map.size, [3, 2]);
+        assert_eq!(map.properties.get("movement").map(String::as_str), Some("platformer"));
+        assert_eq!(map.properties.get("intro").map(String::as_str), Some("Long text\nin the element"));
+        assert_eq!(map.tilesets[0].image, Path::new("maps").join("ground.png"));
+        assert_eq!(map.tilesets[0].margin, 2);
+
+        assert_eq!(map.layers.len(), 2);
+        let MapLayer::Tiles(ground) = &map.layers[0] else { panic!("expected a tile layer") };
+        assert_eq!(ground.opacity, 0.5);
+        assert_eq!(ground.tile(1, 1), 0x4000_0003);
+        assert!(map.layers[1].is_collision());
+        assert!(map.collision.is_solid(0, 0));
+        assert!(map.collision.is_solid(2, 1));
+        assert!(!map.collision.is_solid(1, 0));
+    }
+
+    #[test]
+    fn reads_objects_with_group_offsets() {
+        let layers = r#"
+            <group name="things" offsetx="10" offsety="20">
+                <objectgroup name="spawns" offsety="5">
+                    <object id="1" name="player" type="spawn" x="1" y="2" width="8" height="4" rotation="45">
+                        <properties><property name="solid" type="bool" value="true"/></properties>
+                    </object>
+                    <object id="2" name="door" class="door" x="0" y="0"><ellipse/></object>
+                    <object id="3" x="0" y="0"><point/></object>
+                    <object id="4" x="0" y="0"><polygon points="0,0 4,0 0,4"/></object>
+                    <object id="5" x="0" y="0"><polyline points="0,0 4,0"/></object>
+                    <object id="6" x="3" y="4" gid="2" visible="0"/>
+                </objectgroup>
+            </group>"#;
+        let map = parse_map(&map_xml("", layers), Path::new("")).unwrap();
+        let objects: Vec<&MapObject> = map.objects().collect();
+
+        assert_eq!(objects.len(), 6);
+        assert_eq!(objects[0].name, "player");
+        assert_eq!(objects[0].class, "spawn");
+        assert_eq!(objects[0].position, [11.0, 27.0]);
+        assert_eq!(objects[0].size, [8.0, 4.0]);
+        assert_eq!(objects[0].rotation, 45.0);
+        assert_eq!(objects[0].properties.get("solid").map(String::as_str), Some("true"));
+        assert_eq!(objects[1].class, "door");
+        assert_eq!(objects[1].shape, ObjectShape::Ellipse);
+        assert_eq!(objects[2].shape, ObjectShape::Point);
+        assert_eq!(objects[3].shape, ObjectShape::Polygon(vec![[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]]));
+        assert_eq!(objects[4].shape, ObjectShape::Polyline(vec![[0.0, 0.0], [4.0, 0.0]]));
+        assert_eq!(objects[5].gid, Some(2));
+        assert!(!objects[5].visible);
+    }
+
+    #[test]
+    fn refuses_maps_it_cant_show() {
+        let error = |xml: String| parse_map(&xml, Path::new("")).unwrap_err().to_string();
+
+        assert!(error(map_xml(r#"infinite="1""#, "")).contains("Infinite"));
+        assert!(error(map_xml("", r#"<layer name="big" width="3" height="2"><data encoding="csv"><chunk/></data></layer>"#)).contains("chunked"));
+        assert!(error(map_xml("", r#"<layer name="empty" width="3" height="2"/>"#)).contains("no tile data"));
+        assert!(error(String::from(r#"<map orientation="hexagonal" width="3" height="2" tilewidth="16" tileheight="16"/>"#)).contains("orthogonal"));
+        assert!(error(String::from(r#"<map width="100000" height="100000" tilewidth="16" tileheight="16"/>"#)).contains("at most"));
+        assert!(error(String::from(r#"<tileset name="ground"/>"#)).contains("<map>"));
+    }
+}
@@ -1,3 +1,10 @@
+pub mod assets;
 pub mod config;
+pub mod input;
 pub mod grafx;
+pub mod ui;
+pub mod ecs;
+pub mod map;
+pub mod physics;
+pub mod collision;
 pub mod game;
\ No newline at end of file
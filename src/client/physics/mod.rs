@@ -0,0 +1,2 @@
+mod platformer;
+pub use self::platformer::{Platformer, PlatformerInput, PlatformerSettings};
\ No newline at end of file
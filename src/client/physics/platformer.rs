@@ -0,0 +1,433 @@
+// This file contains the side scrolling character controller, it moves a box through the map by hand instead of simulating forces
+use crate::client::map::CollisionGrid;
+
+/// How a platformer character moves, speeds are in pixels per second
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlatformerSettings {
+    /// Pixels per second squared
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+    pub run_speed: f32,
+    /// How quickly running speeds up and slows down, in pixels per second squared
+    pub ground_acceleration: f32,
+    pub air_acceleration: f32,
+    /// Upward speed at the start of a jump
+    pub jump_speed: f32,
+    /// Share of the upward speed kept when jump is let go early, lower makes taps shorter
+    pub jump_cut: f32,
+    /// Seconds after walking off a ledge that a jump still works
+    pub coyote_time: f32,
+    /// Seconds before landing that a jump press is remembered
+    pub jump_buffer: f32,
+    /// Highest ledge walked up onto without jumping, slopes add the distance moved
+    pub step_height: f32,
+    /// Seconds one way floors are ignored after dropping through one
+    pub drop_time: f32,
+}
+
+impl Default for PlatformerSettings {
+    fn default() -> Self {
+        Self {
+            gravity: 2000.0,
+            max_fall_speed: 900.0,
+            run_speed: 240.0,
+            ground_acceleration: 2400.0,
+            air_acceleration: 1200.0,
+            jump_speed: 720.0,
+            jump_cut: 0.4,
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+            step_height: 4.0,
+            drop_time: 0.25,
+        }
+    }
+}
+
+/// What the player is asking a platformer character to do this step
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PlatformerInput {
+    /// -1 runs left, 1 runs right
+    pub horizontal: f32,
+    /// Held to jump, letting go early makes a shorter jump
+    pub jump: bool,
+    /// Held to drop down through one way floors
+    pub drop: bool,
+}
+
+/// A character that runs and jumps over the map's collision, its position is the center of its box
+#[derive(Clone, Debug, PartialEq)]
+pub struct Platformer {
+    pub settings: PlatformerSettings,
+    pub half_size: [f32; 2],
+    pub velocity: [f32; 2],
+    grounded: bool,
+    // Seconds since last standing on something, for coyote time
+    since_grounded: f32,
+    // Seconds since jump was pressed, for the jump buffer
+    since_jump_pressed: f32,
+    jump_held: bool,
+    // Rising from a jump, letting go of jump cuts it short
+    jumping: bool,
+    // Seconds left of ignoring one way floors
+    dropping: f32,
+}
+
+impl Platformer {
+    pub fn new(size: [f32; 2]) -> Self {
+        Self {
+            settings: PlatformerSettings::default(),
+            half_size: [size[0] / 2.0, size[1] / 2.0],
+            velocity: [0.0, 0.0],
+            grounded: false,
+            since_grounded: f32::INFINITY,
+            since_jump_pressed: f32::INFINITY,
+            jump_held: false,
+            jumping: false,
+            dropping: 0.0,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: PlatformerSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// True if standing on something after the last step
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Advance by one physics step, moving `position` through the collision
+    pub fn step(&mut self, position: &mut [f32; 2], input: PlatformerInput, collision: &CollisionGrid, dt: f32) {
+        let settings: PlatformerSettings = self.settings;
+
+        // Jumps are started by the press, not the hold, so holding jump doesn't bunny hop
+        let pressed: bool = input.jump && !self.jump_held;
+        self.jump_held = input.jump;
+        self.since_jump_pressed = if pressed { 0.0 } else { self.since_jump_pressed + dt };
+        self.since_grounded = if self.grounded { 0.0 } else { self.since_grounded + dt };
+        self.dropping = if input.drop && self.grounded { settings.drop_time } else { (self.dropping - dt).max(0.0) };
+
+        // Run towards the input speed
+        let target: f32 = input.horizontal.clamp(-1.0, 1.0) * settings.run_speed;
+        let acceleration: f32 = if self.grounded { settings.ground_acceleration } else { settings.air_acceleration };
+        let change: f32 = acceleration * dt;
+        self.velocity[0] = if self.velocity[0] < target {
+            (self.velocity[0] + change).min(target)
+        } else {
+            (self.velocity[0] - change).max(target)
+        };
+
+        if self.since_jump_pressed <= settings.jump_buffer && self.since_grounded <= settings.coyote_time {
+            self.velocity[1] = -settings.jump_speed;
+            self.jumping = true;
+            self.grounded = false;
+            // Use up the press and the ground so one press is one jump
+            self.since_jump_pressed = f32::INFINITY;
+            self.since_grounded = f32::INFINITY;
+        }
+
+        if self.jumping && (self.velocity[1] >= 0.0 || !input.jump) {
+            if self.velocity[1] < 0.0 {
+                self.velocity[1] *= settings.jump_cut;
+            }
+            self.jumping = false;
+        }
+
+        self.velocity[1] = (self.velocity[1] + settings.gravity * dt).min(settings.max_fall_speed);
+
+        let was_grounded: bool = self.grounded;
+        let one_way: bool = self.dropping <= 0.0;
+
+        // Horizontally only solid areas block, slopes are climbed by the vertical pass
+        let dx: f32 = self.velocity[0] * dt;
+        let (min, max) = self.bounds(*position);
+        let moved_x: f32 = collision.move_box(min, max, [dx, 0.0])[0];
+        if moved_x != dx {
+            self.velocity[0] = 0.0;
+        }
+        position[0] += moved_x;
+
+        let dy: f32 = self.velocity[1] * dt;
+        let (min, max) = self.bounds(*position);
+        self.grounded = false;
+
+        if dy < 0.0 {
+            // Going up only solid areas block, one way floors and slopes are jumped through
+            let moved_y: f32 = collision.move_box(min, max, [0.0, dy])[1];
+            if moved_y != dy {
+                self.velocity[1] = 0.0;
+                self.jumping = false;
+            }
+            position[1] += moved_y;
+            return;
+        }
+
+        // Land on the highest floor the feet pass, walking characters can step up a little, e.g. onto a slope
+        let moved_y: f32 = collision.move_box(min, max, [0.0, dy])[1];
+        let climb: f32 = if was_grounded { settings.step_height + moved_x.abs() } else { 0.0 };
+        let mut floor: Option<f32> = collision.floor_below(min[0], max[0], max[1] - climb, max[1] + moved_y, one_way);
+
+        // Walking down a slope or off a small ledge, stick to the ground instead of falling off in small hops
+        if floor.is_none() && was_grounded {
+            floor = collision.floor_below(min[0], max[0], max[1], max[1] + settings.step_height + moved_x.abs(), one_way);
+        }
+
+        match floor {
+            Some(y) => {
+                position[1] = y - self.half_size[1];
+                self.velocity[1] = 0.0;
+                self.grounded = true;
+            }
+            None => position[1] += moved_y
+        }
+    }
+
+    /// React to being pushed out of something solid that isn't part of the map, e.g. a crate
+    /// Speed into it is lost, and being pushed up out of it means standing on it
+    pub fn push_out(&mut self, push: [f32; 2]) {
+        if self.velocity[0] * push[0] < 0.0 {
+            self.velocity[0] = 0.0;
+        }
+        if self.velocity[1] * push[1] < 0.0 {
+            self.velocity[1] = 0.0;
+            self.jumping = false;
+        }
+        if push[1] < 0.0 && -push[1] >= push[0].abs() && self.velocity[1] >= 0.0 {
+            self.grounded = true;
+        }
+    }
+
+    // Get the (min, max) corners of the box at a position
+    fn bounds(&self, position: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        (
+            [position[0] - self.half_size[0], position[1] - self.half_size[1]],
+            [position[0] + self.half_size[0], position[1] + self.half_size[1]],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A 20 by 10 map of 16 pixel tiles with a solid floor along the bottom row, its top at y 144
+    fn level() -> CollisionGrid {
+        let mut collision = CollisionGrid::new([20, 10], [16.0, 16.0]);
+        for x in 0..20 {
+            collision.set_solid(x, 9, true);
+        }
+        collision
+    }
+
+    /// A 16 by 32 character standing on the floor of `level` at x
+    fn standing(collision: &CollisionGrid, x: f32) -> (Platformer, [f32; 2]) {
+        let mut platformer = Platformer::new([16.0, 32.0]);
+        let mut position = [x, 128.0];
+        platformer.step(&mut position, PlatformerInput::default(), collision, DT);
+        assert!(platformer.is_grounded());
+        (platformer, position)
+    }
+
+    /// Run steps with the same input, returns the highest point reached
+    fn run(platformer: &mut Platformer, position: &mut [f32; 2], input: PlatformerInput, collision: &CollisionGrid, steps: usize) -> f32 {
+        let mut highest = position[1];
+        for _ in 0..steps {
+            platformer.step(position, input, collision, DT);
+            highest = highest.min(position[1]);
+        }
+        highest
+    }
+
+    fn jump() -> PlatformerInput {
+        PlatformerInput { jump: true, ..Default::default() }
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_floor() {
+        let collision = level();
+        let mut platformer = Platformer::new([16.0, 32.0]);
+        let mut position = [40.0, 20.0];
+
+        run(&mut platformer, &mut position, PlatformerInput::default(), &collision, 120);
+        assert!(platformer.is_grounded());
+        assert_eq!(position[1], 128.0);
+        assert_eq!(platformer.velocity[1], 0.0);
+    }
+
+    #[test]
+    fn gravity_is_capped() {
+        let collision = CollisionGrid::new([1, 1], [16.0, 16.0]);
+        let mut platformer = Platformer::new([16.0, 32.0]);
+        let mut position = [0.0, 0.0];
+
+        run(&mut platformer, &mut position, PlatformerInput::default(), &collision, 300);
+        assert_eq!(platformer.velocity[1], platformer.settings.max_fall_speed);
+    }
+
+    #[test]
+    fn runs_into_walls() {
+        let mut collision = level();
+        collision.set_solid(10, 8, true);
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+
+        let right = PlatformerInput { horizontal: 1.0, ..Default::default() };
+        run(&mut platformer, &mut position, right, &collision, 120);
+        // Flush against the wall's left side at x 160
+        assert_eq!(position[0], 152.0);
+        assert_eq!(platformer.velocity[0], 0.0);
+    }
+
+    #[test]
+    fn holding_jump_jumps_higher_than_tapping() {
+        let collision = level();
+
+        let (mut held, mut held_position) = standing(&collision, 40.0);
+        let held_top = run(&mut held, &mut held_position, jump(), &collision, 60);
+
+        let (mut tapped, mut tapped_position) = standing(&collision, 40.0);
+        run(&mut tapped, &mut tapped_position, jump(), &collision, 3);
+        let tapped_top = run(&mut tapped, &mut tapped_position, PlatformerInput::default(), &collision, 60);
+
+        // v^2 / 2g is about 130 pixels for the default settings
+        assert!(128.0 - held_top > 120.0, "held jump peaked at {}", held_top);
+        assert!(tapped_top > held_top + 50.0, "tapped {} vs held {}", tapped_top, held_top);
+    }
+
+    #[test]
+    fn holding_jump_only_jumps_once() {
+        let collision = level();
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+
+        run(&mut platformer, &mut position, jump(), &collision, 120);
+        assert!(platformer.is_grounded());
+        platformer.step(&mut position, jump(), &collision, DT);
+        assert!(platformer.is_grounded());
+    }
+
+    #[test]
+    fn coyote_time_allows_late_jumps() {
+        // A ledge ending at x 64, with nothing below it
+        let mut collision = CollisionGrid::new([20, 10], [16.0, 16.0]);
+        for x in 0..4 {
+            collision.set_solid(x, 9, true);
+        }
+        let right = PlatformerInput { horizontal: 1.0, ..Default::default() };
+
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+        platformer.velocity[0] = platformer.settings.run_speed;
+        // Run until the box is fully off the ledge
+        while position[0] - platformer.half_size[0] < 64.0 {
+            platformer.step(&mut position, right, &collision, DT);
+        }
+        assert!(!platformer.is_grounded());
+
+        platformer.step(&mut position, PlatformerInput { jump: true, ..right }, &collision, DT);
+        assert!(platformer.velocity[1] < 0.0, "jump after leaving the ledge was ignored");
+
+        // Too late once the coyote time has passed
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+        platformer.velocity[0] = platformer.settings.run_speed;
+        while position[0] - platformer.half_size[0] < 64.0 {
+            platformer.step(&mut position, right, &collision, DT);
+        }
+        run(&mut platformer, &mut position, right, &collision, 10);
+        platformer.step(&mut position, PlatformerInput { jump: true, ..right }, &collision, DT);
+        assert!(platformer.velocity[1] > 0.0);
+    }
+
+    #[test]
+    fn buffered_jump_fires_on_landing() {
+        let collision = level();
+        let mut platformer = Platformer::new([16.0, 32.0]);
+        let mut position = [40.0, 100.0];
+
+        // Press jump just before touching down
+        run(&mut platformer, &mut position, PlatformerInput::default(), &collision, 8);
+        assert!(!platformer.is_grounded());
+        run(&mut platformer, &mut position, jump(), &collision, 4);
+        assert!(platformer.velocity[1] < 0.0, "the early press was forgotten");
+    }
+
+    #[test]
+    fn one_way_floors_hold_from_above_and_can_be_dropped_through() {
+        let mut collision = level();
+        // A one way floor at y 80, two tiles above the ground
+        for x in 0..20 {
+            collision.set_one_way(x, 5, true);
+        }
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+
+        // Jump up through it and land on top
+        run(&mut platformer, &mut position, jump(), &collision, 90);
+        assert!(platformer.is_grounded());
+        assert_eq!(position[1], 64.0);
+
+        // Hold down to fall back through
+        let drop = PlatformerInput { drop: true, ..Default::default() };
+        run(&mut platformer, &mut position, drop, &collision, 2);
+        run(&mut platformer, &mut position, PlatformerInput::default(), &collision, 60);
+        assert_eq!(position[1], 128.0);
+    }
+
+    #[test]
+    fn walks_up_and_down_slopes() {
+        let mut collision = level();
+        // A 45 degree ramp from the floor at x 100 up to x 164, then back down to x 228
+        collision.add_slope([100.0, 144.0], [164.0, 80.0]);
+        collision.add_slope([164.0, 80.0], [228.0, 144.0]);
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+
+        let right = PlatformerInput { horizontal: 1.0, ..Default::default() };
+        let mut highest = position[1];
+        while position[0] < 260.0 {
+            platformer.step(&mut position, right, &collision, DT);
+            highest = highest.min(position[1]);
+            assert!(platformer.is_grounded(), "left the ground at x {}", position[0]);
+        }
+        // Reached the peak, within a step of standing on it by its center
+        assert!(highest <= 80.0 - 16.0 + 4.0, "peaked at {}", highest);
+        assert_eq!(position[1], 128.0);
+    }
+
+    #[test]
+    fn jumps_bump_heads_on_ceilings() {
+        let mut collision = level();
+        for x in 0..20 {
+            collision.set_solid(x, 5, true);
+        }
+        let (mut platformer, mut position) = standing(&collision, 40.0);
+
+        let highest = run(&mut platformer, &mut position, jump(), &collision, 90);
+        // The ceiling's underside is at y 96
+        assert_eq!(highest, 112.0);
+        assert!(platformer.is_grounded());
+    }
+
+    #[test]
+    fn stands_on_things_it_is_pushed_out_of() {
+        // Nothing in the map, a box below is pushed out of by hand the way resolve_contacts does
+        let collision = CollisionGrid::new([1, 1], [16.0, 16.0]);
+        let mut platformer = Platformer::new([16.0, 32.0]);
+        let mut position = [0.0, 0.0];
+
+        for _ in 0..120 {
+            platformer.step(&mut position, PlatformerInput::default(), &collision, DT);
+            let sunk: f32 = position[1] + 16.0 - 100.0;
+            if sunk > 0.0 {
+                position[1] -= sunk;
+                platformer.push_out([0.0, -sunk]);
+            }
+        }
+
+        assert!(platformer.is_grounded());
+        assert_eq!(platformer.velocity[1], 0.0);
+        assert!((position[1] - 84.0).abs() < 0.001);
+
+        // And can jump off it
+        platformer.step(&mut position, jump(), &collision, DT);
+        assert!(platformer.velocity[1] < 0.0);
+    }
+}
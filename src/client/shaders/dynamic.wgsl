@@ -3,48 +3,58 @@ struct VertexInput {
     @location(1) tex_coords: vec2<f32>,
 };
 
+// Per-sprite data from the sprite batch instance buffer
+struct InstanceInput {
+    @location(2) position: vec2<f32>,
+    @location(3) scale: vec2<f32>,
+    @location(4) rotation: f32,
+    @location(5) uv_rect: vec4<f32>,
+    @location(6) color: vec4<f32>,
+};
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>
 };
 
-struct SpriteUniform {
-    position: vec2<f32>,
-    scale: vec2<f32>,
-    rotation: f32,
-    // padding is handled automatically in wgsl
-}
+// Camera data, shared by every sprite
+struct ViewUniform {
+    view_proj: mat4x4<f32>,
+};
 
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
-@group(1) @binding(0)  // New binding group for transform
-var<uniform> sprite: SpriteUniform;
+@group(1) @binding(0)
+var<uniform> view: ViewUniform;
 
 @vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
+fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
     var output: VertexOutput;
     
     // Apply scale
-    let scaled = input.position.xy * sprite.scale;
+    let scaled = input.position.xy * instance.scale;
     
     // Apply rotation
-    let angle = sprite.rotation;
+    let angle = instance.rotation;
     let rotated = vec2<f32>(
         scaled.x * cos(angle) - scaled.y * sin(angle),
         scaled.x * sin(angle) + scaled.y * cos(angle)
     );
     
-    // Apply translation
-    let final_pos = rotated + sprite.position;
+    // Apply translation (in world space pixels)
+    let world_pos = rotated + instance.position;
     
-    output.position = vec4<f32>(final_pos, input.position.z, 1.0);
-    output.tex_coords = input.tex_coords;
+    output.position = view.view_proj * vec4<f32>(world_pos, input.position.z, 1.0);
+    // Map the quad's 0..1 tex coords onto the instance's area of the texture
+    output.tex_coords = instance.uv_rect.xy + input.tex_coords * instance.uv_rect.zw;
+    output.color = instance.color;
     return output;
 }
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, input.tex_coords);
-}
\ No newline at end of file
+    return textureSample(t_diffuse, s_diffuse, input.tex_coords) * input.color;
+}
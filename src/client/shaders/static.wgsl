@@ -8,11 +8,23 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>
 };
 
+// Which part of the texture a background layer shows, moved with the camera for parallax
+struct BackgroundUniform {
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    // Color multiplied in (rgb) and layer opacity (a), used to cross-fade day and night
+    tint: vec4<f32>,
+    repeat_x: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> layer: BackgroundUniform;
+
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
     output.position = vec4(input.position, 1.0);
-    output.tex_coords = input.tex_coords;
+    output.tex_coords = input.tex_coords * layer.uv_scale + layer.uv_offset;
     return output;
 }
 
@@ -23,5 +35,12 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, input.tex_coords);
-}
\ No newline at end of file
+    let color = textureSample(t_diffuse, s_diffuse, input.tex_coords);
+
+    // Layers that don't repeat leave whatever is beneath them showing past their edges
+    if (layer.repeat_x < 0.5 && (input.tex_coords.x < 0.0 || input.tex_coords.x > 1.0)) {
+        discard;
+    }
+
+    return color * layer.tint;
+}
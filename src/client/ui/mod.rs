@@ -0,0 +1,5 @@
+#[allow(clippy::module_inception)]
+mod ui;
+mod nine_slice;
+pub use self::ui::{Ui, UiFrame, UiTheme, UI_FRAME_TEXTURE_KEY};
+pub use self::nine_slice::NineSlice;
\ No newline at end of file
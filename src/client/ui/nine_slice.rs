@@ -0,0 +1,113 @@
+// This file contains nine-slice frames, a texture cut into corners, edges and a middle so it stretches to any size without distorting its border
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NineSlice {
+    pub texture_key: String,
+    /// Size of the texture in pixels
+    pub texture_size: [u32; 2],
+    /// Width of the left, top, right and bottom borders in pixels, they are drawn at this size and never stretched
+    pub border: [f32; 4],
+}
+
+impl NineSlice {
+    pub fn new(texture_key: &str, texture_size: [u32; 2], border: [f32; 4]) -> Self {
+        Self {
+            texture_key: String::from(texture_key),
+            texture_size,
+            border,
+        }
+    }
+
+    /// Cut `rect` (x, y, width, height) into the pieces to draw, each is its rect and the matching texture area
+    /// Borders shrink evenly if the rect is too small to fit them
+    pub fn slices(&self, rect: [f32; 4]) -> Vec<([f32; 4], [f32; 4])> {
+        let [left, top, right, bottom] = self.border;
+
+        let fit = |start: f32, end: f32, size: f32| -> (f32, f32) {
+            if start + end > size && start + end > 0.0 {
+                let scale = size / (start + end);
+                (start * scale, end * scale)
+            } else {
+                (start, end)
+            }
+        };
+        let (draw_left, draw_right) = fit(left, right, rect[2]);
+        let (draw_top, draw_bottom) = fit(top, bottom, rect[3]);
+
+        // Edges of the three columns and rows, on screen and in the texture
+        let xs: [f32; 4] = [rect[0], rect[0] + draw_left, rect[0] + rect[2] - draw_right, rect[0] + rect[2]];
+        let ys: [f32; 4] = [rect[1], rect[1] + draw_top, rect[1] + rect[3] - draw_bottom, rect[1] + rect[3]];
+        let width = self.texture_size[0].max(1) as f32;
+        let height = self.texture_size[1].max(1) as f32;
+        let us: [f32; 4] = [0.0, left / width, 1.0 - right / width, 1.0];
+        let vs: [f32; 4] = [0.0, top / height, 1.0 - bottom / height, 1.0];
+
+        let mut slices: Vec<([f32; 4], [f32; 4])> = Vec::new();
+        for row in 0..3 {
+            for column in 0..3 {
+                let slice_width = xs[column + 1] - xs[column];
+                let slice_height = ys[row + 1] - ys[row];
+                if slice_width <= 0.0 || slice_height <= 0.0 {
+                    continue;
+                }
+
+                slices.push((
+                    [xs[column], ys[row], slice_width, slice_height],
+                    [us[column], vs[row], us[column + 1] - us[column], vs[row + 1] - vs[row]],
+                ));
+            }
+        }
+
+        slices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_corners_edges_and_middle() {
+        let frame = NineSlice::new("frame", [32, 32], [8.0; 4]);
+        let slices = frame.slices([10.0, 20.0, 100.0, 50.0]);
+
+        assert_eq!(slices.len(), 9);
+        // Corners keep their size, edges and the middle stretch
+        assert_eq!(slices[0], ([10.0, 20.0, 8.0, 8.0], [0.0, 0.0, 0.25, 0.25]));
+        assert_eq!(slices[1], ([18.0, 20.0, 84.0, 8.0], [0.25, 0.0, 0.5, 0.25]));
+        assert_eq!(slices[4], ([18.0, 28.0, 84.0, 34.0], [0.25, 0.25, 0.5, 0.5]));
+        assert_eq!(slices[8], ([102.0, 62.0, 8.0, 8.0], [0.75, 0.75, 0.25, 0.25]));
+    }
+
+    #[test]
+    fn uneven_borders() {
+        let frame = NineSlice::new("frame", [40, 20], [4.0, 2.0, 12.0, 6.0]);
+        let slices = frame.slices([0.0, 0.0, 60.0, 30.0]);
+
+        assert_eq!(slices.len(), 9);
+        assert_eq!(slices[0], ([0.0, 0.0, 4.0, 2.0], [0.0, 0.0, 0.1, 0.1]));
+        assert_eq!(slices[4].0, [4.0, 2.0, 44.0, 22.0]);
+        assert_eq!(slices[8].0, [48.0, 24.0, 12.0, 6.0]);
+        assert!((slices[8].1[0] - 0.7).abs() < 0.0001 && (slices[8].1[1] - 0.7).abs() < 0.0001);
+    }
+
+    #[test]
+    fn borders_shrink_to_fit_small_rects() {
+        let frame = NineSlice::new("frame", [32, 32], [8.0; 4]);
+        let slices = frame.slices([0.0, 0.0, 8.0, 40.0]);
+
+        // The borders halve to fit across and the middle column is gone, the texture areas stay the same
+        assert_eq!(slices.len(), 6);
+        assert_eq!(slices[0], ([0.0, 0.0, 4.0, 8.0], [0.0, 0.0, 0.25, 0.25]));
+        assert_eq!(slices[1], ([4.0, 0.0, 4.0, 8.0], [0.75, 0.0, 0.25, 0.25]));
+        assert_eq!(slices[2], ([0.0, 8.0, 4.0, 24.0], [0.0, 0.25, 0.25, 0.5]));
+
+        assert!(frame.slices([0.0, 0.0, 0.0, 40.0]).is_empty());
+    }
+
+    #[test]
+    fn no_border_is_one_stretched_slice() {
+        let frame = NineSlice::new("frame", [16, 16], [0.0; 4]);
+        assert_eq!(frame.slices([5.0, 5.0, 30.0, 10.0]), vec![([5.0, 5.0, 30.0, 10.0], [0.0, 0.0, 1.0, 1.0])]);
+    }
+}
@@ -0,0 +1,381 @@
+// This file contains the immediate mode UI, widgets are declared every frame and drawn through Grafx over the world
+use std::collections::HashMap;
+
+use super::nine_slice::NineSlice;
+use crate::client::grafx::{Grafx, TextAlign, TextStyle};
+
+/// Key of the built in frame texture the default theme's panels and buttons are cut from
+pub const UI_FRAME_TEXTURE_KEY: &str = "__ui_frame";
+
+/// Size of the built in frame texture and the width of its border, in pixels
+const FRAME_TEXTURE_SIZE: u32 = 8;
+const FRAME_BORDER: u32 = 2;
+
+/// Colors, frames and the font widgets are drawn with
+#[derive(Clone, Debug)]
+pub struct UiTheme {
+    /// Font labels are drawn with, labels are skipped while it isn't loaded
+    pub font_key: String,
+    pub text_style: TextStyle,
+    pub panel: NineSlice,
+    pub panel_color: [f32; 4],
+    pub button: NineSlice,
+    pub button_color: [f32; 4],
+    pub button_hover_color: [f32; 4],
+    pub button_pressed_color: [f32; 4],
+    pub tab_selected_color: [f32; 4],
+    pub scrollbar_width: f32,
+    pub scrollbar_color: [f32; 4],
+    pub scrollbar_thumb_color: [f32; 4],
+    /// Pixels a scroll area moves per mouse wheel line
+    pub scroll_speed: f32,
+}
+
+impl UiTheme {
+    /// A plain beveled theme drawn from a built in texture, so it works without any UI art
+    pub fn new(font_key: &str) -> Self {
+        let frame: NineSlice = NineSlice::new(UI_FRAME_TEXTURE_KEY, [FRAME_TEXTURE_SIZE, FRAME_TEXTURE_SIZE], [FRAME_BORDER as f32; 4]);
+
+        Self {
+            font_key: String::from(font_key),
+            text_style: TextStyle { outline: Some(([0.0, 0.0, 0.0, 1.0], 1.0)), ..TextStyle::default() },
+            panel: frame.clone(),
+            panel_color: [0.36, 0.31, 0.24, 0.95],
+            button: frame,
+            button_color: [0.5, 0.44, 0.34, 1.0],
+            button_hover_color: [0.62, 0.55, 0.42, 1.0],
+            button_pressed_color: [0.4, 0.35, 0.27, 1.0],
+            tab_selected_color: [0.7, 0.6, 0.3, 1.0],
+            scrollbar_width: 8.0,
+            scrollbar_color: [0.0, 0.0, 0.0, 0.4],
+            scrollbar_thumb_color: [0.62, 0.55, 0.42, 1.0],
+            scroll_speed: 24.0,
+        }
+    }
+}
+
+/// Mouse input gathered between frames, in logical pixels
+#[derive(Default)]
+struct UiInput {
+    mouse: [f32; 2],
+    down: bool,
+    pressed: bool,
+    released: bool,
+    wheel: f32,
+}
+
+pub struct Ui {
+    theme: UiTheme,
+    input: UiInput,
+    // Widget the mouse went down on, a click only counts if it is released over the same widget
+    active: Option<String>,
+    // Areas covered by UI last frame and this frame, clicks there don't reach the world
+    blocking: Vec<[f32; 4]>,
+    next_blocking: Vec<[f32; 4]>,
+    scroll_offsets: HashMap<String, f32>,
+    // Visible area of the scroll areas being declared, innermost last
+    clip_stack: Vec<[f32; 4]>,
+    // Widgets declared while this is off are drawn but can't be hovered or clicked
+    interactive: bool,
+}
+
+impl Ui {
+    pub fn new(theme: UiTheme) -> Self {
+        Self {
+            theme,
+            input: UiInput::default(),
+            active: None,
+            blocking: Vec::new(),
+            next_blocking: Vec::new(),
+            scroll_offsets: HashMap::new(),
+            clip_stack: Vec::new(),
+            interactive: true,
+        }
+    }
+
+    pub fn theme_mut(&mut self) -> &mut UiTheme {
+        &mut self.theme
+    }
+
+    /// Move the mouse, in logical pixels (see `Grafx::screen_to_logical`)
+    pub fn mouse_moved(&mut self, position: [f32; 2]) {
+        self.input.mouse = position;
+    }
+
+    /// Press or release the left mouse button, returns true if the UI took the click so the world shouldn't see it
+    pub fn mouse_button(&mut self, pressed: bool) -> bool {
+        if pressed {
+            self.input.pressed = true;
+        } else {
+            self.input.released = true;
+        }
+        self.input.down = pressed;
+
+        // A release finishes a click that started on the UI even if the mouse has wandered off it
+        self.is_over_ui(self.input.mouse) || (!pressed && self.active.is_some())
+    }
+
+    /// Scroll the mouse wheel by a number of lines, returns true if the UI took it
+    pub fn mouse_wheel(&mut self, lines: f32) -> bool {
+        self.input.wheel += lines;
+        self.is_over_ui(self.input.mouse)
+    }
+
+    /// True if the UI covered this position on the last frame
+    pub fn is_over_ui(&self, position: [f32; 2]) -> bool {
+        self.blocking.iter().any(|rect| contains(*rect, position))
+    }
+
+    /// True if the UI covered the mouse on the last frame, e.g. to keep right clicks on a panel from reaching the world
+    pub fn is_mouse_over_ui(&self) -> bool {
+        self.is_over_ui(self.input.mouse)
+    }
+
+    /// Let widgets react to the mouse or only draw them, e.g. for menus covered by another menu
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// Start declaring widgets, several groups can be declared in one frame before `end_frame`
+    pub fn begin<'a>(&'a mut self, grafx: &'a mut Grafx) -> UiFrame<'a> {
+        // The built in frame texture is made on first use
+        if grafx.get_texture_size(UI_FRAME_TEXTURE_KEY).is_none() {
+            grafx.insert_texture(UI_FRAME_TEXTURE_KEY, &frame_image());
+        }
+
+        self.clip_stack.clear();
+        UiFrame { ui: self, grafx }
+    }
+
+    /// Finish the frame once every widget is declared, mouse input after this goes to the next frame
+    pub fn end_frame(&mut self) {
+        self.blocking = std::mem::take(&mut self.next_blocking);
+
+        // Clicks end on release, wherever the mouse is
+        if self.input.released {
+            self.active = None;
+        }
+        self.input.pressed = false;
+        self.input.released = false;
+        self.input.wheel = 0.0;
+    }
+}
+
+/// The widgets of one frame, each call draws the widget and returns how it was interacted with
+pub struct UiFrame<'a> {
+    ui: &'a mut Ui,
+    grafx: &'a mut Grafx,
+}
+
+impl UiFrame<'_> {
+    /// A frame to put widgets on, it blocks clicks from reaching the world
+    pub fn panel(&mut self, rect: [f32; 4]) {
+        let panel: NineSlice = self.ui.theme.panel.clone();
+        let color: [f32; 4] = self.ui.theme.panel_color;
+        self.draw_nine_slice(&panel, rect, color);
+        self.block(rect);
+    }
+
+    /// Text with its top left corner at `position`, returns its size
+    pub fn label(&mut self, position: [f32; 2], text: &str) -> [f32; 2] {
+        let style: TextStyle = self.ui.theme.text_style.clone();
+        self.label_styled(position, text, &style)
+    }
+
+    /// Text drawn with its own style, the alignment decides which edge of the text `position` is
+    pub fn label_styled(&mut self, position: [f32; 2], text: &str, style: &TextStyle) -> [f32; 2] {
+        let clip: Option<[f32; 4]> = self.clip();
+        // Without the font the label is left out, the rest of the UI still works
+        self.grafx.draw_ui_text(&self.ui.theme.font_key, text, position, style, clip).unwrap_or([0.0, 0.0])
+    }
+
+    /// A plain colored rect, e.g. to darken what is behind a menu
+    pub fn rect(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        self.draw_rect(rect, color);
+    }
+
+    /// A button with a centered label, returns true on the frame it is clicked
+    pub fn button(&mut self, id: &str, rect: [f32; 4], text: &str) -> bool {
+        let (clicked, hovered, held) = self.interact(id, rect);
+
+        let color: [f32; 4] = if held {
+            self.ui.theme.button_pressed_color
+        } else if hovered {
+            self.ui.theme.button_hover_color
+        } else {
+            self.ui.theme.button_color
+        };
+
+        let button: NineSlice = self.ui.theme.button.clone();
+        self.draw_nine_slice(&button, rect, color);
+        self.centered_label(rect, text);
+        self.block(rect);
+
+        clicked
+    }
+
+    /// A row of tabs filling `rect`, returns true on the frame `selected` changes
+    pub fn tabs(&mut self, id: &str, rect: [f32; 4], labels: &[&str], selected: &mut usize) -> bool {
+        if labels.is_empty() {
+            return false;
+        }
+
+        let tab_width: f32 = rect[2] / labels.len() as f32;
+        let mut changed: bool = false;
+
+        for (index, label) in labels.iter().enumerate() {
+            let tab_rect: [f32; 4] = [rect[0] + tab_width * index as f32, rect[1], tab_width, rect[3]];
+            let (clicked, hovered, held) = self.interact(&format!("{}#{}", id, index), tab_rect);
+
+            if clicked && *selected != index {
+                *selected = index;
+                changed = true;
+            }
+
+            let color: [f32; 4] = if *selected == index {
+                self.ui.theme.tab_selected_color
+            } else if held {
+                self.ui.theme.button_pressed_color
+            } else if hovered {
+                self.ui.theme.button_hover_color
+            } else {
+                self.ui.theme.button_color
+            };
+
+            let button: NineSlice = self.ui.theme.button.clone();
+            self.draw_nine_slice(&button, tab_rect, color);
+            self.centered_label(tab_rect, label);
+        }
+
+        self.block(rect);
+        changed
+    }
+
+    /// Start a scrolling area `content_height` pixels tall seen through `rect`, the mouse wheel scrolls it while hovered
+    /// Returns the top left of the content, widgets placed from there are scrolled and cut off at the edges until `end_scroll`
+    pub fn begin_scroll(&mut self, id: &str, rect: [f32; 4], content_height: f32) -> [f32; 2] {
+        let max_offset: f32 = (content_height - rect[3]).max(0.0);
+        let mut offset: f32 = self.ui.scroll_offsets.get(id).copied().unwrap_or(0.0);
+
+        if self.hovered(rect) && self.ui.input.wheel != 0.0 {
+            offset -= self.ui.input.wheel * self.ui.theme.scroll_speed;
+            // Only the innermost area under the mouse scrolls
+            self.ui.input.wheel = 0.0;
+        }
+        let offset: f32 = offset.clamp(0.0, max_offset);
+        self.ui.scroll_offsets.insert(String::from(id), offset);
+
+        // Content is narrower when it needs a scrollbar
+        let mut content_rect: [f32; 4] = rect;
+        if max_offset > 0.0 {
+            let width: f32 = self.ui.theme.scrollbar_width;
+            let track: [f32; 4] = [rect[0] + rect[2] - width, rect[1], width, rect[3]];
+            let thumb_height: f32 = (rect[3] * rect[3] / content_height).max(width);
+            let thumb: [f32; 4] = [track[0], track[1] + (rect[3] - thumb_height) * offset / max_offset, width, thumb_height];
+
+            let (track_color, thumb_color) = (self.ui.theme.scrollbar_color, self.ui.theme.scrollbar_thumb_color);
+            self.draw_rect(track, track_color);
+            self.draw_rect(thumb, thumb_color);
+            content_rect[2] -= width;
+        }
+
+        let clip: [f32; 4] = match self.clip() {
+            Some(outer) => intersect(outer, content_rect),
+            None => content_rect
+        };
+        self.ui.clip_stack.push(clip);
+        self.block(rect);
+
+        [rect[0], rect[1] - offset]
+    }
+
+    pub fn end_scroll(&mut self) {
+        self.ui.clip_stack.pop();
+    }
+
+    /// True if the mouse is over `rect` and not cut off by a scroll area
+    pub fn hovered(&self, rect: [f32; 4]) -> bool {
+        if !self.ui.interactive {
+            return false;
+        }
+
+        let visible: [f32; 4] = match self.clip() {
+            Some(clip) => intersect(clip, rect),
+            None => rect
+        };
+        contains(visible, self.ui.input.mouse)
+    }
+
+    // Track presses on a widget, returns whether it was clicked this frame, is hovered and is held down
+    fn interact(&mut self, id: &str, rect: [f32; 4]) -> (bool, bool, bool) {
+        let hovered: bool = self.hovered(rect);
+
+        if hovered && self.ui.input.pressed {
+            self.ui.active = Some(String::from(id));
+        }
+
+        let active: bool = self.ui.active.as_deref() == Some(id);
+        let clicked: bool = active && hovered && self.ui.input.released;
+        let held: bool = active && hovered && self.ui.input.down;
+
+        (clicked, hovered, held)
+    }
+
+    fn centered_label(&mut self, rect: [f32; 4], text: &str) {
+        let style: TextStyle = TextStyle { align: TextAlign::Center, ..self.ui.theme.text_style.clone() };
+        let height: f32 = self.grafx.measure_text(&self.ui.theme.font_key, text, &style).map(|size| size[1]).unwrap_or(0.0);
+        self.label_styled([rect[0] + rect[2] / 2.0, rect[1] + (rect[3] - height) / 2.0], text, &style);
+    }
+
+    fn draw_nine_slice(&mut self, nine_slice: &NineSlice, rect: [f32; 4], color: [f32; 4]) {
+        let clip: Option<[f32; 4]> = self.clip();
+        for (slice_rect, uv_rect) in nine_slice.slices(rect) {
+            self.grafx.draw_ui_rect(&nine_slice.texture_key, slice_rect, uv_rect, color, clip);
+        }
+    }
+
+    fn draw_rect(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        let clip: Option<[f32; 4]> = self.clip();
+        self.grafx.draw_ui_rect(crate::client::grafx::WHITE_TEXTURE_KEY, rect, [0.0, 0.0, 1.0, 1.0], color, clip);
+    }
+
+    // Stop clicks on this area reaching the world, only the visible part of widgets in a scroll area counts
+    fn block(&mut self, rect: [f32; 4]) {
+        let visible: [f32; 4] = match self.clip() {
+            Some(clip) => intersect(clip, rect),
+            None => rect
+        };
+        self.ui.next_blocking.push(visible);
+    }
+
+    fn clip(&self) -> Option<[f32; 4]> {
+        self.ui.clip_stack.last().copied()
+    }
+}
+
+
+fn contains(rect: [f32; 4], point: [f32; 2]) -> bool {
+    point[0] >= rect[0] && point[0] < rect[0] + rect[2] && point[1] >= rect[1] && point[1] < rect[1] + rect[3]
+}
+
+// The overlap of two rects, zero sized if they don't overlap
+fn intersect(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    let x: f32 = a[0].max(b[0]);
+    let y: f32 = a[1].max(b[1]);
+    let width: f32 = ((a[0] + a[2]).min(b[0] + b[2]) - x).max(0.0);
+    let height: f32 = ((a[1] + a[3]).min(b[1] + b[3]) - y).max(0.0);
+    [x, y, width, height]
+}
+
+// A white square with a dark outline and a light bevel, tinted per widget
+fn frame_image() -> image::RgbaImage {
+    image::RgbaImage::from_fn(FRAME_TEXTURE_SIZE, FRAME_TEXTURE_SIZE, |x, y| {
+        let ring: u32 = x.min(y).min(FRAME_TEXTURE_SIZE - 1 - x).min(FRAME_TEXTURE_SIZE - 1 - y);
+        match ring {
+            0 => image::Rgba([24, 20, 16, 255]),
+            ring if ring < FRAME_BORDER && (x < FRAME_BORDER || y < FRAME_BORDER) => image::Rgba([255, 255, 255, 255]),
+            ring if ring < FRAME_BORDER => image::Rgba([150, 150, 150, 255]),
+            _ => image::Rgba([215, 215, 215, 255]),
+        }
+    })
+}
@@ -1,8 +1,6 @@
-mod client;
-
 use winit::event_loop::EventLoop;
 
-use client::game::Game;
+use poprustica::client::game::Game;
 
 
 fn main() {